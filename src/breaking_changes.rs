@@ -4,6 +4,7 @@
 //!     1. The Topgrade being executed is a new major release
 //!     2. This is the first launch of that major release
 
+use crate::config::Config;
 use crate::terminal::print_separator;
 #[cfg(windows)]
 use crate::WINDOWS_DIRS;
@@ -71,7 +72,7 @@ impl Version {
 pub(crate) static BREAKINGCHANGES: &str = include_str!("../BREAKINGCHANGES.md");
 
 /// Return platform's data directory.
-fn data_dir() -> PathBuf {
+pub(crate) fn data_dir() -> PathBuf {
     #[cfg(unix)]
     return XDG_DIRS.data_dir();
 
@@ -92,7 +93,12 @@ fn keep_file_path() -> PathBuf {
 
 /// If environment variable `TOPGRADE_SKIP_BRKC_NOTIFY` is set to `true`, then
 /// we won't notify the user of the breaking changes.
-pub(crate) fn should_skip() -> bool {
+pub(crate) fn should_skip(config: &Config) -> bool {
+    // Nobody can confirm in a run started by another Topgrade or in a CI pipeline
+    if config.remote_guest() || config.ci() {
+        return true;
+    }
+
     if let Ok(var) = var("TOPGRADE_SKIP_BRKC_NOTIFY") {
         return var.as_str() == "true";
     }
@@ -3,6 +3,7 @@
 use std::fmt::Display;
 use std::process::Child;
 use std::process::{Command, ExitStatus, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use color_eyre::eyre;
 use color_eyre::eyre::eyre;
@@ -12,6 +13,27 @@ use crate::error::TopgradeError;
 
 use tracing::debug;
 
+/// Set by `--explain`, no command is run at all then.
+static EXPLAINING: AtomicBool = AtomicBool::new(false);
+
+/// Print the commands run through an `Executor` instead of running them, even the ones that run for
+/// real during a dry run.
+///
+/// `--explain` uses this so that explaining a step changes nothing, not even the package indexes.
+/// The queries run with `output_checked` still run, so the step gets as far as a dry run would.
+pub fn set_explaining() {
+    EXPLAINING.store(true, Ordering::Relaxed);
+}
+
+/// Print `cmd` instead of running it when explaining, returning whether it was.
+pub fn explained(cmd: &Command) -> bool {
+    let explaining = EXPLAINING.load(Ordering::Relaxed);
+    if explaining {
+        println!("Dry running: {}", format_program_and_args(cmd));
+    }
+    explaining
+}
+
 /// Like [`Output`], but UTF-8 decoded.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Utf8Output {
@@ -1,28 +1,30 @@
 #![allow(dead_code)]
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{write, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use std::{env, fs};
 
 use clap::{Parser, ValueEnum};
 use clap_complete::Shell;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, eyre};
 use etcetera::base_strategy::BaseStrategy;
 use merge::Merge;
 use regex::Regex;
 use regex_split::RegexSplit;
 use serde::Deserialize;
-use strum::{EnumIter, EnumString, IntoEnumIterator, VariantNames};
+use strum::{AsRefStr, EnumIter, EnumString, IntoEnumIterator, VariantNames};
 use which_crate::which;
 
 use super::utils::editor;
 use crate::command::CommandExt;
 use crate::sudo::SudoKind;
-use crate::utils::string_prepend_str;
+use crate::utils::{parse_duration, string_prepend_str};
 use tracing::{debug, error};
 
 pub static EXAMPLE_CONFIG: &str = include_str!("../config.example.toml");
@@ -44,41 +46,60 @@ macro_rules! str_value {
 
 pub type Commands = BTreeMap<String, String>;
 
-#[derive(ValueEnum, EnumString, VariantNames, Debug, Clone, PartialEq, Eq, Deserialize, EnumIter, Copy)]
+#[derive(ValueEnum, EnumString, VariantNames, Debug, Clone, PartialEq, Eq, Hash, Deserialize, EnumIter, Copy)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum Step {
     AM,
+    AnsibleGalaxy,
     AppMan,
     Asdf,
     Atom,
     Audit,
+    Aur,
     AutoCpufreq,
+    Bat,
     Bin,
     Bob,
+    Bootloader,
     BrewCask,
     BrewFormula,
+    Buildx,
     Bun,
     BunPackages,
+    Cabal,
+    CacheRefresh,
     Cargo,
+    CargoCompanions,
+    CargoProjects,
+    CargoSubcommands,
+    CargoTools,
     Certbot,
     Chezmoi,
     Chocolatey,
     Choosenim,
     ClamAvDb,
+    CloudAuth,
+    Completions,
+    Compose,
     Composer,
+    Conan,
     Conda,
     ConfigUpdate,
     Containers,
     CustomCommands,
     DebGet,
+    DebSources,
     Deno,
+    Direnv,
     Distrobox,
     DkpPacman,
     Dotnet,
+    Drivers,
     Elan,
     Emacs,
+    Etckeeper,
     Firmware,
     Flatpak,
     Flutter,
@@ -86,6 +107,7 @@ pub enum Step {
     Gcloud,
     Gem,
     Ghcup,
+    GitBuilds,
     GithubCliExtensions,
     GitRepos,
     GnomeShellExtensions,
@@ -93,16 +115,22 @@ pub enum Step {
     Guix,
     Haxelib,
     Helm,
+    InstallerScripts,
     HomeManager,
     Jetpack,
     Julia,
     Juliaup,
+    Just,
     Kakoune,
     Helix,
     Krew,
+    KubectlManifests,
+    Locatedb,
     Lure,
     Macports,
     Mamba,
+    Mandb,
+    Micromamba,
     Miktex,
     Mas,
     Maza,
@@ -110,27 +138,36 @@ pub enum Step {
     Mise,
     Myrepos,
     Nix,
+    NixDarwin,
     Node,
+    NodeVersionManager,
+    Ollama,
     Opam,
     Pacdef,
     Pacstall,
+    PasswordManagers,
     Pearl,
     Pip3,
     PipReview,
     PipReviewLocal,
     Pipupgrade,
     Pipx,
+    Pixi,
     Pkg,
     Pkgin,
+    Pkgx,
+    PluginUpdates,
     PlatformioCore,
     Pnpm,
     Powershell,
     Protonup,
     Pyenv,
+    PythonVenvs,
     Raco,
     Rcm,
     Remotes,
     Restarts,
+    Restic,
     Rtcl,
     RubyGems,
     Rustup,
@@ -138,21 +175,28 @@ pub enum Step {
     Scoop,
     Sdkman,
     SelfUpdate,
+    SelfUpdatingTools,
     Sheldon,
     Shell,
     Snap,
+    Spack,
     Sparkle,
     Spicetify,
     Stack,
     Stew,
+    Stow,
     System,
+    TerraformProjects,
+    Tfenv,
     Tldr,
     Tlmgr,
     Tmux,
     Toolbx,
+    UbuntuReleaseUpgrade,
     Vagrant,
     Vcpkg,
     Vim,
+    VisualStudio,
     Vscode,
     Waydroid,
     Winget,
@@ -161,6 +205,47 @@ pub enum Step {
     Xcodes,
     Yadm,
     Yarn,
+    Zellij,
+}
+
+impl Step {
+    /// The tags a step carries out of the box, used to select steps with `--tag`.
+    ///
+    /// More tags can be added with `step_tags` in the configuration file.
+    pub fn default_tags(self) -> &'static [&'static str] {
+        use Step::*;
+
+        match self {
+            AM | AppMan | Audit | Aur | AutoCpufreq | Bootloader | BrewCask | BrewFormula | Chocolatey | ClamAvDb
+            | ConfigUpdate | DebGet | DebSources | DkpPacman | Drivers | Etckeeper | Firmware | Flatpak
+            | GnomeShellExtensions | Guix | HomeManager | Just | Locatedb | Lure | Macports | Mandb | Mas | Nix
+            | NixDarwin | Pacdef | Pacstall | Pkg | Pkgin | Restarts | Scoop | Snap | Sparkle | System | Winget
+            | Wsl | WslUpdate | Xcodes => &["system"],
+            Buildx | Compose | Containers | Distrobox | Toolbx | Vagrant | Waydroid => &["containers"],
+            Helm | Krew | KubectlManifests => &["containers", "dev"],
+            AnsibleGalaxy | Asdf | Bin | Bun | BunPackages | Cabal | Cargo | CargoCompanions | CargoProjects
+            | CargoSubcommands | CargoTools | Choosenim | Composer | Conan | Conda | Deno | Direnv | Dotnet | Elan
+            | Flutter | Fossil | Gcloud | Gem | Ghcup | GitBuilds | GithubCliExtensions | Go | Haxelib | Jetpack
+            | Julia | Juliaup | Mamba | Micromamba | Miktex | Mise | Node | NodeVersionManager | Ollama | Opam
+            | Pip3 | PipReview | PipReviewLocal | Pipupgrade | Pipx | Pixi | Pkgx | PlatformioCore | Pnpm
+            | Powershell | Pyenv | PythonVenvs | Raco | Rtcl | RubyGems | Rustup | Rye | Sdkman | Spack | Stack
+            | Stew | TerraformProjects | Tfenv | Tlmgr | Vcpkg | VisualStudio | Yarn => &["dev"],
+            Atom | Bat | Bob | Emacs | Helix | Kakoune | Micro | Vim | Vscode => &["editors"],
+            Chezmoi | Completions | Myrepos | Pearl | Rcm | Sheldon | Shell | Stow | Tmux | Yadm | Zellij => &["shell"],
+            CacheRefresh | Certbot | CloudAuth | CustomCommands | GitRepos | InstallerScripts | Maza
+            | PasswordManagers | PluginUpdates | Protonup | Remotes | Restic | SelfUpdate | SelfUpdatingTools
+            | Spicetify | Tldr | UbuntuReleaseUpgrade => &[],
+        }
+    }
+
+    /// Whether the step can only refresh its metadata and list what is outdated, used by
+    /// `--metadata-only`. The other steps are skipped in that mode.
+    pub fn supports_metadata_only(self) -> bool {
+        matches!(
+            self,
+            Step::BrewCask | Step::BrewFormula | Step::Cargo | Step::Flatpak | Step::System
+        )
+    }
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -175,6 +260,13 @@ pub struct Include {
 pub struct Containers {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     ignored_containers: Option<Vec<String>>,
+
+    buildx_prune: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    compose_projects: Option<Vec<String>>,
+
+    compose_prune: Option<bool>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -189,6 +281,7 @@ pub struct Git {
     repos: Option<Vec<String>>,
 
     pull_predefined: Option<bool>,
+    update_submodules: Option<bool>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -209,6 +302,11 @@ pub struct Windows {
     open_remotes_in_new_terminal: Option<bool>,
     wsl_update_pre_release: Option<bool>,
     wsl_update_use_web_download: Option<bool>,
+    visual_studio: Option<bool>,
+    driver_updates: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    winget_ignore: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -218,6 +316,11 @@ pub struct Python {
     enable_pip_review_local: Option<bool>,
     enable_pipupgrade: Option<bool>,
     pipupgrade_arguments: Option<String>,
+    pip_only_explicit: Option<bool>,
+    pip_constraints: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    venv_roots: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -242,6 +345,9 @@ pub struct Yarn {
 #[allow(clippy::upper_case_acronyms)]
 pub struct NPM {
     use_sudo: Option<bool>,
+    version_manager: Option<NodeVersionManager>,
+    node_channel: Option<NodeChannel>,
+    migrate_packages: Option<bool>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -256,6 +362,13 @@ pub struct Firmware {
 #[allow(clippy::upper_case_acronyms)]
 pub struct Flatpak {
     use_sudo: Option<bool>,
+    remove_unused: Option<bool>,
+    repair: Option<bool>,
+    repair_interval: Option<u32>,
+    report_permission_changes: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::commands_merge_opt)]
+    remotes: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -263,8 +376,33 @@ pub struct Flatpak {
 pub struct Brew {
     greedy_cask: Option<bool>,
     greedy_latest: Option<bool>,
+    greedy_auto_updates: Option<bool>,
+    cask_no_quarantine: Option<bool>,
     autoremove: Option<bool>,
     fetch_head: Option<bool>,
+    doctor: Option<bool>,
+    parallel_downloads: Option<u32>,
+    bottle_mirror: Option<String>,
+    api_domain: Option<String>,
+    restart_services: Option<bool>,
+    check_missing: Option<bool>,
+    bundle_cleanup: Option<bool>,
+    report_migrations: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    reinstall_casks: Option<Vec<String>>,
+}
+
+/// How Topgrade updates itself.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfUpdateStrategy {
+    /// Leave the update to the package manager that installed Topgrade, if any
+    Auto,
+    /// Always use the built-in self-update
+    Builtin,
+    /// Never use the built-in self-update, a package manager updates Topgrade
+    PackageManager,
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -281,6 +419,176 @@ pub enum ArchPackageManager {
     Yay,
 }
 
+/// How much of the output of a step is shown.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepVerbosity {
+    /// Show the output of the commands as they run
+    Normal,
+    /// Only show the output of the commands if the step fails
+    Quiet,
+}
+
+/// The tool used to install Node itself.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeVersionManager {
+    /// Use the first one found among volta, fnm, nvm, nodenv and n
+    Autodetect,
+    Fnm,
+    Nvm,
+    Nodenv,
+    N,
+    Volta,
+}
+
+/// The Node release line to follow.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeChannel {
+    Lts,
+    Latest,
+}
+
+/// The Neovim channel bob keeps installed and in use.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BobChannel {
+    Stable,
+    Nightly,
+}
+
+/// The package manager used by the Emacs configuration.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmacsPackageManager {
+    /// Upgrade packages with `paradox-upgrade-packages`, if Paradox is installed
+    Paradox,
+    /// The built-in package.el, requires Emacs 29
+    Package,
+    /// straight.el
+    Straight,
+}
+
+/// Which Terraform implementation to keep up to date.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TerraformFlavor {
+    /// Managed with `tfenv`
+    Terraform,
+    /// Managed with `tofuenv`
+    #[serde(rename = "opentofu")]
+    OpenTofu,
+}
+
+/// The format of the summary, as printed at the end of the run or emailed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// The summary as shown in the terminal, without colors
+    Text,
+    /// The run complete payload of the webhook, with the status of each step
+    Json,
+}
+
+/// Where and how to email the summary of the run
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    /// Whether to email the summary after the run
+    #[serde(default)]
+    pub report: bool,
+    pub format: Option<ReportFormat>,
+    pub smtp_host: String,
+    pub smtp_port: Option<u16>,
+    pub smtp_from: String,
+    pub smtp_to: Vec<String>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+/// How often a step runs, see `step_frequency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepFrequency {
+    /// At most once per this duration
+    Interval(Duration),
+    /// Once every this many runs
+    Runs(u32),
+}
+
+/// Parse a step frequency: `daily`, `weekly`, `monthly`, `every 3 runs` or a duration such as `12h`.
+fn parse_step_frequency(s: &str) -> Result<StepFrequency, String> {
+    const DAY: u64 = 24 * 60 * 60;
+
+    let s = s.trim();
+    match s {
+        "daily" => return Ok(StepFrequency::Interval(Duration::from_secs(DAY))),
+        "weekly" => return Ok(StepFrequency::Interval(Duration::from_secs(7 * DAY))),
+        "monthly" => return Ok(StepFrequency::Interval(Duration::from_secs(30 * DAY))),
+        _ => (),
+    }
+
+    if let Some(runs) = s.strip_prefix("every ").and_then(|s| s.strip_suffix(" runs")) {
+        return match runs.trim().parse() {
+            Ok(0) | Err(_) => Err(format!("invalid frequency `{s}`: expected a positive number of runs")),
+            Ok(runs) => Ok(StepFrequency::Runs(runs)),
+        };
+    }
+
+    parse_duration(s).map(StepFrequency::Interval)
+}
+
+/// The colors used for the separators, the messages and the summary.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorTheme {
+    /// Pick `dark` or `light` from the background of the terminal, `dark` when it is unknown
+    Auto,
+    /// The colors readable on a dark background
+    Dark,
+    /// The colors readable on a light background
+    Light,
+    /// No colors at all
+    None,
+}
+
+/// A color of the `[colors]` section.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminalColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+/// The colors overriding the ones of the `color_theme`.
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Colors {
+    pub success: Option<TerminalColor>,
+    pub failure: Option<TerminalColor>,
+    pub skip: Option<TerminalColor>,
+    pub separator: Option<TerminalColor>,
+}
+
+/// Where the summary printed at the end of a run goes.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryOutput {
+    /// Print the summary to the terminal
+    Terminal,
+    /// Pipe the summary through `$PAGER`
+    Pager,
+    /// Write the summary to `summary_file`
+    File,
+}
+
 #[derive(Deserialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Linux {
@@ -294,6 +602,14 @@ pub struct Linux {
     aura_pacman_arguments: Option<String>,
     arch_package_manager: Option<ArchPackageManager>,
     show_arch_news: Option<bool>,
+    arch_update_keyring_first: Option<bool>,
+    arch_refresh_keys: Option<bool>,
+    pacman_check: Option<bool>,
+    pacman_merge_tool: Option<String>,
+    aur_only: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    aur_ignore: Option<Vec<String>>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     garuda_update_arguments: Option<String>,
@@ -319,10 +635,30 @@ pub struct Linux {
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     apt_arguments: Option<String>,
 
+    apt_include_phased: Option<bool>,
+    apt_sources_override: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::commands_merge_opt)]
+    deb_sources: Option<BTreeMap<String, String>>,
+
+    dpkg_configure_pending: Option<bool>,
+    apt_wait_for_unattended: Option<bool>,
+    apt_unattended_timeout: Option<u64>,
+    nix_rollback_on_failure: Option<bool>,
+    ubuntu_release_upgrade: Option<bool>,
     enable_tlmgr: Option<bool>,
     redhat_distro_sync: Option<bool>,
     suse_dup: Option<bool>,
     rpm_ostree: Option<bool>,
+    needrestart: Option<bool>,
+    update_bootloader: Option<bool>,
+    snap_remove_old_revisions: Option<bool>,
+    snap_report_disconnected: Option<bool>,
+    snap_reconnect: Option<bool>,
+    report_pending_security: Option<bool>,
+    report_restart_needed: Option<bool>,
+    auto_restart_services: Option<bool>,
+    etckeeper: Option<bool>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     emerge_sync_flags: Option<String>,
@@ -334,12 +670,128 @@ pub struct Linux {
     home_manager_arguments: Option<Vec<String>>,
 }
 
+/// A plugin system updated with user provided commands
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PluginUpdate {
+    pub name: String,
+    /// Run first, the update is skipped unless it exits successfully
+    pub detect: String,
+    pub update: String,
+}
+
+/// A tool built from a git repository, rebuilt when pulling it changes its HEAD
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GitBuild {
+    pub path: String,
+    /// Run in the repository with the shell
+    pub build: String,
+}
+
+/// An editor configuration repository, pulled right before the plugins of its editor are updated
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EditorConfigRepo {
+    pub path: String,
+    /// The step updating the plugins of the editor, such as `vim` or `emacs`
+    pub step: Step,
+}
+
+/// A repository of Kubernetes manifests, pulled and applied to its cluster
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct KubectlManifests {
+    pub path: String,
+    /// The kubectl context the manifests are applied to, never the current one
+    pub context: String,
+}
+
+/// A tool installed outside of any package manager which can update itself
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SelfUpdatingTool {
+    pub binary: String,
+    pub update_args: Vec<String>,
+}
+
+/// A tool updated by running the installer or update script it ships in its install directory
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InstallerScriptTool {
+    pub tool: String,
+    pub script: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Steps skipped while the system is busy
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SkipIfBusy {
+    /// The 1-minute load average above which the system is busy
+    pub load: f64,
+    pub steps: Vec<Step>,
+}
+
+/// A cargo subcommand updated with its own update invocation
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CargoSubcommandUpdate {
+    /// The subcommand, `nextest` for `cargo nextest`
+    pub subcommand: String,
+    pub update_args: Vec<String>,
+}
+
+/// A completion script or man page printed by a tool, regenerated after the upgrades
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Completion {
+    pub binary: String,
+    pub args: Vec<String>,
+    /// Where the output is written, relative to `completions_dir`
+    pub file: String,
+}
+
+/// A cloud CLI whose authentication is refreshed before the upgrades by running `<binary> <command>`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CloudAuth {
+    pub binary: String,
+    pub command: Vec<String>,
+}
+
+/// A cache rebuilt after the upgrades by running `<binary> <command>`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CacheRefresh {
+    pub binary: String,
+    pub command: Vec<String>,
+}
+
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    cpu_quota: Option<String>,
+    io_weight: Option<u16>,
+    memory_max: Option<String>,
+}
+
 #[derive(Deserialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Composer {
     self_update: Option<bool>,
 }
 
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Terraform {
+    flavor: Option<TerraformFlavor>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    projects: Option<Vec<String>>,
+}
+
 #[derive(Deserialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Vim {
@@ -374,6 +826,14 @@ pub struct Misc {
 
     display_time: Option<bool>,
 
+    separator_char: Option<char>,
+
+    color_theme: Option<ColorTheme>,
+
+    separator_width: Option<u16>,
+
+    separator_full_width: Option<bool>,
+
     assume_yes: Option<bool>,
 
     no_retry: Option<bool>,
@@ -388,12 +848,176 @@ pub struct Misc {
 
     bashit_branch: Option<String>,
 
+    flutter_channel: Option<String>,
+
+    chezmoi_apply: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    go_ignore: Option<Vec<String>>,
+
+    opam_all_switches: Option<bool>,
+
+    home_manager_flake: Option<String>,
+
+    nix_darwin_flake: Option<String>,
+
+    emacs_package_manager: Option<EmacsPackageManager>,
+
+    emacs_timeout: Option<u64>,
+
+    macports_reclaim: Option<bool>,
+
+    confirm_destructive: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    password_managers: Option<Vec<String>>,
+
+    cargo_update_git: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    cargo_projects: Option<Vec<String>>,
+
+    cargo_projects_upgrade: Option<bool>,
+
+    rustup_self_update: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    skip_on_battery: Option<Vec<Step>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    needs_tty: Option<Vec<Step>>,
+
+    skip_if_busy: Option<SkipIfBusy>,
+
+    step_verbosity: Option<StepVerbosity>,
+
+    step_verbosity_overrides: Option<HashMap<Step, StepVerbosity>>,
+
+    step_frequency: Option<HashMap<Step, String>>,
+
+    schedule_interval: Option<String>,
+
+    webhook_url: Option<String>,
+
+    per_step_logs_dir: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    ollama_models: Option<Vec<String>>,
+
+    rye_self_update: Option<bool>,
+
+    network_jitter: Option<u64>,
+
+    bob_channel: Option<BobChannel>,
+
+    gem_user_install: Option<bool>,
+
+    regenerate_completions: Option<bool>,
+
+    completions_dir: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    dotnet_ignore: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    rye_projects: Option<Vec<String>>,
+
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     only: Option<Vec<Step>>,
 
+    step_tags: Option<HashMap<Step, Vec<String>>>,
+
     no_self_update: Option<bool>,
 
+    self_update_strategy: Option<SelfUpdateStrategy>,
+
     log_filters: Option<Vec<String>>,
+
+    summary_output: Option<SummaryOutput>,
+
+    summary_file: Option<String>,
+
+    summary_collapse_success: Option<bool>,
+
+    summary_format: Option<ReportFormat>,
+
+    fail_fast: Option<bool>,
+
+    omz: Option<bool>,
+
+    guix_pull: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    micromamba_ignore: Option<Vec<String>>,
+
+    rebuild_mandb: Option<bool>,
+
+    rebuild_locatedb: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    zellij_plugins: Option<Vec<String>>,
+
+    pixi_self_update: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    pixi_projects: Option<Vec<String>>,
+
+    pyenv_install_latest: Option<bool>,
+
+    restic_self_update: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    self_updating_tools_ignore: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    installer_script_tools_ignore: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    vcpkg_projects: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    conan_projects: Option<Vec<String>>,
+
+    freebsd_update: Option<bool>,
+
+    openbsd_fw_update: Option<bool>,
+
+    openbsd_syspatch: Option<bool>,
+
+    nix_env_upgrade: Option<bool>,
+
+    nix_check_substituters: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    direnv_dirs: Option<Vec<String>>,
+
+    reshim_after_update: Option<bool>,
+
+    refresh_cloud_auth: Option<bool>,
+
+    suggest_rehash: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    spack_environments: Option<Vec<String>>,
+
+    bun_self_update: Option<bool>,
+
+    toolbx_command: Option<String>,
+
+    just_update_recipe: Option<String>,
+
+    cargo_companion_prefix: Option<String>,
+
+    cargo_tools_manifest: Option<String>,
+
+    bandwidth_limit: Option<u32>,
+
+    stow_dir: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    stow_packages: Option<Vec<String>>,
+
+    ansible_galaxy_requirements: Option<String>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -415,6 +1039,39 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::commands_merge_opt)]
     commands: Option<Commands>,
 
+    #[merge(strategy = crate::utils::merge_strategies::commands_merge_opt)]
+    command_shells: Option<BTreeMap<String, Vec<String>>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    plugin_updates: Option<Vec<PluginUpdate>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    git_builds: Option<Vec<GitBuild>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    editor_config_repos: Option<Vec<EditorConfigRepo>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    kubectl_manifests: Option<Vec<KubectlManifests>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    self_updating_tools: Option<Vec<SelfUpdatingTool>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    installer_script_tools: Option<Vec<InstallerScriptTool>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    completions: Option<Vec<Completion>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    cache_refresh: Option<Vec<CacheRefresh>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    cloud_auth: Option<Vec<CloudAuth>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    cargo_subcommand_updates: Option<Vec<CargoSubcommandUpdate>>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     python: Option<Python>,
 
@@ -445,6 +1102,9 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     vim: Option<Vim>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    terraform: Option<Terraform>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     firmware: Option<Firmware>,
 
@@ -456,6 +1116,14 @@ pub struct ConfigFile {
 
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     distrobox: Option<Distrobox>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    resource_limits: Option<ResourceLimits>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    colors: Option<Colors>,
+
+    email: Option<Email>,
 }
 
 fn config_directory() -> PathBuf {
@@ -539,6 +1207,24 @@ impl ConfigFile {
         Ok(res)
     }
 
+    /// Read and merge the configuration files given on the command line.
+    ///
+    /// Files are merged from left to right, so the last one has the highest priority.
+    /// Without any file, the default configuration file is read.
+    fn read_layered(config_paths: &[PathBuf]) -> Result<ConfigFile> {
+        if config_paths.is_empty() {
+            return Self::read(None);
+        }
+
+        let mut result = Self::default();
+        // When merging, values that are already set take precedence, so start from the last file
+        for path in config_paths.iter().rev() {
+            result.merge(Self::read(Some(path.clone()))?);
+        }
+
+        Ok(result)
+    }
+
     /// Read the configuration file.
     ///
     /// If the configuration file does not exist, the function returns the default ConfigFile.
@@ -555,13 +1241,11 @@ impl ConfigFile {
             to read the include directory before returning the main config path
             */
             for include in dir_include {
-                let include_contents = fs::read_to_string(&include).map_err(|e| {
+                let include_contents = fs::read_to_string(&include).inspect_err(|_| {
                     error!("Unable to read {}", include.display());
-                    e
                 })?;
-                let include_contents_parsed = toml::from_str(include_contents.as_str()).map_err(|e| {
+                let include_contents_parsed = toml::from_str(include_contents.as_str()).inspect_err(|_| {
                     error!("Failed to deserialize {}", include.display());
-                    e
                 })?;
 
                 result.merge(include_contents_parsed);
@@ -576,9 +1260,8 @@ impl ConfigFile {
             return Ok(result);
         }
 
-        let mut contents_non_split = fs::read_to_string(&config_path).map_err(|e| {
+        let mut contents_non_split = fs::read_to_string(&config_path).inspect_err(|_| {
             error!("Unable to read {}", config_path.display());
-            e
         })?;
 
         Self::ensure_misc_is_present(&mut contents_non_split, &config_path);
@@ -589,9 +1272,8 @@ impl ConfigFile {
         let contents_split = regex_match_include.split_inclusive_left(contents_non_split.as_str());
 
         for contents in contents_split {
-            let config_file_include_only: ConfigFileIncludeOnly = toml::from_str(contents).map_err(|e| {
+            let config_file_include_only: ConfigFileIncludeOnly = toml::from_str(contents).inspect_err(|_| {
                 error!("Failed to deserialize an include section of {}", config_path.display());
-                e
             })?;
 
             if let Some(includes) = &config_file_include_only.include {
@@ -666,8 +1348,12 @@ impl ConfigFile {
 
 // Command line arguments
 #[derive(Parser, Debug)]
-#[clap(name = "Topgrade", version)]
+#[clap(name = "Topgrade", version, disable_version_flag = true)]
 pub struct CommandLineArgs {
+    /// Print version, with `--verbose` also the steps and features of this build
+    #[clap(short = 'V', long = "version")]
+    pub version: bool,
+
     /// Edit the configuration file
     #[clap(long = "edit-config")]
     edit_config: bool,
@@ -676,6 +1362,18 @@ pub struct CommandLineArgs {
     #[clap(long = "config-reference")]
     show_config_reference: bool,
 
+    /// Check the environment and the configuration without upgrading anything
+    #[clap(long = "doctor")]
+    doctor: bool,
+
+    /// Run Topgrade periodically with the scheduler of the platform, every `schedule_interval`
+    #[clap(long = "schedule", conflicts_with = "unschedule")]
+    schedule: bool,
+
+    /// Remove the periodic run installed with `--schedule`
+    #[clap(long = "unschedule")]
+    unschedule: bool,
+
     /// Run inside tmux
     #[clap(short = 't', long = "tmux")]
     run_in_tmux: bool,
@@ -688,10 +1386,36 @@ pub struct CommandLineArgs {
     #[clap(short = 'n', long = "dry-run")]
     dry_run: bool,
 
+    /// Only refresh the package indexes and list what is outdated, never install anything
+    #[clap(long = "metadata-only")]
+    metadata_only: bool,
+
+    /// List every step in the summary, even with `summary_collapse_success`
+    #[clap(long = "verbose-summary")]
+    verbose_summary: bool,
+
+    /// Print the commands the given step would run, where and with which environment, then exit
+    #[clap(long = "explain", value_name = "STEP", value_enum)]
+    explain: Option<Step>,
+
     /// Do not ask to retry failed steps
     #[clap(long = "no-retry")]
     no_retry: bool,
 
+    /// Run the Ubuntu release upgrade without asking for a confirmation
+    #[clap(long = "confirm-release-upgrade")]
+    confirm_release_upgrade: bool,
+
+    /// Stop at the first failing step, the remaining ones are not run
+    #[clap(long = "strict")]
+    strict: bool,
+
+    /// Run in a CI pipeline: say yes to every step, don't retry, don't update Topgrade, don't
+    /// notify, don't show the breaking changes, print the summary as JSON and stop at the first
+    /// failure. The options of the configuration file still override these defaults
+    #[clap(long = "ci")]
+    ci: bool,
+
     /// Do not perform upgrades for the given steps
     #[clap(long = "disable", value_name = "STEP", value_enum, num_args = 1..)]
     disable: Vec<Step>,
@@ -700,6 +1424,19 @@ pub struct CommandLineArgs {
     #[clap(long = "only", value_name = "STEP", value_enum, num_args = 1..)]
     only: Vec<Step>,
 
+    /// Run the given steps even if they are disabled or not selected with `--only`
+    #[clap(long = "force-run", value_name = "STEP", value_enum, num_args = 1..)]
+    force_run: Vec<Step>,
+
+    /// Run the steps even if they are not due yet according to `step_frequency`
+    #[clap(long = "force")]
+    force: bool,
+
+    /// Perform only the steps carrying the given tag, in addition to the ones selected with `--only`.
+    /// `--disable` still applies to them
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+
     /// Run only specific custom commands
     #[clap(long = "custom-commands", value_name = "NAME", num_args = 1..)]
     custom_commands: Vec<String>,
@@ -734,9 +1471,10 @@ pub struct CommandLineArgs {
     #[clap(long = "disable-predefined-git-repos")]
     disable_predefined_git_repos: bool,
 
-    /// Alternative configuration file
+    /// Alternative configuration file.
+    /// Can be given several times, later files override the earlier ones
     #[clap(long = "config", value_name = "PATH")]
-    config: Option<PathBuf>,
+    config: Vec<PathBuf>,
 
     /// A regular expression for restricting remote host execution
     #[clap(long = "remote-host-limit", value_name = "REGEX")]
@@ -763,6 +1501,18 @@ pub struct CommandLineArgs {
     /// Don't update Topgrade
     #[clap(long = "no-self-update")]
     pub no_self_update: bool,
+
+    /// Stop after the given step has run, the later steps are not run
+    #[clap(long = "until", value_name = "STEP", value_enum)]
+    until: Option<Step>,
+
+    /// Skip the steps before the given one and run from there onward
+    #[clap(long = "resume-from", value_name = "STEP", value_enum)]
+    resume_from: Option<Step>,
+
+    /// Stop starting new steps once the run has taken longer than the given duration (e.g. `45m`, `1h30m`)
+    #[clap(long = "max-run-time", value_name = "DURATION", value_parser = parse_duration)]
+    max_run_time: Option<Duration>,
 }
 
 impl CommandLineArgs {
@@ -817,9 +1567,15 @@ impl Config {
     ///
     /// The function parses the command line arguments and reads the configuration file.
     pub fn load(opt: CommandLineArgs) -> Result<Self> {
+        // Unlike errors in their contents, missing configuration files that were explicitly
+        // asked for are most likely a mistake, don't run with a different configuration.
+        if let Some(missing) = opt.config.iter().find(|path| !path.is_file()) {
+            bail!("Configuration file {} does not exist", missing.display());
+        }
+
         let config_directory = config_directory();
-        let config_file = if config_directory.is_dir() {
-            ConfigFile::read(opt.config.clone()).unwrap_or_else(|e| {
+        let config_file = if !opt.config.is_empty() || config_directory.is_dir() {
+            ConfigFile::read_layered(&opt.config).unwrap_or_else(|e| {
                 // Inform the user about errors when loading the configuration,
                 // but fallback to the default config to at least attempt to do something
                 error!("failed to load configuration: {}", e);
@@ -830,6 +1586,11 @@ impl Config {
             ConfigFile::default()
         };
 
+        let frequencies = config_file.misc.as_ref().and_then(|misc| misc.step_frequency.as_ref());
+        for frequency in frequencies.into_iter().flat_map(HashMap::values) {
+            parse_step_frequency(frequency).map_err(|e| eyre!("step_frequency: {e}"))?;
+        }
+
         let allowed_steps = Self::allowed_steps(&opt, &config_file);
 
         Ok(Self {
@@ -839,6 +1600,445 @@ impl Config {
         })
     }
 
+    /// Whether to install the periodic run with `--schedule`
+    pub fn schedule(&self) -> bool {
+        self.opt.schedule
+    }
+
+    /// Whether to remove the periodic run with `--unschedule`
+    pub fn unschedule(&self) -> bool {
+        self.opt.unschedule
+    }
+
+    /// The configuration files given with `--config`
+    pub fn config_paths(&self) -> &[PathBuf] {
+        &self.opt.config
+    }
+
+    /// The interval between the runs installed with `--schedule` (default: 24h)
+    pub fn schedule_interval(&self) -> Result<Duration> {
+        match self
+            .config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.schedule_interval.as_deref())
+        {
+            Some(interval) => parse_duration(interval).map_err(|e| eyre!("schedule_interval: {e}")),
+            None => Ok(Duration::from_secs(24 * 60 * 60)),
+        }
+    }
+
+    /// The URL the step results are posted to
+    pub fn webhook_url(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.webhook_url.as_deref())
+    }
+
+    /// The directory the output of each step is written to, in its own file
+    pub fn per_step_logs_dir(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.per_step_logs_dir.as_deref())
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).into_owned()))
+    }
+
+    /// Where to email the summary of the run, if it should be
+    pub fn email_report(&self) -> Option<&Email> {
+        self.config_file.email.as_ref().filter(|email| email.report)
+    }
+
+    /// The ollama models to pull, all the installed ones when not set
+    pub fn ollama_models(&self) -> Option<&[String]> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.ollama_models.as_deref())
+    }
+
+    /// Whether to run `rye self update` (default: true)
+    pub fn rye_self_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rye_self_update)
+            .unwrap_or(true)
+    }
+
+    /// The Rye projects to sync
+    pub fn rye_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rye_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// The maximum random delay before the first step, in seconds (default: 0)
+    pub fn network_jitter(&self) -> u64 {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.network_jitter)
+            .unwrap_or(0)
+    }
+
+    /// The Neovim channel bob should use, all the installed versions are updated when not set
+    pub fn bob_channel(&self) -> Option<BobChannel> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.bob_channel)
+    }
+
+    /// Whether to update the gems installed with `--user-install` (`Some(true)`) or the system gems
+    /// with sudo (`Some(false)`), guessed from the environment when not set
+    pub fn gem_user_install(&self) -> Option<bool> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.gem_user_install)
+    }
+
+    /// Whether to regenerate the `[[completions]]` after the upgrades (default: false)
+    pub fn regenerate_completions(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.regenerate_completions)
+            .unwrap_or(false)
+    }
+
+    /// The directory the `[[completions]]` are written to
+    pub fn completions_dir(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.completions_dir.as_ref())
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).into_owned()))
+    }
+
+    /// The .NET global tools that should not be updated
+    pub fn dotnet_ignore(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.dotnet_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to update oh-my-zsh itself with its upgrade script (default: true)
+    pub fn omz(&self) -> bool {
+        self.config_file.misc.as_ref().and_then(|misc| misc.omz).unwrap_or(true)
+    }
+
+    /// Whether to run `guix pull` before upgrading the Guix profile (default: true)
+    pub fn guix_pull(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.guix_pull)
+            .unwrap_or(true)
+    }
+
+    /// The micromamba environments that should not be updated, by name or path
+    pub fn micromamba_ignore(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.micromamba_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to rebuild the man page index after the system packages (default: false)
+    pub fn rebuild_mandb(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rebuild_mandb)
+            .unwrap_or(false)
+    }
+
+    /// Whether to refresh the `locate` database after the system packages (default: false)
+    pub fn rebuild_locatedb(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rebuild_locatedb)
+            .unwrap_or(false)
+    }
+
+    /// The URLs of the zellij plugins to download again
+    pub fn zellij_plugins(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.zellij_plugins.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to run `pixi self-update` (default: true)
+    pub fn pixi_self_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.pixi_self_update)
+            .unwrap_or(true)
+    }
+
+    /// The pixi projects to update
+    pub fn pixi_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.pixi_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// Whether pyenv installs the latest patch release of the installed Python versions (default: false)
+    pub fn pyenv_install_latest(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.pyenv_install_latest)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run `restic self-update` (default: false)
+    pub fn restic_self_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.restic_self_update)
+            .unwrap_or(false)
+    }
+
+    /// The built-in self-updating tools that should not be updated
+    pub fn self_updating_tools_ignore(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.self_updating_tools_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The built-in tools updated with their installer script that should not be updated
+    pub fn installer_script_tools_ignore(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.installer_script_tools_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The vcpkg manifest mode projects to upgrade
+    pub fn vcpkg_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.vcpkg_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// The Conan projects whose dependencies are updated
+    pub fn conan_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.conan_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// Whether to update the FreeBSD base system with `freebsd-update` (default: true)
+    pub fn freebsd_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.freebsd_update)
+            .unwrap_or(true)
+    }
+
+    /// Whether to update the OpenBSD firmware with `fw_update` (default: true)
+    pub fn openbsd_fw_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.openbsd_fw_update)
+            .unwrap_or(true)
+    }
+
+    /// Whether to install the OpenBSD binary patches with `syspatch` (default: false)
+    pub fn openbsd_syspatch(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.openbsd_syspatch)
+            .unwrap_or(false)
+    }
+
+    /// Whether to upgrade the packages installed imperatively in the Nix user profile (default: true)
+    pub fn nix_env_upgrade(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.nix_env_upgrade)
+            .unwrap_or(true)
+    }
+
+    /// Whether to report the unreachable Nix substituters before the Nix upgrade (default: false)
+    pub fn nix_check_substituters(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.nix_check_substituters)
+            .unwrap_or(false)
+    }
+
+    /// The project directories whose direnv environment is warmed up after the upgrades
+    pub fn direnv_dirs(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.direnv_dirs.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// Whether to refresh the authentication of the cloud CLIs before the upgrades (default: false)
+    pub fn refresh_cloud_auth(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.refresh_cloud_auth)
+            .unwrap_or(false)
+    }
+
+    /// Whether to remind to rehash the shell after the brew and MacPorts upgrades (default: false)
+    pub fn suggest_rehash(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.suggest_rehash)
+            .unwrap_or(false)
+    }
+
+    /// Whether asdf and mise regenerate their shims after the update (default: true)
+    pub fn reshim_after_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.reshim_after_update)
+            .unwrap_or(true)
+    }
+
+    /// The Spack environments to concretize again and install
+    pub fn spack_environments(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.spack_environments.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to run `bun upgrade` (default: true)
+    pub fn bun_self_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.bun_self_update)
+            .unwrap_or(true)
+    }
+
+    /// The command run in each toolbox instead of Topgrade
+    pub fn toolbx_command(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.toolbx_command.as_deref())
+    }
+
+    /// The `ujust` or `just` recipe updating the system
+    pub fn just_update_recipe(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.just_update_recipe.as_deref())
+    }
+
+    /// The prefix of the cargo installed binaries updated in their own step
+    pub fn cargo_companion_prefix(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_companion_prefix.as_deref())
+    }
+
+    /// The download rate limit of the package managers, in KB/s
+    pub fn bandwidth_limit(&self) -> Option<u32> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.bandwidth_limit)
+    }
+
+    /// The manifest of the tools installed with `cargo install`, with their versions
+    pub fn cargo_tools_manifest(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_tools_manifest.as_deref())
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+    }
+
+    /// The dotfiles directory of the `stow` packages
+    pub fn stow_dir(&self) -> PathBuf {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.stow_dir.as_deref())
+            .map_or_else(
+                || PathBuf::from(shellexpand::tilde("~/dotfiles").as_ref()),
+                |dir| PathBuf::from(shellexpand::tilde(dir).as_ref()),
+            )
+    }
+
+    /// The requirements file of the Ansible Galaxy roles and collections kept up to date
+    pub fn ansible_galaxy_requirements(&self) -> PathBuf {
+        let path = self
+            .config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.ansible_galaxy_requirements.as_deref())
+            .unwrap_or("~/.ansible/requirements.yml");
+        PathBuf::from(shellexpand::tilde(path).as_ref())
+    }
+
+    /// The packages of `stow_dir` restowed by the `stow` step
+    pub fn stow_packages(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.stow_packages.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to only check the environment with `--doctor`
+    pub fn doctor(&self) -> bool {
+        self.opt.doctor
+    }
+
+    /// Read the configuration files again, returning the errors `load` falls back from.
+    pub fn check_config_files(&self) -> Result<()> {
+        ConfigFile::read_layered(&self.opt.config).map(|_| ())
+    }
+
     /// Launch an editor to edit the configuration
     pub fn edit() -> Result<()> {
         ConfigFile::edit()
@@ -859,6 +2059,69 @@ impl Config {
         &self.config_file.commands
     }
 
+    /// The shell and its arguments running the custom command or plugin update `name`, if not the default one
+    pub fn command_shell(&self, name: &str) -> Option<&[String]> {
+        self.config_file
+            .command_shells
+            .as_ref()
+            .and_then(|shells| shells.get(name))
+            .map(Vec::as_slice)
+    }
+
+    /// The plugin systems updated with user provided commands.
+    pub fn plugin_updates(&self) -> &[PluginUpdate] {
+        self.config_file.plugin_updates.as_deref().unwrap_or_default()
+    }
+
+    /// The tools built from git repositories.
+    pub fn git_builds(&self) -> &[GitBuild] {
+        self.config_file.git_builds.as_deref().unwrap_or_default()
+    }
+
+    /// The editor configuration repositories pulled before the plugins of `step` are updated.
+    pub fn editor_config_repos(&self, step: Step) -> impl Iterator<Item = &EditorConfigRepo> {
+        self.config_file
+            .editor_config_repos
+            .iter()
+            .flatten()
+            .filter(move |repo| repo.step == step)
+    }
+
+    /// The manifest repositories applied with kubectl.
+    pub fn kubectl_manifests(&self) -> &[KubectlManifests] {
+        self.config_file.kubectl_manifests.as_deref().unwrap_or_default()
+    }
+
+    /// The tools updated by running their own update subcommand.
+    pub fn self_updating_tools(&self) -> &[SelfUpdatingTool] {
+        self.config_file.self_updating_tools.as_deref().unwrap_or_default()
+    }
+
+    /// The tools updated with the installer script they ship.
+    pub fn installer_script_tools(&self) -> &[InstallerScriptTool] {
+        self.config_file.installer_script_tools.as_deref().unwrap_or_default()
+    }
+
+    /// The cargo subcommands updated with their own update invocation.
+    pub fn cargo_subcommand_updates(&self) -> &[CargoSubcommandUpdate] {
+        self.config_file.cargo_subcommand_updates.as_deref().unwrap_or_default()
+    }
+
+    /// The completion scripts and man pages to regenerate.
+    pub fn completions(&self) -> &[Completion] {
+        self.config_file.completions.as_deref().unwrap_or_default()
+    }
+
+    /// The caches to rebuild, the built-in ones when `[[cache_refresh]]` isn't set.
+    pub fn cache_refresh(&self) -> Option<&[CacheRefresh]> {
+        self.config_file.cache_refresh.as_deref()
+    }
+
+    /// The cloud CLIs whose authentication is refreshed, the built-in ones when it isn't set.
+    pub fn cloud_auth(&self) -> Option<&[CloudAuth]> {
+        self.config_file.cloud_auth.as_deref()
+    }
+
     /// The list of additional git repositories to pull.
     pub fn git_repos(&self) -> Option<&Vec<String>> {
         self.config_file.git.as_ref().and_then(|git| git.repos.as_ref())
@@ -872,6 +2135,36 @@ impl Config {
             .and_then(|containers| containers.ignored_containers.as_ref())
     }
 
+    /// Whether to prune the build cache of the docker buildx builders (default: false)
+    pub fn buildx_prune(&self) -> bool {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.buildx_prune)
+            .unwrap_or(false)
+    }
+
+    /// The directories of the docker compose projects to pull and recreate
+    pub fn compose_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.compose_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// Whether to take the compose projects down before recreating them (default: false)
+    pub fn compose_prune(&self) -> bool {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.compose_prune)
+            .unwrap_or(false)
+    }
+
     /// Tell whether the specified step should run.
     ///
     /// If the step appears either in the `--disable` command line argument
@@ -881,6 +2174,10 @@ impl Config {
     }
 
     fn allowed_steps(opt: &CommandLineArgs, config_file: &ConfigFile) -> Vec<Step> {
+        if let Some(step) = opt.explain {
+            return vec![step];
+        }
+
         let mut enabled_steps: Vec<Step> = Vec::new();
         enabled_steps.extend(&opt.only);
 
@@ -890,7 +2187,20 @@ impl Config {
             }
         }
 
-        if enabled_steps.is_empty() {
+        if !opt.tags.is_empty() {
+            let step_tags = config_file.misc.as_ref().and_then(|misc| misc.step_tags.as_ref());
+            enabled_steps.extend(Step::iter().filter(|step| {
+                let configured_tags = step_tags.and_then(|tags| tags.get(step)).into_iter().flatten();
+                step.default_tags()
+                    .iter()
+                    .copied()
+                    .chain(configured_tags.map(String::as_str))
+                    .any(|tag| opt.tags.iter().any(|wanted| wanted == tag))
+            }));
+        }
+
+        // Selecting a tag that no step carries shouldn't run everything
+        if enabled_steps.is_empty() && opt.tags.is_empty() {
             enabled_steps.extend(Step::iter());
         }
 
@@ -903,18 +2213,63 @@ impl Config {
         }
 
         enabled_steps.retain(|e| !disabled_steps.contains(e) || opt.only.contains(e));
+
+        for step in &opt.force_run {
+            if !enabled_steps.contains(step) {
+                enabled_steps.push(*step);
+            }
+        }
+
         enabled_steps
     }
 
+    /// Tell whether this Topgrade was started by another one, in a remote host, a Vagrant box, a
+    /// WSL distribution or a Toolbx container. Nobody is there to answer prompts, and the binary
+    /// of the guest is not ours to replace.
+    pub fn remote_guest(&self) -> bool {
+        env::var_os("TOPGRADE_PREFIX").is_some()
+    }
+
+    /// Tell whether we run in a CI pipeline, with `--ci`.
+    pub fn ci(&self) -> bool {
+        self.opt.ci
+    }
+
     /// Tell whether we should run a self-update.
     pub fn no_self_update(&self) -> bool {
         self.opt.no_self_update
+            || self.remote_guest()
             || self
                 .config_file
                 .misc
                 .as_ref()
                 .and_then(|misc| misc.no_self_update)
-                .unwrap_or(false)
+                .unwrap_or(self.opt.ci)
+    }
+
+    /// How Topgrade should update itself
+    #[cfg_attr(not(feature = "self-update"), allow(dead_code))]
+    pub fn self_update_strategy(&self) -> SelfUpdateStrategy {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.self_update_strategy)
+            .unwrap_or(SelfUpdateStrategy::Auto)
+    }
+
+    /// The step after which the run stops, if any.
+    pub fn until(&self) -> Option<Step> {
+        self.opt.until
+    }
+
+    /// The step the run starts from, if any.
+    pub fn resume_from(&self) -> Option<Step> {
+        self.opt.resume_from
+    }
+
+    /// The overall time budget of the run, if any.
+    pub fn max_run_time(&self) -> Option<Duration> {
+        self.opt.max_run_time
     }
 
     /// Tell whether we should run in tmux.
@@ -941,18 +2296,45 @@ impl Config {
 
     /// Tell whether we are dry-running.
     pub fn dry_run(&self) -> bool {
-        self.opt.dry_run
+        self.opt.dry_run || self.opt.explain.is_some()
+    }
+
+    /// The step to explain instead of running it.
+    pub fn explain(&self) -> Option<Step> {
+        self.opt.explain
+    }
+
+    /// Tell whether we should only refresh metadata instead of upgrading.
+    pub fn metadata_only(&self) -> bool {
+        self.opt.metadata_only
+    }
+
+    /// Whether the Ubuntu release upgrade was confirmed on the command line
+    pub fn confirm_release_upgrade(&self) -> bool {
+        self.opt.confirm_release_upgrade
     }
 
     /// Tell whether we should not attempt to retry anything.
     pub fn no_retry(&self) -> bool {
         self.opt.no_retry
+            || self.remote_guest()
             || self
                 .config_file
                 .misc
                 .as_ref()
                 .and_then(|misc| misc.no_retry)
-                .unwrap_or(false)
+                .unwrap_or(self.opt.ci)
+    }
+
+    /// Tell whether the run stops at the first failing step.
+    pub fn fail_fast(&self) -> bool {
+        self.opt.strict
+            || self
+                .config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.fail_fast)
+                .unwrap_or(self.opt.ci)
     }
 
     /// List of remote hosts to run Topgrade in
@@ -985,6 +2367,15 @@ impl Config {
         self.config_file.git.as_ref().and_then(|git| git.arguments.as_ref())
     }
 
+    /// Whether to also move the submodules of the pulled repositories to their remote branch
+    pub fn git_update_submodules(&self) -> bool {
+        self.config_file
+            .git
+            .as_ref()
+            .and_then(|git| git.update_submodules)
+            .unwrap_or(false)
+    }
+
     /// Extra Tmux arguments
     pub fn tmux_arguments(&self) -> Result<Vec<String>> {
         let args = &self
@@ -1006,7 +2397,7 @@ impl Config {
 
     /// Prompt for a key before exiting
     pub fn keep_at_end(&self) -> bool {
-        self.opt.keep_at_end || env::var("TOPGRADE_KEEP_END").is_ok()
+        (self.opt.keep_at_end || env::var("TOPGRADE_KEEP_END").is_ok()) && !self.remote_guest()
     }
 
     /// Skip sending a notification at the end of a run
@@ -1015,7 +2406,7 @@ impl Config {
             return yes;
         }
 
-        self.opt.skip_notify
+        self.opt.skip_notify || self.opt.ci
     }
 
     /// Whether to set the terminal title
@@ -1029,6 +2420,10 @@ impl Config {
 
     /// Whether to say yes to package managers
     pub fn yes(&self, step: Step) -> bool {
+        if self.remote_guest() {
+            return true;
+        }
+
         if let Some(yes) = self.config_file.misc.as_ref().and_then(|misc| misc.assume_yes) {
             return yes;
         }
@@ -1041,7 +2436,7 @@ impl Config {
             return yes_list.contains(&step);
         }
 
-        false
+        self.opt.ci
     }
 
     /// Bash-it branch
@@ -1053,6 +2448,171 @@ impl Config {
             .unwrap_or("stable")
     }
 
+    /// The Flutter channel to switch to before upgrading
+    pub fn flutter_channel(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.flutter_channel.as_deref())
+    }
+
+    /// Whether `chezmoi update` should also apply the pulled changes
+    pub fn chezmoi_apply(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.chezmoi_apply)
+            .unwrap_or(true)
+    }
+
+    /// Go packages or modules that shouldn't be reinstalled
+    pub fn go_ignore(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.go_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to upgrade the packages of all opam switches instead of the current one
+    pub fn opam_all_switches(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.opam_all_switches)
+            .unwrap_or(false)
+    }
+
+    /// The flake `home-manager switch` should use
+    pub fn home_manager_flake(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.home_manager_flake.as_ref())
+            .map(|flake| PathBuf::from(shellexpand::tilde(flake).into_owned()))
+    }
+
+    /// The flake `darwin-rebuild switch` should use
+    pub fn nix_darwin_flake(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.nix_darwin_flake.as_ref())
+            .map(|flake| PathBuf::from(shellexpand::tilde(flake).into_owned()))
+    }
+
+    /// The package manager used to upgrade Emacs packages
+    pub fn emacs_package_manager(&self) -> EmacsPackageManager {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.emacs_package_manager)
+            .unwrap_or(EmacsPackageManager::Paradox)
+    }
+
+    /// How long upgrading Emacs packages may take before Emacs is killed
+    pub fn emacs_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.emacs_timeout)
+                .unwrap_or(600),
+        )
+    }
+
+    /// Whether to run `port reclaim` after upgrading MacPorts ports
+    pub fn macports_reclaim(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.macports_reclaim)
+            .unwrap_or_else(|| self.cleanup())
+    }
+
+    /// Whether to ask before deleting anything during cleanups
+    pub fn confirm_destructive(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.confirm_destructive)
+            .unwrap_or(false)
+    }
+
+    /// The password manager CLIs whose vault should be synced
+    pub fn password_managers(&self) -> &[String] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.password_managers.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether `cargo install-update` should also update crates installed from git
+    pub fn cargo_update_git(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_update_git)
+            .unwrap_or(true)
+    }
+
+    /// The Rust projects whose dependencies are updated
+    pub fn cargo_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
+    /// Whether to also run `cargo upgrade` in `cargo_projects` when cargo-edit is installed
+    pub fn cargo_projects_upgrade(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_projects_upgrade)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run `rustup self update`
+    pub fn rustup_self_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rustup_self_update)
+            .unwrap_or(true)
+    }
+
+    /// Steps skipped when the load average is above a threshold
+    pub fn skip_if_busy(&self) -> Option<&SkipIfBusy> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.skip_if_busy.as_ref())
+    }
+
+    /// Steps whose commands run in a pseudo-terminal when stdout is not a terminal
+    pub fn needs_tty(&self) -> &[Step] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.needs_tty.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Steps that shouldn't run when the system runs on battery
+    pub fn skip_on_battery(&self) -> &[Step] {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.skip_on_battery.as_deref())
+            .unwrap_or_default()
+    }
+
     /// Whether to accept all Windows updates
     pub fn accept_all_windows_updates(&self) -> bool {
         self.config_file
@@ -1089,6 +2649,33 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to install the driver updates offered by Windows Update (default: false)
+    pub fn windows_driver_updates(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.driver_updates)
+            .unwrap_or(false)
+    }
+
+    /// The identifiers of the packages winget doesn't upgrade
+    pub fn winget_ignore(&self) -> &[String] {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.winget_ignore.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to update the installed Visual Studio instances (default: false)
+    pub fn visual_studio(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.visual_studio)
+            .unwrap_or(false)
+    }
+
     /// Whether Brew cask should be greedy
     pub fn brew_cask_greedy(&self) -> bool {
         self.config_file
@@ -1107,6 +2694,24 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether Brew cask should also upgrade the casks updating themselves
+    pub fn brew_greedy_auto_updates(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.greedy_auto_updates)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew cask should upgrade without quarantining the downloads
+    pub fn brew_cask_no_quarantine(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.cask_no_quarantine)
+            .unwrap_or(false)
+    }
+
     /// Whether Brew should autoremove
     pub fn brew_autoremove(&self) -> bool {
         self.config_file
@@ -1116,6 +2721,26 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to run `brew doctor` to find deprecated formulae
+    pub fn brew_doctor(&self) -> bool {
+        self.config_file.brew.as_ref().and_then(|c| c.doctor).unwrap_or(false)
+    }
+
+    /// How many bottles Brew downloads at once during the upgrades, Brew's own setting when not set
+    pub fn brew_parallel_downloads(&self) -> Option<u32> {
+        self.config_file.brew.as_ref().and_then(|c| c.parallel_downloads)
+    }
+
+    /// The mirror Brew downloads the bottles from, set as `HOMEBREW_BOTTLE_DOMAIN`
+    pub fn brew_bottle_mirror(&self) -> Option<&str> {
+        self.config_file.brew.as_ref().and_then(|c| c.bottle_mirror.as_deref())
+    }
+
+    /// The mirror of the Homebrew API, set as `HOMEBREW_API_DOMAIN`
+    pub fn brew_api_domain(&self) -> Option<&str> {
+        self.config_file.brew.as_ref().and_then(|c| c.api_domain.as_deref())
+    }
+
     /// Whether Brew should upgrade formulae built from the HEAD branch
     pub fn brew_fetch_head(&self) -> bool {
         self.config_file
@@ -1125,6 +2750,51 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to run `brew missing` to find the formulae with missing dependencies
+    pub fn brew_check_missing(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.check_missing)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report the installed formulae which moved to a cask (default: false)
+    pub fn brew_report_migrations(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.report_migrations)
+            .unwrap_or(false)
+    }
+
+    /// The casks reinstalled instead of upgraded when they are outdated
+    pub fn brew_reinstall_casks(&self) -> &[String] {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.reinstall_casks.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to uninstall the formulae and casks missing from the global Brewfile (default: false)
+    pub fn brew_bundle_cleanup(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.bundle_cleanup)
+            .unwrap_or(false)
+    }
+
+    /// Whether to restart the running `brew services` of the upgraded formulae
+    pub fn brew_restart_services(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.restart_services)
+            .unwrap_or(false)
+    }
+
     /// Whether Composer should update itself
     pub fn composer_self_update(&self) -> bool {
         self.config_file
@@ -1143,6 +2813,27 @@ impl Config {
             .unwrap_or_default()
     }
 
+    /// Which Terraform implementation to keep up to date
+    pub fn terraform_flavor(&self) -> TerraformFlavor {
+        self.config_file
+            .terraform
+            .as_ref()
+            .and_then(|terraform| terraform.flavor)
+            .unwrap_or(TerraformFlavor::Terraform)
+    }
+
+    /// The Terraform projects whose providers are upgraded
+    pub fn terraform_projects(&self) -> Vec<PathBuf> {
+        self.config_file
+            .terraform
+            .as_ref()
+            .and_then(|terraform| terraform.projects.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
     /// Whether to send a desktop notification at the beginning of every step
     pub fn notify_each_step(&self) -> bool {
         self.config_file
@@ -1185,17 +2876,71 @@ impl Config {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.pamac_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|s| s.pamac_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Show news on Arch Linux
+    pub fn show_arch_news(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.show_arch_news)
+            .unwrap_or(true)
+    }
+
+    /// Whether to update `archlinux-keyring` before the Arch Linux upgrade (default: false)
+    pub fn arch_update_keyring_first(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.arch_update_keyring_first)
+            .unwrap_or(false)
+    }
+
+    /// Whether to also run `pacman-key --refresh-keys` with the keyring update (default: false)
+    pub fn arch_refresh_keys(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.arch_refresh_keys)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report a pacman lock left behind and unmerged `.pacnew` files after the upgrades (default: false)
+    pub fn pacman_check(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.pacman_check)
+            .unwrap_or(false)
+    }
+
+    /// The command merging the `.pacnew` and `.pacsave` files, such as `pacdiff`
+    pub fn pacman_merge_tool(&self) -> Option<&str> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.pacman_merge_tool.as_deref())
+    }
+
+    /// Whether yay or paru only upgrade the repository packages with the system, leaving the AUR
+    /// packages to the `aur` step (default: false)
+    pub fn aur_only(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.aur_only)
+            .unwrap_or(false)
     }
 
-    /// Show news on Arch Linux
-    pub fn show_arch_news(&self) -> bool {
+    /// The AUR packages that should not be upgraded
+    pub fn aur_ignore(&self) -> &[String] {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.show_arch_news)
-            .unwrap_or(true)
+            .and_then(|linux| linux.aur_ignore.as_deref())
+            .unwrap_or_default()
     }
 
     /// Get the package manager of an Arch Linux system
@@ -1240,6 +2985,80 @@ impl Config {
             .and_then(|linux| linux.apt_arguments.as_deref())
     }
 
+    /// The sideloaded `.deb` packages and the commands fetching their latest `.deb`
+    pub fn deb_sources(&self) -> Option<&BTreeMap<String, String>> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.deb_sources.as_ref())
+    }
+
+    /// The sources list apt uses instead of the system's ones, such as a snapshot mirror
+    pub fn apt_sources_override(&self) -> Option<PathBuf> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_sources_override.as_deref())
+            .map(|path| PathBuf::from(shellexpand::tilde(path).into_owned()))
+    }
+
+    /// Whether apt should install the updates held back by Ubuntu's phasing (default: false)
+    pub fn apt_include_phased(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_include_phased)
+            .unwrap_or(false)
+    }
+
+    /// Whether apt waits for a running unattended-upgrade to finish instead of skipping (default: false)
+    pub fn apt_wait_for_unattended(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_wait_for_unattended)
+            .unwrap_or(false)
+    }
+
+    /// How long apt waits for a running unattended-upgrade to finish (default: 600 seconds)
+    pub fn apt_unattended_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.config_file
+                .linux
+                .as_ref()
+                .and_then(|linux| linux.apt_unattended_timeout)
+                .unwrap_or(600),
+        )
+    }
+
+    /// Whether to run `dpkg --configure -a` when `dpkg --audit` finds broken packages and `--yes` is set
+    /// (default: false)
+    pub fn dpkg_configure_pending(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.dpkg_configure_pending)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run `nixos-rebuild switch --rollback` when the switch fails and `--yes` is set (default: false)
+    pub fn nix_rollback_on_failure(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.nix_rollback_on_failure)
+            .unwrap_or(false)
+    }
+
+    /// Whether to offer upgrading Ubuntu to its next release with `do-release-upgrade` (default: false)
+    pub fn ubuntu_release_upgrade(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.ubuntu_release_upgrade)
+            .unwrap_or(false)
+    }
+
     /// Extra dnf arguments
     pub fn dnf_arguments(&self) -> Option<&str> {
         self.config_file
@@ -1348,6 +3167,87 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to update the bootloader and regenerate its configuration after the upgrades (default: false)
+    pub fn update_bootloader(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.update_bootloader)
+            .unwrap_or(false)
+    }
+
+    /// Whether to remove the disabled snap revisions during cleanups (default: false)
+    pub fn snap_remove_old_revisions(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snap_remove_old_revisions)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report the snap interfaces disconnected by the refresh (default: false)
+    pub fn snap_report_disconnected(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snap_report_disconnected)
+            .unwrap_or(false)
+    }
+
+    /// Whether to reconnect the snap interfaces disconnected by the refresh, with `--yes` (default: false)
+    pub fn snap_reconnect(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snap_reconnect)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report the security updates apt or dnf still didn't install (default: false)
+    pub fn report_pending_security(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.report_pending_security)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run needrestart after the system upgrade (default: true)
+    pub fn needrestart(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.needrestart)
+            .unwrap_or(true)
+    }
+
+    /// Whether to list the services running outdated code when needrestart isn't used (default: false)
+    pub fn report_restart_needed(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.report_restart_needed)
+            .unwrap_or(false)
+    }
+
+    /// Whether to restart the services `report_restart_needed` lists, with `--yes` (default: false)
+    pub fn auto_restart_services(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.auto_restart_services)
+            .unwrap_or(false)
+    }
+
+    /// Whether to commit /etc with etckeeper around the system package steps (default: false)
+    pub fn etckeeper(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.etckeeper)
+            .unwrap_or(false)
+    }
+
     /// Determine if we should ignore failures for this step
     pub fn ignore_failure(&self, step: Step) -> bool {
         self.config_file
@@ -1393,8 +3293,9 @@ impl Config {
         ret
     }
 
+    /// Whether the skipped steps are reported, always when explaining a step
     pub fn show_skipped(&self) -> bool {
-        self.opt.show_skipped
+        self.opt.show_skipped || self.opt.explain.is_some()
     }
 
     pub fn open_remotes_in_new_terminal(&self) -> bool {
@@ -1427,6 +3328,33 @@ impl Config {
             .and_then(|npm| npm.use_sudo)
             .unwrap_or(false)
     }
+    /// The tool used to install Node itself
+    pub fn node_version_manager(&self) -> NodeVersionManager {
+        self.config_file
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.version_manager)
+            .unwrap_or(NodeVersionManager::Autodetect)
+    }
+
+    /// The Node release line to follow
+    pub fn node_channel(&self) -> NodeChannel {
+        self.config_file
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.node_channel)
+            .unwrap_or(NodeChannel::Lts)
+    }
+
+    /// Whether to reinstall the global packages of the previous Node version
+    pub fn node_migrate_packages(&self) -> bool {
+        self.config_file
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.migrate_packages)
+            .unwrap_or(false)
+    }
+
     #[cfg(target_os = "linux")]
     pub fn yarn_use_sudo(&self) -> bool {
         self.config_file
@@ -1454,6 +3382,69 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// The Flatpak remotes added to the system installation when missing, by name
+    pub fn flatpak_remotes(&self) -> Option<&BTreeMap<String, String>> {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.remotes.as_ref())
+    }
+
+    /// Whether to uninstall the unused Flatpak runtimes after the update (default: false)
+    pub fn flatpak_remove_unused(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.remove_unused)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report the apps whose permissions changed since the previous run (default: false)
+    pub fn flatpak_report_permission_changes(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.report_permission_changes)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run `flatpak repair` on the installations (default: false)
+    pub fn flatpak_repair(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.repair)
+            .unwrap_or(false)
+    }
+
+    /// Run `flatpak repair` once every this many runs (default: 1)
+    pub fn flatpak_repair_interval(&self) -> u32 {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.repair_interval)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// The `systemd-run` properties limiting the resources used by the commands of the steps
+    #[cfg(target_os = "linux")]
+    pub fn resource_limit_properties(&self) -> Vec<String> {
+        let mut properties = Vec::new();
+        if let Some(limits) = self.config_file.resource_limits.as_ref() {
+            if let Some(cpu_quota) = &limits.cpu_quota {
+                properties.push(format!("CPUQuota={cpu_quota}"));
+            }
+            if let Some(io_weight) = limits.io_weight {
+                properties.push(format!("IOWeight={io_weight}"));
+            }
+            if let Some(memory_max) = &limits.memory_max {
+                properties.push(format!("MemoryMax={memory_max}"));
+            }
+        }
+        properties
+    }
+
     #[cfg(target_os = "linux")]
     str_value!(linux, emerge_sync_flags);
 
@@ -1477,12 +3468,11 @@ impl Config {
     }
 
     pub fn enable_pipupgrade(&self) -> bool {
-        return self
-            .config_file
+        self.config_file
             .python
             .as_ref()
             .and_then(|python| python.enable_pipupgrade)
-            .unwrap_or(false);
+            .unwrap_or(false)
     }
     pub fn pipupgrade_arguments(&self) -> &str {
         self.config_file
@@ -1491,21 +3481,50 @@ impl Config {
             .and_then(|s| s.pipupgrade_arguments.as_deref())
             .unwrap_or("")
     }
+
+    /// Whether the pip3 step also upgrades the explicitly installed user packages, not their dependencies
+    pub fn pip_only_explicit(&self) -> bool {
+        self.config_file
+            .python
+            .as_ref()
+            .and_then(|python| python.pip_only_explicit)
+            .unwrap_or(false)
+    }
+
+    /// The constraints file passed to `pip install`
+    pub fn pip_constraints(&self) -> Option<PathBuf> {
+        self.config_file
+            .python
+            .as_ref()
+            .and_then(|python| python.pip_constraints.as_deref())
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+    }
+
+    /// The directories searched for the virtualenvs whose requirements are upgraded
+    pub fn python_venv_roots(&self) -> Vec<PathBuf> {
+        self.config_file
+            .python
+            .as_ref()
+            .and_then(|python| python.venv_roots.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|path| PathBuf::from(shellexpand::tilde(path).as_ref()))
+            .collect()
+    }
+
     pub fn enable_pip_review(&self) -> bool {
-        return self
-            .config_file
+        self.config_file
             .python
             .as_ref()
             .and_then(|python| python.enable_pip_review)
-            .unwrap_or(false);
+            .unwrap_or(false)
     }
     pub fn enable_pip_review_local(&self) -> bool {
-        return self
-            .config_file
+        self.config_file
             .python
             .as_ref()
             .and_then(|python| python.enable_pip_review_local)
-            .unwrap_or(false);
+            .unwrap_or(false)
     }
 
     pub fn display_time(&self) -> bool {
@@ -1516,6 +3535,120 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// How much of the output of `step` should be shown
+    pub fn step_verbosity(&self, step: Step) -> StepVerbosity {
+        if self.verbose() {
+            return StepVerbosity::Normal;
+        }
+
+        let misc = self.config_file.misc.as_ref();
+        misc.and_then(|misc| misc.step_verbosity_overrides.as_ref())
+            .and_then(|overrides| overrides.get(&step).copied())
+            .or_else(|| misc.and_then(|misc| misc.step_verbosity))
+            .unwrap_or(StepVerbosity::Normal)
+    }
+
+    /// How often `step` runs, every time when not set in `step_frequency`.
+    ///
+    /// The frequencies are checked when the configuration is loaded.
+    pub fn step_frequency(&self, step: Step) -> Option<StepFrequency> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.step_frequency.as_ref())
+            .and_then(|frequencies| frequencies.get(&step))
+            .and_then(|frequency| parse_step_frequency(frequency).ok())
+    }
+
+    /// Whether `step` runs even if it is not due according to `step_frequency`, with `--force` or
+    /// `--force-run`.
+    pub fn force_run(&self, step: Step) -> bool {
+        self.opt.force || self.opt.force_run.contains(&step)
+    }
+
+    /// The colors of the separators, the messages and the summary (default: auto)
+    pub fn color_theme(&self) -> ColorTheme {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.color_theme)
+            .unwrap_or(ColorTheme::Auto)
+    }
+
+    /// The colors overriding the ones of the theme
+    pub fn colors(&self) -> Option<&Colors> {
+        self.config_file.colors.as_ref()
+    }
+
+    /// The character used to draw step separators
+    pub fn separator_char(&self) -> char {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.separator_char)
+            .unwrap_or('─')
+    }
+
+    /// The width of step separators
+    pub fn separator_width(&self) -> u16 {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.separator_width)
+            .unwrap_or(80)
+    }
+
+    /// Whether step separators should span the whole terminal width
+    pub fn separator_full_width(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.separator_full_width)
+            .unwrap_or(false)
+    }
+
+    /// Where to show the summary at the end of the run
+    pub fn summary_output(&self) -> SummaryOutput {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.summary_output)
+            .unwrap_or(SummaryOutput::Terminal)
+    }
+
+    /// The format of the summary at the end of the run (default: text, json with `--ci`)
+    pub fn summary_format(&self) -> ReportFormat {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.summary_format)
+            .unwrap_or(if self.opt.ci {
+                ReportFormat::Json
+            } else {
+                ReportFormat::Text
+            })
+    }
+
+    /// The file the summary is written to when `summary_output` is `file`
+    pub fn summary_file(&self) -> Option<PathBuf> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.summary_file.as_deref())
+            .map(|path| PathBuf::from(shellexpand::tilde(path).into_owned()))
+    }
+
+    /// Whether the summary counts the successful steps instead of listing them
+    pub fn summary_collapse_success(&self) -> bool {
+        !self.opt.verbose_summary
+            && self
+                .config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.summary_collapse_success)
+                .unwrap_or(false)
+    }
+
     pub fn should_run_custom_command(&self, name: &str) -> bool {
         if self.opt.custom_commands.is_empty() {
             return true;
@@ -1525,12 +3658,41 @@ impl Config {
     }
 }
 
+#[cfg(test)]
+impl Config {
+    /// A configuration made of the command line `args` and the `config_file` contents, for the tests.
+    pub fn for_tests(args: &[&str], config_file: &str) -> Config {
+        let opt = CommandLineArgs::parse_from(std::iter::once("topgrade").chain(args.iter().copied()));
+        let config_file: ConfigFile = toml::from_str(config_file).unwrap();
+        let allowed_steps = Self::allowed_steps(&opt, &config_file);
+        Config {
+            opt,
+            config_file,
+            allowed_steps,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use crate::config::*;
     use color_eyre::eyre::eyre;
 
+    #[test]
+    fn test_parse_step_frequency() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(parse_step_frequency("daily"), Ok(StepFrequency::Interval(day)));
+        assert_eq!(parse_step_frequency("weekly"), Ok(StepFrequency::Interval(day * 7)));
+        assert_eq!(
+            parse_step_frequency("12h"),
+            Ok(StepFrequency::Interval(Duration::from_secs(12 * 60 * 60)))
+        );
+        assert_eq!(parse_step_frequency("every 3 runs"), Ok(StepFrequency::Runs(3)));
+        assert!(parse_step_frequency("every 0 runs").is_err());
+        assert!(parse_step_frequency("sometimes").is_err());
+    }
+
     /// Test the default configuration in `config.example.toml` is valid.
     #[test]
     fn test_default_config() {
@@ -0,0 +1,122 @@
+//! `--doctor`: check that the environment is fit for a run, without upgrading anything.
+use std::env;
+use std::fs;
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result};
+use console::style;
+use tempfile::tempfile_in;
+
+use crate::breaking_changes::data_dir;
+use crate::config::{Config, Step};
+#[cfg(target_os = "linux")]
+use crate::steps::os::linux::Distribution;
+use crate::terminal::print_separator;
+use crate::utils::require;
+
+/// The binaries some steps need, a step runs if any of them is found.
+const STEP_BINARIES: &[(Step, &[&str])] = &[
+    (Step::Asdf, &["asdf"]),
+    (Step::Bat, &["bat", "batcat"]),
+    (Step::Bun, &["bun"]),
+    (Step::Cabal, &["cabal"]),
+    (Step::Cargo, &["cargo"]),
+    (Step::Chezmoi, &["chezmoi"]),
+    (Step::Composer, &["composer"]),
+    (Step::Conda, &["conda"]),
+    (Step::Containers, &["docker", "podman"]),
+    (Step::Deno, &["deno"]),
+    (Step::Dotnet, &["dotnet"]),
+    (Step::Emacs, &["emacs"]),
+    (Step::Etckeeper, &["etckeeper"]),
+    (Step::Flatpak, &["flatpak"]),
+    (Step::Flutter, &["flutter", "dart"]),
+    (Step::Gcloud, &["gcloud"]),
+    (Step::Gem, &["gem"]),
+    (Step::Ghcup, &["ghcup"]),
+    (Step::GitRepos, &["git"]),
+    (Step::Go, &["go"]),
+    (Step::Helix, &["helix", "hx"]),
+    (Step::Helm, &["helm"]),
+    (Step::HomeManager, &["home-manager"]),
+    (Step::Krew, &["kubectl-krew"]),
+    (Step::Mise, &["mise"]),
+    (Step::Nix, &["nix"]),
+    (Step::Node, &["npm"]),
+    (Step::Ollama, &["ollama"]),
+    (Step::Opam, &["opam"]),
+    (Step::Pipx, &["pipx"]),
+    (Step::Pkgx, &["pkgx", "pkgm"]),
+    (Step::Pnpm, &["pnpm"]),
+    (Step::Rustup, &["rustup"]),
+    (Step::Snap, &["snap"]),
+    (Step::Stack, &["stack"]),
+    (Step::Tldr, &["tldr"]),
+    (Step::Tmux, &["tmux"]),
+    (Step::Vim, &["vim", "nvim"]),
+    (Step::Vscode, &["code"]),
+    (Step::Yarn, &["yarn"]),
+];
+
+/// Print a diagnostic of the environment.
+///
+/// Returns an error if something would clearly break a run.
+pub fn run(config: &Config) -> Result<()> {
+    let mut problems = Vec::new();
+
+    print_separator("Environment");
+    println!("Topgrade {}", env!("CARGO_PKG_VERSION"));
+    println!("OS: {}", env!("TARGET"));
+    #[cfg(target_os = "linux")]
+    match Distribution::detect() {
+        Ok(distribution) => println!("Distribution: {distribution:?}"),
+        Err(e) => println!(
+            "{} {e}\nHint: the system upgrade step won't run, please report your distribution",
+            style("Unknown distribution:").yellow().bold()
+        ),
+    }
+
+    print_separator("Configuration");
+    match config.check_config_files() {
+        Ok(()) => println!("{}", style("The configuration is valid").green()),
+        Err(e) => {
+            println!("{} {e:?}", style("The configuration is invalid:").red().bold());
+            problems.push("the configuration is invalid");
+        }
+    }
+
+    print_separator("Data directory");
+    let data_dir = data_dir();
+    match fs::create_dir_all(&data_dir).and_then(|_| tempfile_in(&data_dir)) {
+        Ok(_) => println!("{} is writable", data_dir.display()),
+        Err(e) => {
+            println!(
+                "{} {}: {e}\nHint: Topgrade will ask to confirm the breaking changes of a new major release on every run",
+                style("Cannot write to").red().bold(),
+                data_dir.display()
+            );
+            problems.push("the data directory is not writable");
+        }
+    }
+
+    print_separator("Steps");
+    for (step, binaries) in STEP_BINARIES {
+        let step_name = step.to_possible_value().expect("steps are not skipped");
+        let step_name = step_name.get_name();
+        if !config.should_run(*step) {
+            println!("{step_name}: {}", style("disabled").dim());
+            continue;
+        }
+
+        match binaries.iter().find_map(|binary| require(binary).ok()) {
+            Some(path) => println!("{step_name}: {}", style(path.display()).green()),
+            None => println!("{step_name}: {} ({})", style("not found").dim(), binaries.join(", ")),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Found problems: {}", problems.join(", ")))
+    }
+}
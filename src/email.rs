@@ -0,0 +1,104 @@
+//! Email the summary of the run with the settings of the `[email]` section.
+//!
+//! The message is sent with `curl`, a delivery failure is only logged.
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use tempfile::NamedTempFile;
+use tracing::{debug, warn};
+
+use crate::command::CommandExt;
+use crate::config::{Email, ReportFormat};
+use crate::report::{Report, StepResult};
+use crate::terminal::summary_text;
+use crate::utils::hostname;
+use crate::webhook::report_json;
+
+/// The subject of the message, with the counts of the results.
+fn subject(report: &Report) -> String {
+    let count = |wanted: fn(&StepResult) -> bool| report.data().iter().filter(|(_, result)| wanted(result)).count();
+    let failed = count(|result| matches!(result, StepResult::Failure(_)));
+    let succeeded = count(|result| matches!(result, StepResult::Success));
+    let host = hostname().unwrap_or_else(|_| String::from("unknown host"));
+    if failed > 0 {
+        format!("Topgrade on {host}: {failed} failed, {succeeded} succeeded")
+    } else {
+        format!("Topgrade on {host}: {succeeded} succeeded")
+    }
+}
+
+fn send(email: &Email, report: &Report, duration: Duration) -> Result<()> {
+    let (content_type, body) = match email.format.unwrap_or(ReportFormat::Text) {
+        ReportFormat::Text => ("text/plain", summary_text(report)),
+        ReportFormat::Json => ("application/json", report_json(report, duration)),
+    };
+    let mut message = NamedTempFile::new()?;
+    write!(
+        message,
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: {content_type}; charset=utf-8\r\n\r\n{}",
+        email.smtp_from,
+        email.smtp_to.join(", "),
+        subject(report),
+        chrono::Local::now().to_rfc2822(),
+        body.replace('\n', "\r\n")
+    )?;
+
+    // Port 465 is SMTP over TLS, the others upgrade the connection with STARTTLS
+    let port = email.smtp_port.unwrap_or(587);
+    let scheme = if port == 465 { "smtps" } else { "smtp" };
+    let mut command = Command::new("curl");
+    command
+        .args(["--silent", "--show-error", "--max-time", "30", "--ssl-reqd"])
+        .arg("--url")
+        .arg(format!("{scheme}://{}:{port}", email.smtp_host))
+        .arg("--mail-from")
+        .arg(&email.smtp_from);
+    for to in &email.smtp_to {
+        command.arg("--mail-rcpt").arg(to);
+    }
+    command.arg("--upload-file").arg(message.path());
+
+    // The credentials go through stdin so that they don't show in the process list
+    let mut child = command
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_checked()?;
+    if let (Some(mut stdin), Some(user)) = (child.stdin.take(), &email.smtp_user) {
+        let password = email.smtp_password.as_deref().unwrap_or_default();
+        writeln!(stdin, "user = {}", curl_config_string(&format!("{user}:{password}")))?;
+    }
+    let output = child.wait_with_output().context("Failed to wait for curl")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(eyre!("{}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Quote `value` for a curl config file, in which a backslash escapes the next character.
+fn curl_config_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Email the summary of the run, when `report` is set in the `[email]` section.
+pub fn send_report(email: &Email, report: &Report, duration: Duration) {
+    debug!("Emailing the report to {:?}", email.smtp_to);
+    if let Err(e) = send(email, report, duration) {
+        warn!("Failed to email the report: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_config_string() {
+        assert_eq!(curl_config_string("me:secret"), r#""me:secret""#);
+        assert_eq!(curl_config_string(r#"me:a\b"c\"#), r#""me:a\\b\"c\\""#);
+    }
+}
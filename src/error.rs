@@ -1,5 +1,8 @@
+use std::fmt::{self, Display};
+use std::io;
 use std::process::ExitStatus;
 
+use color_eyre::eyre;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -27,6 +30,129 @@ pub enum TopgradeError {
 #[error("A step failed")]
 pub struct StepFailed;
 
+/// Why a step failed, shown in the summary to make triaging easier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    BinaryMissing,
+    Network,
+    Permission,
+    LockHeld,
+    NonzeroExit,
+    Timeout,
+    Other,
+}
+
+impl FailureKind {
+    /// Find out why a step failed from its error.
+    ///
+    /// Steps knowing better tag their errors with [`ClassifiedFailure`], the other errors are
+    /// classified from the failed process and its stderr. Most steps run their commands with
+    /// `status_checked`, which doesn't capture the output, so their failures are only told apart by
+    /// the exit code and mostly end up as `nonzero exit`.
+    pub fn classify(error: &eyre::Report) -> Self {
+        if let Some(classified) = error.downcast_ref::<ClassifiedFailure>() {
+            return classified.kind;
+        }
+
+        if let Some(e) = error.chain().find_map(|cause| cause.downcast_ref::<io::Error>()) {
+            match e.kind() {
+                io::ErrorKind::NotFound => return FailureKind::BinaryMissing,
+                io::ErrorKind::PermissionDenied => return FailureKind::Permission,
+                io::ErrorKind::TimedOut => return FailureKind::Timeout,
+                _ => (),
+            }
+        }
+
+        match error.downcast_ref::<TopgradeError>() {
+            Some(TopgradeError::ProcessFailedWithOutput(_, status, stderr)) => {
+                Self::from_output(stderr).unwrap_or_else(|| Self::from_status(*status))
+            }
+            Some(TopgradeError::ProcessFailed(_, status)) => Self::from_status(*status),
+            _ => FailureKind::Other,
+        }
+    }
+
+    fn from_output(output: &str) -> Option<Self> {
+        const PATTERNS: &[(FailureKind, &[&str])] = &[
+            (
+                FailureKind::LockHeld,
+                &[
+                    "could not get lock",
+                    "unable to lock",
+                    "database is locked",
+                    "unable to acquire",
+                    "waiting for cache lock",
+                    "another process is already executing",
+                ],
+            ),
+            (
+                FailureKind::Permission,
+                &[
+                    "permission denied",
+                    "operation not permitted",
+                    "are you root",
+                    "a password is required",
+                    "not in the sudoers file",
+                    "incorrect password",
+                ],
+            ),
+            (FailureKind::Timeout, &["timed out", "timeout"]),
+            (
+                FailureKind::Network,
+                &[
+                    "could not resolve",
+                    "temporary failure in name resolution",
+                    "network is unreachable",
+                    "connection refused",
+                    "connection reset",
+                    "failed to connect",
+                    "could not connect",
+                    "no route to host",
+                    "ssl certificate",
+                    "certificate verify failed",
+                ],
+            ),
+        ];
+
+        let output = output.to_lowercase();
+        PATTERNS
+            .iter()
+            .find(|(_, patterns)| patterns.iter().any(|pattern| output.contains(pattern)))
+            .map(|(kind, _)| *kind)
+    }
+
+    fn from_status(status: ExitStatus) -> Self {
+        // The shell conventions for commands that can't be executed
+        match status.code() {
+            Some(126) => FailureKind::Permission,
+            Some(127) => FailureKind::BinaryMissing,
+            _ => FailureKind::NonzeroExit,
+        }
+    }
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::BinaryMissing => "binary missing",
+            FailureKind::Network => "network",
+            FailureKind::Permission => "permission/sudo",
+            FailureKind::LockHeld => "lock held",
+            FailureKind::NonzeroExit => "nonzero exit",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Other => "other",
+        })
+    }
+}
+
+/// An error whose [`FailureKind`] is known by the step that returned it.
+#[derive(Error, Debug)]
+#[error("{message}")]
+pub struct ClassifiedFailure {
+    pub kind: FailureKind,
+    pub message: String,
+}
+
 #[derive(Error, Debug)]
 #[error("Dry running")]
 pub struct DryRun();
@@ -39,3 +165,39 @@ pub struct SkipStep(pub String);
 #[derive(Error, Debug)]
 #[error("Topgrade Upgraded")]
 pub struct Upgraded(pub ExitStatus);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let missing = eyre::Report::new(io::Error::from(io::ErrorKind::NotFound)).wrap_err("Failed to execute `foo`");
+        assert_eq!(FailureKind::classify(&missing), FailureKind::BinaryMissing);
+
+        let classified = eyre::eyre!("ssh failed").wrap_err(ClassifiedFailure {
+            kind: FailureKind::Network,
+            message: String::from("Failed to run Topgrade in the box"),
+        });
+        assert_eq!(FailureKind::classify(&classified), FailureKind::Network);
+
+        assert_eq!(
+            FailureKind::from_output("E: Could not get lock /var/lib/dpkg/lock-frontend"),
+            Some(FailureKind::LockHeld)
+        );
+        assert_eq!(
+            FailureKind::from_output("curl: (6) Could not resolve host: example.com"),
+            Some(FailureKind::Network)
+        );
+        assert_eq!(
+            FailureKind::from_output("curl: (60) SSL certificate problem: unable to get local issuer certificate"),
+            Some(FailureKind::Network)
+        );
+        assert_eq!(FailureKind::from_output("error: nothing to do"), None);
+        assert_eq!(FailureKind::from_output("error: failed to build libssl3"), None);
+
+        // Only the output of the failed process is looked at, not the messages of the error
+        let other = eyre::eyre!("Connection refused by the user");
+        assert_eq!(FailureKind::classify(&other), FailureKind::Other);
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(dead_code)]
+use crate::config::Step;
 use crate::executor::RunType;
 use crate::sudo::Sudo;
+use crate::terminal::prompt_yesno;
 use crate::utils::{require_option, REQUIRE_SUDO};
 use crate::{config::Config, executor::Executor};
 use color_eyre::eyre::Result;
@@ -49,6 +51,17 @@ impl<'a> ExecutionContext<'a> {
         self.config
     }
 
+    /// Ask the user whether to delete `what`, if `confirm_destructive` is enabled.
+    ///
+    /// Doesn't ask in dry-run mode, or when `--yes` applies to `step`.
+    pub fn confirm_destructive(&self, step: Step, what: &str) -> Result<bool> {
+        if self.run_type.dry() || !self.config.confirm_destructive() || self.config.yes(step) {
+            return Ok(true);
+        }
+
+        Ok(prompt_yesno(&format!("Delete {what}?"))?)
+    }
+
     pub fn under_ssh(&self) -> bool {
         self.under_ssh
     }
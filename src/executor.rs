@@ -1,15 +1,289 @@
 //! Utilities for command execution
 use std::ffi::{OsStr, OsString};
-use std::path::Path;
-use std::process::{Child, Command, ExitStatus, Output};
-
-use color_eyre::eyre::Result;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{Context, Result};
+use once_cell::sync::OnceCell;
 use tracing::debug;
 
-use crate::command::CommandExt;
-use crate::error::DryRun;
+use crate::command::{explained, CommandExt};
+use crate::error::{ClassifiedFailure, DryRun, FailureKind, TopgradeError};
+use crate::terminal::print_warning;
+use crate::utils::which;
+
+/// A command line that is prepended to every unprivileged command created by `RunType::execute`.
+///
+/// This is used to run the commands of all the steps through a wrapper, such as `systemd-run`,
+/// without them having to know about it.
+static COMMAND_WRAPPER: OnceCell<CommandWrapper> = OnceCell::new();
+
+struct CommandWrapper {
+    command: Vec<OsString>,
+    /// The sudo program, whose commands run outside of the wrapper
+    sudo: Option<OsString>,
+}
+
+/// Run every command created by `RunType::execute` through `wrapper`, except the ones run
+/// through `sudo`.
+///
+/// Only the first call has any effect.
+#[cfg(target_os = "linux")]
+pub fn set_command_wrapper(wrapper: Vec<OsString>, sudo: Option<&OsStr>) {
+    debug!("Command wrapper: {:?}", wrapper);
+    COMMAND_WRAPPER
+        .set(CommandWrapper {
+            command: wrapper,
+            sudo: sudo.map(OsStr::to_os_string),
+        })
+        .ok();
+}
+
+/// The output of the commands run in the current step, when it is being captured.
+static CAPTURED_OUTPUT: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Capture the output of the commands run by `Executor::status_checked` instead of printing it,
+/// until `stop_capturing` is called.
+///
+/// Only the output of these commands is captured: the messages of Topgrade and the commands run
+/// otherwise, such as the queries, are still printed. Their stdout and stderr are read from two
+/// pipes, so the captured output only keeps their order as the chunks come in. Captured commands
+/// don't get any stdin.
+pub fn start_capturing() {
+    *CAPTURED_OUTPUT.lock().unwrap() = Some(Vec::new());
+}
+
+/// Stop capturing output, returning what has been captured since `start_capturing`.
+pub fn stop_capturing() -> Option<Vec<u8>> {
+    CAPTURED_OUTPUT.lock().unwrap().take()
+}
+
+/// The file the output of the commands run in the current step is also written to.
+struct StepLog {
+    path: PathBuf,
+    /// Whether to truncate the file, or to append to it, when it is opened.
+    truncate: bool,
+    /// The file, opened when the first command of the step is run.
+    file: Option<File>,
+}
+
+static STEP_LOG: Mutex<Option<StepLog>> = Mutex::new(None);
+
+/// Also write the output of the commands run by `Executor::status_checked` to the file at `path`,
+/// until `stop_step_log` is called.
+///
+/// The file is only created once a command is run, so steps that are skipped leave no log behind.
+pub fn start_step_log(path: PathBuf, truncate: bool) {
+    *STEP_LOG.lock().unwrap() = Some(StepLog {
+        path,
+        truncate,
+        file: None,
+    });
+}
+
+/// Stop writing the output of the commands to the step log, returning whether it was written.
+pub fn stop_step_log() -> bool {
+    STEP_LOG.lock().unwrap().take().is_some_and(|log| log.file.is_some())
+}
+
+/// The step log, opened the first time it is needed.
+fn step_log() -> Option<File> {
+    let mut step_log = STEP_LOG.lock().unwrap();
+    let log = step_log.as_mut()?;
+    if log.file.is_none() {
+        let file = log.path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| {
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(log.truncate)
+                .append(!log.truncate)
+                .open(&log.path)
+        });
+        match file {
+            Ok(file) => log.file = Some(file),
+            Err(e) => {
+                print_warning(format!("Cannot write the step log to {}: {e}", log.path.display()));
+                // Don't try again for every command of the step
+                *step_log = None;
+                return None;
+            }
+        }
+    }
+    log.file.as_ref().and_then(|file| file.try_clone().ok())
+}
+
+/// Set while the commands of the current step should run in a pseudo-terminal, see `needs_tty`.
+static IN_PTY: AtomicBool = AtomicBool::new(false);
+
+/// Run the commands of `Executor::status_checked` in a pseudo-terminal allocated by `script`,
+/// until called again with `false`.
+pub fn set_in_pty(in_pty: bool) {
+    IN_PTY.store(in_pty, Ordering::Relaxed);
+}
+
+/// Tell whether `script` can give the commands a pseudo-terminal on this platform.
+pub fn pty_available() -> bool {
+    cfg!(any(target_os = "linux", target_os = "macos", target_os = "freebsd")) && which("script").is_some()
+}
+
+/// Rebuild `command` to run through `script`, which runs it in a pseudo-terminal.
+fn pty_command(command: &Command) -> Command {
+    let mut wrapped = Command::new("script");
+    if cfg!(target_os = "linux") {
+        // util-linux `script` takes a shell command line, `-e` returns the exit code of the command
+        let command_line = shell_words::join(
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy()),
+        );
+        wrapped.args(["-qefc", &command_line, "/dev/null"]);
+    } else {
+        // The BSD `script` takes the command and its arguments after the output file
+        wrapped
+            .args(["-q", "/dev/null"])
+            .arg(command.get_program())
+            .args(command.get_args());
+    }
+
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => wrapped.env(key, value),
+            None => wrapped.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    wrapped
+}
+
+/// Like `status_checked_with`, but the output also goes to the step log, and to `CAPTURED_OUTPUT` instead
+/// of the terminal if it is being captured.
+fn status_checked_with_capture(command: &mut Command, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> Result<()> {
+    status_checked_with_capture_within(command, None, succeeded)
+}
+
+/// Like `status_checked_with_capture`, but `command` is killed if it doesn't finish within `timeout`.
+fn status_checked_with_capture_within(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    succeeded: impl Fn(ExitStatus) -> Result<(), ()>,
+) -> Result<()> {
+    if explained(command) {
+        return Ok(());
+    }
+
+    let mut wrapped;
+    let command = if IN_PTY.load(Ordering::Relaxed) {
+        wrapped = pty_command(command);
+        &mut wrapped
+    } else {
+        command
+    };
+
+    let log = step_log();
+    if timeout.is_none() && log.is_none() && CAPTURED_OUTPUT.lock().unwrap().is_none() {
+        return command.status_checked_with(succeeded);
+    }
+
+    status_checked_with_tee(command, log, timeout, succeeded)
+}
+
+/// Wait for `child`, killing it if it doesn't exit within `timeout`, in which case `None` is returned.
+fn wait_within(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() > timeout {
+            child.kill().ok();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Copy `from` to `log` if any, and to `to` or `CAPTURED_OUTPUT` if the output is being captured.
+fn tee(mut from: impl Read, mut to: impl Write, mut log: Option<File>) {
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match from.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let chunk = &buffer[..read];
+        if let Some(log) = log.as_mut() {
+            log.write_all(chunk).ok();
+        }
+        match CAPTURED_OUTPUT.lock().unwrap().as_mut() {
+            Some(captured) => captured.extend_from_slice(chunk),
+            None => {
+                to.write_all(chunk).ok();
+                to.flush().ok();
+            }
+        }
+    }
+}
+
+/// Run `command` with its output going through `tee`, to be written to the step `log` or captured.
+fn status_checked_with_tee(
+    command: &mut Command,
+    log: Option<File>,
+    timeout: Option<Duration>,
+    succeeded: impl Fn(ExitStatus) -> Result<(), ()>,
+) -> Result<()> {
+    if CAPTURED_OUTPUT.lock().unwrap().is_some() {
+        command.stdin(Stdio::null());
+    }
+    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn_checked()?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_log = log.as_ref().map(File::try_clone).transpose()?;
+    let stdout_tee = thread::spawn(move || tee(stdout, io::stdout(), log));
+    let stderr_tee = thread::spawn(move || tee(stderr, io::stderr(), stderr_log));
+    let status = match timeout {
+        Some(timeout) => wait_within(&mut child, timeout),
+        None => child.wait().map(Some),
+    };
+    stdout_tee.join().ok();
+    stderr_tee.join().ok();
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let Some(status) = status.with_context(|| format!("Failed to execute `{program}`"))? else {
+        return Err(ClassifiedFailure {
+            kind: FailureKind::Timeout,
+            message: format!(
+                "`{program}` didn't finish within {} seconds",
+                timeout.unwrap_or_default().as_secs()
+            ),
+        }
+        .into());
+    };
+    if succeeded(status).is_ok() {
+        Ok(())
+    } else {
+        Err(TopgradeError::ProcessFailed(program.clone(), status))
+            .with_context(|| format!("Command failed: `{program}`"))
+    }
+}
 
 /// An enum telling whether Topgrade should perform dry runs or actually perform the steps.
+///
+/// Only the commands created by [`RunType::execute`] are dry run. The queries that change nothing,
+/// such as listing the outdated packages, use a plain [`Command`] or `RunType::Wet` so that they run
+/// for real even during a dry run, which can then tell what would be upgraded. Only `--explain`
+/// prints them instead of running them.
 #[derive(Clone, Copy, Debug)]
 pub enum RunType {
     /// Executing commands will just print the command with its argument.
@@ -30,7 +304,25 @@ impl RunType {
     }
 
     /// Create an instance of `Executor` that should run `program`.
+    ///
+    /// If a command wrapper has been set with `set_command_wrapper`, `program` becomes an
+    /// argument of the wrapper, unless it is sudo.
     pub fn execute<S: AsRef<OsStr>>(self, program: S) -> Executor {
+        match COMMAND_WRAPPER
+            .get()
+            .filter(|wrapper| wrapper.sudo.as_deref() != Some(program.as_ref()))
+            .and_then(|wrapper| wrapper.command.split_first())
+        {
+            Some((wrapper, wrapper_args)) => {
+                let mut executor = self.execute_unwrapped(wrapper);
+                executor.args(wrapper_args).arg(program);
+                executor
+            }
+            None => self.execute_unwrapped(program),
+        }
+    }
+
+    fn execute_unwrapped<S: AsRef<OsStr>>(self, program: S) -> Executor {
         match self {
             RunType::Dry => Executor::Dry(DryCommand {
                 program: program.as_ref().into(),
@@ -123,7 +415,7 @@ impl Executor {
             Executor::Wet(c) => {
                 c.env_remove(key);
             }
-            Executor::Dry(_) => (),
+            Executor::Dry(c) => c.env.push((key.as_ref().into(), None)),
         }
 
         self
@@ -140,7 +432,7 @@ impl Executor {
             Executor::Wet(c) => {
                 c.env(key, val);
             }
-            Executor::Dry(_) => (),
+            Executor::Dry(c) => c.env.push((key.as_ref().into(), Some(val.as_ref().into()))),
         }
 
         self
@@ -149,6 +441,7 @@ impl Executor {
     /// See `std::process::Command::spawn`
     pub fn spawn(&mut self) -> Result<ExecutorChild> {
         let result = match self {
+            Executor::Wet(c) if explained(c) => ExecutorChild::Dry,
             Executor::Wet(c) => {
                 debug!("Running {:?}", c);
                 c.spawn_checked().map(ExecutorChild::Wet)?
@@ -173,12 +466,35 @@ impl Executor {
         }
     }
 
+    /// Like `status_checked`, but the command is killed if it doesn't finish within `timeout`.
+    pub fn status_checked_within(&mut self, timeout: Duration) -> Result<()> {
+        match self {
+            Executor::Wet(c) => {
+                status_checked_with_capture_within(
+                    c,
+                    Some(timeout),
+                    |status| {
+                        if status.success() {
+                            Ok(())
+                        } else {
+                            Err(())
+                        }
+                    },
+                )
+            }
+            Executor::Dry(c) => {
+                c.dry_run();
+                Ok(())
+            }
+        }
+    }
+
     /// An extension of `status_checked` that allows you to set a sequence of codes
     /// that can indicate success of a script
     #[allow(dead_code)]
     pub fn status_checked_with_codes(&mut self, codes: &[i32]) -> Result<()> {
         match self {
-            Executor::Wet(c) => c.status_checked_with(|status| {
+            Executor::Wet(c) => status_checked_with_capture(c, |status| {
                 if status.success() || status.code().as_ref().map(|c| codes.contains(c)).unwrap_or(false) {
                     Ok(())
                 } else {
@@ -204,6 +520,8 @@ pub struct DryCommand {
     program: OsString,
     args: Vec<OsString>,
     directory: Option<OsString>,
+    /// The environment overrides, `None` removing the variable.
+    env: Vec<(OsString, Option<OsString>)>,
 }
 
 impl DryCommand {
@@ -218,10 +536,21 @@ impl DryCommand {
                     .collect::<Vec<String>>()
             )
         );
-        match &self.directory {
-            Some(dir) => println!(" in {}", dir.to_string_lossy()),
-            None => println!(),
-        };
+        if let Some(dir) = &self.directory {
+            print!(" in {}", dir.to_string_lossy());
+        }
+        if !self.env.is_empty() {
+            let env: Vec<String> = self
+                .env
+                .iter()
+                .map(|(key, val)| match val {
+                    Some(val) => format!("{}={}", key.to_string_lossy(), val.to_string_lossy()),
+                    None => format!("without {}", key.to_string_lossy()),
+                })
+                .collect();
+            print!(" with {}", env.join(", "));
+        }
+        println!();
     }
 }
 
@@ -250,7 +579,7 @@ impl CommandExt for Executor {
 
     fn status_checked_with(&mut self, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> Result<()> {
         match self {
-            Executor::Wet(c) => c.status_checked_with(succeeded),
+            Executor::Wet(c) => status_checked_with_capture(c, succeeded),
             Executor::Dry(c) => {
                 c.dry_run();
                 Ok(())
@@ -262,3 +591,50 @@ impl CommandExt for Executor {
         self.spawn()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<&OsStr> {
+        command.get_args().collect()
+    }
+
+    #[test]
+    fn test_pty_command() {
+        let mut command = Command::new("apt-get");
+        command
+            .args(["install", "a package"])
+            .env("DEBIAN_FRONTEND", "noninteractive")
+            .env_remove("LANG")
+            .current_dir("/tmp");
+        let wrapped = pty_command(&command);
+
+        assert_eq!(wrapped.get_program(), "script");
+        if cfg!(target_os = "linux") {
+            assert_eq!(args(&wrapped), ["-qefc", "apt-get install 'a package'", "/dev/null"]);
+        } else {
+            assert_eq!(args(&wrapped), ["-q", "/dev/null", "apt-get", "install", "a package"]);
+        }
+        assert_eq!(
+            wrapped.get_envs().collect::<Vec<_>>(),
+            [
+                (OsStr::new("DEBIAN_FRONTEND"), Some(OsStr::new("noninteractive"))),
+                (OsStr::new("LANG"), None)
+            ]
+        );
+        assert_eq!(wrapped.get_current_dir(), Some(Path::new("/tmp")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_explaining() {
+        crate::command::set_explaining();
+
+        // The explained step gets past its queries to the commands changing the system, which are
+        // printed instead of being run
+        assert!(Command::new("echo").arg("query").output_checked().is_ok());
+        assert!(RunType::Wet.execute("false").status_checked().is_ok());
+        assert!(matches!(RunType::Wet.execute("false").spawn(), Ok(ExecutorChild::Dry)));
+    }
+}
@@ -2,13 +2,13 @@
 
 use std::env;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::time::Duration;
 
 use crate::breaking_changes::{first_run_of_major_release, print_breaking_changes, should_skip, write_keep_file};
-use clap::CommandFactory;
 use clap::{crate_version, Parser};
+use clap::{CommandFactory, ValueEnum};
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use console::Key;
@@ -20,10 +20,10 @@ use etcetera::base_strategy::Xdg;
 use once_cell::sync::Lazy;
 use tracing::debug;
 
-use self::config::{CommandLineArgs, Config, Step};
-use self::error::StepFailed;
+use self::config::{CommandLineArgs, Config, ReportFormat, Step};
 #[cfg(all(windows, feature = "self-update"))]
 use self::error::Upgraded;
+use self::error::{SkipStep, StepFailed};
 use self::steps::{remote::*, *};
 use self::terminal::*;
 
@@ -33,11 +33,15 @@ mod breaking_changes;
 mod command;
 mod config;
 mod ctrlc;
+mod doctor;
+mod email;
 mod error;
 mod execution_context;
 mod executor;
+mod power;
 mod report;
 mod runner;
+mod schedule;
 #[cfg(windows)]
 mod self_renamer;
 #[cfg(feature = "self-update")]
@@ -46,6 +50,7 @@ mod steps;
 mod sudo;
 mod terminal;
 mod utils;
+mod webhook;
 
 pub(crate) static HOME_DIR: Lazy<PathBuf> = Lazy::new(|| home::home_dir().expect("No home directory"));
 #[cfg(unix)]
@@ -54,6 +59,49 @@ pub(crate) static XDG_DIRS: Lazy<Xdg> = Lazy::new(|| Xdg::new().expect("No home
 #[cfg(windows)]
 pub(crate) static WINDOWS_DIRS: Lazy<Windows> = Lazy::new(|| Windows::new().expect("No home directory"));
 
+/// Print the version, and with `verbose` what this build supports, for bug reports.
+fn print_version(verbose: bool) {
+    println!("Topgrade {}", crate_version!());
+    if !verbose {
+        return;
+    }
+
+    println!("Platform: {} {}", env::consts::OS, env::consts::ARCH);
+    let features: Vec<&str> = [("self-update", cfg!(feature = "self-update"))]
+        .into_iter()
+        .filter_map(|(feature, enabled)| enabled.then_some(feature))
+        .collect();
+    if features.is_empty() {
+        println!("Features: none");
+    } else {
+        println!("Features: {}", features.join(", "));
+    }
+    // The messages aren't translated
+    println!("Locales: en");
+    println!("Steps:");
+    for step in Step::value_variants() {
+        let name = step.to_possible_value().unwrap();
+        match step.default_tags() {
+            [] => println!("  {}", name.get_name()),
+            tags => println!("  {} ({})", name.get_name(), tags.join(", ")),
+        }
+    }
+}
+
+/// Pull the editor configuration repositories of `step`, right before its plugins are updated.
+fn pull_editor_config_repos(
+    runner: &mut runner::Runner,
+    ctx: &execution_context::ExecutionContext,
+    step: Step,
+) -> Result<()> {
+    for repo in ctx.config().editor_config_repos(step) {
+        runner.execute(step, format!("Editor config ({})", repo.path), || {
+            git::run_editor_config_pull(ctx, &repo.path)
+        })?;
+    }
+    Ok(())
+}
+
 fn run() -> Result<()> {
     install_color_eyre()?;
     ctrlc::set_handler();
@@ -72,6 +120,11 @@ fn run() -> Result<()> {
     // and `Config::tracing_filter_directives()`.
     let reload_handle = install_tracing(&opt.tracing_filter_directives())?;
 
+    if opt.version {
+        print_version(opt.verbose);
+        return Ok(());
+    }
+
     if let Some(shell) = opt.gen_completion {
         let cmd = &mut CommandLineArgs::command();
         clap_complete::generate(shell, cmd, clap::crate_name!(), &mut io::stdout());
@@ -106,6 +159,12 @@ fn run() -> Result<()> {
     update_tracing(&reload_handle, &config.tracing_filter_directives())?;
     set_title(config.set_title());
     display_time(config.display_time());
+    set_separator_style(
+        config.separator_char(),
+        config.separator_width(),
+        config.separator_full_width(),
+    );
+    set_colors(config.color_theme(), config.colors());
     set_desktop_notifications(config.notify_each_step());
 
     debug!("Version: {}", crate_version!());
@@ -115,6 +174,18 @@ fn run() -> Result<()> {
     debug!("self-update Feature Enabled: {:?}", cfg!(feature = "self-update"));
     debug!("Configuration: {:?}", config);
 
+    if config.doctor() {
+        return doctor::run(&config);
+    }
+
+    if config.schedule() {
+        return schedule::schedule(&config);
+    }
+
+    if config.unschedule() {
+        return schedule::unschedule();
+    }
+
     if config.run_in_tmux() && env::var("TOPGRADE_INSIDE_TMUX").is_err() {
         #[cfg(unix)]
         {
@@ -130,17 +201,34 @@ fn run() -> Result<()> {
     let distribution = linux::Distribution::detect();
 
     let sudo = config.sudo_command().map_or_else(sudo::Sudo::detect, sudo::Sudo::new);
+    #[cfg(target_os = "linux")]
+    if let Some(wrapper) = linux::resource_limits_wrapper(&config) {
+        executor::set_command_wrapper(wrapper, sudo.as_ref().map(AsRef::as_ref));
+    }
     let run_type = executor::RunType::new(config.dry_run());
     let ctx = execution_context::ExecutionContext::new(run_type, sudo, &config);
     let mut runner = runner::Runner::new(&ctx);
 
+    if let Some(step) = config.explain() {
+        command::set_explaining();
+        print_info(format!(
+            "Explaining {}, its commands are printed but not executed",
+            step.to_possible_value().unwrap().get_name()
+        ));
+        match ctx.sudo() {
+            Some(sudo) => println!("Commands needing root run through {}", Path::new(sudo).display()),
+            None => println!("No sudo was found, parts needing root will be skipped"),
+        }
+    }
+
     // If
     //
     // 1. the breaking changes notification shouldnot be skipped
     // 2. this is the first execution of a major release
+    // 3. no step is being explained
     //
     // inform user of breaking changes
-    if !should_skip() && first_run_of_major_release()? {
+    if !should_skip(&config) && config.explain().is_none() && first_run_of_major_release()? {
         print_breaking_changes();
 
         if prompt_yesno("Confirmed?")? {
@@ -155,7 +243,10 @@ fn run() -> Result<()> {
     // 2. it is not disabled from configuration (env var/CLI opt/file)
     #[cfg(feature = "self-update")]
     {
-        let should_self_update = env::var("TOPGRADE_NO_SELF_UPGRADE").is_err() && !config.no_self_update();
+        // Explaining another step must not replace the binary
+        let should_self_update = env::var("TOPGRADE_NO_SELF_UPGRADE").is_err()
+            && !config.no_self_update()
+            && config.explain().is_none_or(|step| step == Step::SelfUpdate);
 
         if should_self_update {
             runner.execute(Step::SelfUpdate, "Self Update", || self_update::self_update(&ctx))?;
@@ -169,7 +260,20 @@ fn run() -> Result<()> {
         None
     };
 
-    if let Some(commands) = config.pre_commands() {
+    if config.explain().is_none() {
+        runner.check_until();
+        if config.should_run(Step::Vagrant) {
+            vagrant::recover_interrupted_run(&ctx)?;
+        }
+
+        if let Some(step) = runner::interrupted_step().filter(|_| config.resume_from().is_none()) {
+            print_warning(format!(
+                "The previous run was interrupted during the `{step}` step, `--resume-from {step}` continues from there"
+            ));
+        }
+    }
+
+    if let Some(commands) = config.pre_commands().as_ref().filter(|_| config.explain().is_none()) {
         for (name, command) in commands {
             generic::run_custom_command(name, command, &ctx)?;
         }
@@ -181,6 +285,10 @@ fn run() -> Result<()> {
         }
     }
 
+    runner.execute(Step::CloudAuth, "Cloud authentication", || {
+        generic::run_cloud_auth_refresh(&ctx)
+    })?;
+
     if let Some(topgrades) = config.remote_topgrades() {
         for remote_topgrade in topgrades.iter().filter(|t| config.should_execute_remote(hostname(), t)) {
             runner.execute(Step::Remotes, format!("Remote ({remote_topgrade})"), || {
@@ -196,7 +304,11 @@ fn run() -> Result<()> {
         runner.execute(Step::Chocolatey, "Chocolatey", || windows::run_chocolatey(&ctx))?;
         runner.execute(Step::Scoop, "Scoop", || windows::run_scoop(&ctx))?;
         runner.execute(Step::Winget, "Winget", || windows::run_winget(&ctx))?;
+        runner.execute(Step::VisualStudio, "Visual Studio", || {
+            windows::run_visual_studio_update(&ctx)
+        })?;
         runner.execute(Step::System, "Windows update", || windows::windows_update(&ctx))?;
+        runner.execute(Step::Drivers, "Driver updates", || windows::windows_driver_update(&ctx))?;
     }
 
     #[cfg(target_os = "linux")]
@@ -205,6 +317,9 @@ fn run() -> Result<()> {
         // by other package managers.
         runner.execute(Step::Shell, "packer.nu", || linux::run_packer_nu(&ctx))?;
 
+        runner.execute(Step::Etckeeper, "etckeeper (pre)", || {
+            linux::run_etckeeper_commit(&ctx, "pre-topgrade")
+        })?;
         match &distribution {
             Ok(distribution) => {
                 runner.execute(Step::System, "System update", || distribution.upgrade(&ctx))?;
@@ -213,12 +328,29 @@ fn run() -> Result<()> {
                 println!("Error detecting current distribution: {e}");
             }
         }
+        runner.execute(Step::Aur, "AUR", || linux::run_aur_update(&ctx))?;
+        runner.execute(Step::System, "pacman check", || linux::run_pacman_check(&ctx))?;
         runner.execute(Step::ConfigUpdate, "config-update", || linux::run_config_update(&ctx))?;
 
         runner.execute(Step::AM, "am", || linux::run_am(&ctx))?;
         runner.execute(Step::AppMan, "appman", || linux::run_appman(&ctx))?;
         runner.execute(Step::DebGet, "deb-get", || linux::run_deb_get(&ctx))?;
-        runner.execute(Step::Toolbx, "toolbx", || toolbx::run_toolbx(&ctx))?;
+        if let Some(sources) = config.deb_sources() {
+            for (package, command) in sources {
+                runner.execute(Step::DebSources, format!("deb source ({package})"), || {
+                    linux::run_deb_source(&ctx, package, command)
+                })?;
+            }
+        }
+        if config.should_run(Step::Toolbx) {
+            if let Ok(toolboxes) = toolbx::collect_toolboxes() {
+                for toolbox in toolboxes {
+                    runner.execute(Step::Toolbx, format!("Toolbx ({})", toolbox.name()), || {
+                        toolbx::topgrade_toolbox(&ctx, &toolbox)
+                    })?;
+                }
+            }
+        }
         runner.execute(Step::Snap, "snap", || linux::run_snap(&ctx))?;
         runner.execute(Step::Pacstall, "pacstall", || linux::run_pacstall(&ctx))?;
         runner.execute(Step::Pacdef, "pacdef", || linux::run_pacdef(&ctx))?;
@@ -226,10 +358,19 @@ fn run() -> Result<()> {
         runner.execute(Step::Distrobox, "distrobox", || linux::run_distrobox_update(&ctx))?;
         runner.execute(Step::DkpPacman, "dkp-pacman", || linux::run_dkp_pacman_update(&ctx))?;
         runner.execute(Step::System, "pihole", || linux::run_pihole_update(&ctx))?;
+        runner.execute(Step::UbuntuReleaseUpgrade, "Ubuntu release upgrade", || {
+            linux::run_ubuntu_release_upgrade(&ctx)
+        })?;
+        runner.execute(Step::Etckeeper, "etckeeper (post)", || {
+            linux::run_etckeeper_commit(&ctx, "post-topgrade")
+        })?;
         runner.execute(Step::Firmware, "Firmware upgrades", || linux::run_fwupdmgr(&ctx))?;
+        runner.execute(Step::Bootloader, "Bootloader", || linux::run_bootloader_update(&ctx))?;
         runner.execute(Step::Restarts, "Restarts", || linux::run_needrestart(&ctx))?;
+        runner.execute(Step::Restarts, "Restart audit", || linux::run_restart_audit(&ctx))?;
 
         runner.execute(Step::Flatpak, "Flatpak", || linux::run_flatpak(&ctx))?;
+        runner.execute(Step::Flatpak, "Flatpak repair", || linux::run_flatpak_repair(&ctx))?;
         runner.execute(Step::BrewFormula, "Brew", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::Path)
         })?;
@@ -259,6 +400,7 @@ fn run() -> Result<()> {
             unix::run_brew_cask(&ctx, unix::BrewVariant::Path)
         })?;
         runner.execute(Step::Macports, "MacPorts", || macos::run_macports(&ctx))?;
+        runner.execute(Step::NixDarwin, "nix-darwin", || macos::run_nix_darwin(&ctx))?;
         runner.execute(Step::Xcodes, "Xcodes", || macos::update_xcodes(&ctx))?;
         runner.execute(Step::Sparkle, "Sparkle", || macos::run_sparkle(&ctx))?;
         runner.execute(Step::Mas, "App Store", || macos::run_mas(&ctx))?;
@@ -283,6 +425,8 @@ fn run() -> Result<()> {
     #[cfg(target_os = "openbsd")]
     {
         runner.execute(Step::Pkg, "OpenBSD Packages", || openbsd::upgrade_packages(&ctx))?;
+        runner.execute(Step::Firmware, "OpenBSD Firmware", || openbsd::run_fw_update(&ctx))?;
+        runner.execute(Step::System, "OpenBSD Patches", || openbsd::run_syspatch(&ctx))?;
         runner.execute(Step::System, "OpenBSD Upgrade", || openbsd::upgrade_openbsd(&ctx))?;
     }
 
@@ -293,9 +437,16 @@ fn run() -> Result<()> {
 
     #[cfg(unix)]
     {
+        runner.execute(Step::Mandb, "mandb", || unix::run_mandb(&ctx))?;
+        runner.execute(Step::Locatedb, "locate database", || unix::run_locatedb(&ctx))?;
         runner.execute(Step::Yadm, "yadm", || unix::run_yadm(&ctx))?;
         runner.execute(Step::Nix, "nix", || unix::run_nix(&ctx))?;
         runner.execute(Step::Nix, "nix upgrade-nix", || unix::run_nix_self_upgrade(&ctx))?;
+        for dir in &config.direnv_dirs() {
+            runner.execute(Step::Direnv, format!("direnv ({})", dir.display()), || {
+                unix::run_direnv_warmup(&ctx, dir)
+            })?;
+        }
         runner.execute(Step::Guix, "guix", || unix::run_guix(&ctx))?;
         runner.execute(Step::HomeManager, "home-manager", || unix::run_home_manager(&ctx))?;
         runner.execute(Step::Asdf, "asdf", || unix::run_asdf(&ctx))?;
@@ -320,6 +471,7 @@ fn run() -> Result<()> {
         runner.execute(Step::Shell, "fish-plug", || unix::run_fish_plug(&ctx))?;
         runner.execute(Step::Shell, "fundle", || unix::run_fundle(&ctx))?;
         runner.execute(Step::Tmux, "tmux", || tmux::run_tpm(&ctx))?;
+        runner.execute(Step::Tfenv, "tfenv", || unix::run_tfenv(&ctx))?;
         runner.execute(Step::Tldr, "TLDR", || unix::run_tldr(&ctx))?;
         runner.execute(Step::Pearl, "pearl", || unix::run_pearl(&ctx))?;
         #[cfg(not(any(target_os = "macos", target_os = "android")))]
@@ -346,25 +498,55 @@ fn run() -> Result<()> {
     runner.execute(Step::Fossil, "fossil", || generic::run_fossil(&ctx))?;
     runner.execute(Step::Elan, "elan", || generic::run_elan(&ctx))?;
     runner.execute(Step::Rye, "rye", || generic::run_rye(&ctx))?;
+    runner.execute(Step::Pixi, "pixi", || generic::run_pixi(&ctx))?;
+    runner.execute(Step::Restic, "restic", || generic::run_restic(&ctx))?;
+    runner.execute(Step::Just, "just", || generic::run_just_update(&ctx))?;
     runner.execute(Step::Rustup, "rustup", || generic::run_rustup(&ctx))?;
     runner.execute(Step::Juliaup, "juliaup", || generic::run_juliaup(&ctx))?;
     runner.execute(Step::Dotnet, ".NET", || generic::run_dotnet_upgrade(&ctx))?;
     runner.execute(Step::Choosenim, "choosenim", || generic::run_choosenim(&ctx))?;
     runner.execute(Step::Cargo, "cargo", || generic::run_cargo_update(&ctx))?;
+    runner.execute(Step::CargoTools, "Cargo tools", || generic::run_cargo_tools(&ctx))?;
+    runner.execute(Step::CargoCompanions, "Cargo companions", || {
+        generic::run_cargo_companions(&ctx)
+    })?;
+    runner.execute(Step::CargoProjects, "Cargo projects", || {
+        generic::run_cargo_projects_update(&ctx)
+    })?;
     runner.execute(Step::Flutter, "Flutter", || generic::run_flutter_upgrade(&ctx))?;
     runner.execute(Step::Go, "go-global-update", || go::run_go_global_update(&ctx))?;
     runner.execute(Step::Go, "gup", || go::run_go_gup(&ctx))?;
+    runner.execute(Step::Go, "go install", || go::run_go_install_update(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Emacs)?;
     runner.execute(Step::Emacs, "Emacs", || emacs.upgrade(&ctx))?;
     runner.execute(Step::Opam, "opam", || generic::run_opam_update(&ctx))?;
     runner.execute(Step::Vcpkg, "vcpkg", || generic::run_vcpkg_update(&ctx))?;
+    runner.execute(Step::Conan, "Conan", || generic::run_conan(&ctx))?;
+    runner.execute(Step::Spack, "Spack", || generic::run_spack(&ctx))?;
     runner.execute(Step::Pipx, "pipx", || generic::run_pipx_update(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Vscode)?;
     runner.execute(Step::Vscode, "Visual Studio Code extensions", || {
         generic::run_vscode_extensions_update(&ctx)
     })?;
     runner.execute(Step::Conda, "conda", || generic::run_conda_update(&ctx))?;
     runner.execute(Step::Mamba, "mamba", || generic::run_mamba_update(&ctx))?;
+    runner.execute(Step::Micromamba, "micromamba", || generic::run_micromamba_update(&ctx))?;
     runner.execute(Step::Miktex, "miktex", || generic::run_miktex_packages_update(&ctx))?;
     runner.execute(Step::Pip3, "pip3", || generic::run_pip3_update(&ctx))?;
+    let venv_roots = config.python_venv_roots();
+    if !venv_roots.is_empty() && config.should_run(Step::PythonVenvs) {
+        let venvs = generic::python_venvs(&venv_roots);
+        if venvs.is_empty() {
+            runner.execute(Step::PythonVenvs, "Python venvs", || {
+                Err(SkipStep(String::from("No virtualenv found under `venv_roots`")).into())
+            })?;
+        }
+        for venv in &venvs {
+            runner.execute(Step::PythonVenvs, format!("Python venv ({})", venv.display()), || {
+                generic::run_python_venv_update(&ctx, venv)
+            })?;
+        }
+    }
     runner.execute(Step::PipReview, "pip-review", || generic::run_pip_review_update(&ctx))?;
     runner.execute(Step::PipReviewLocal, "pip-review (local)", || {
         generic::run_pip_review_local_update(&ctx)
@@ -372,33 +554,70 @@ fn run() -> Result<()> {
     runner.execute(Step::Pipupgrade, "pipupgrade", || generic::run_pipupgrade_update(&ctx))?;
     runner.execute(Step::Ghcup, "ghcup", || generic::run_ghcup_update(&ctx))?;
     runner.execute(Step::Stack, "stack", || generic::run_stack_update(&ctx))?;
+    runner.execute(Step::Cabal, "cabal", || generic::run_cabal_update(&ctx))?;
     runner.execute(Step::Tlmgr, "tlmgr", || generic::run_tlmgr_update(&ctx))?;
     runner.execute(Step::Myrepos, "myrepos", || generic::run_myrepos_update(&ctx))?;
     runner.execute(Step::Chezmoi, "chezmoi", || generic::run_chezmoi_update(&ctx))?;
+    #[cfg(unix)]
+    runner.execute(Step::Stow, "stow", || generic::run_stow(&ctx))?;
     runner.execute(Step::Jetpack, "jetpack", || generic::run_jetpack(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Vim)?;
     runner.execute(Step::Vim, "vim", || vim::upgrade_vim(&ctx))?;
     runner.execute(Step::Vim, "Neovim", || vim::upgrade_neovim(&ctx))?;
     runner.execute(Step::Vim, "The Ultimate vimrc", || vim::upgrade_ultimate_vimrc(&ctx))?;
     runner.execute(Step::Vim, "voom", || vim::run_voom(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Kakoune)?;
     runner.execute(Step::Kakoune, "Kakoune", || kakoune::upgrade_kak_plug(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Helix)?;
     runner.execute(Step::Helix, "helix", || generic::run_helix_grammars(&ctx))?;
+    runner.execute(Step::Bat, "bat", || generic::run_bat_cache_build(&ctx))?;
+    runner.execute(Step::Pkgx, "pkgx", || generic::run_pkgx_update(&ctx))?;
+    runner.execute(Step::Ollama, "ollama", || generic::run_ollama_pull(&ctx))?;
+    runner.execute(Step::NodeVersionManager, "Node", || {
+        node::run_node_version_manager(&ctx)
+    })?;
     runner.execute(Step::Node, "npm", || node::run_npm_upgrade(&ctx))?;
     runner.execute(Step::Yarn, "yarn", || node::run_yarn_upgrade(&ctx))?;
     runner.execute(Step::Pnpm, "pnpm", || node::run_pnpm_upgrade(&ctx))?;
     runner.execute(Step::Containers, "Containers", || containers::run_containers(&ctx))?;
+    runner.execute(Step::Buildx, "Buildx", || containers::run_buildx_prune(&ctx))?;
+    for project in &config.compose_projects() {
+        runner.execute(Step::Compose, format!("Compose ({})", project.display()), || {
+            containers::run_compose_project(&ctx, project)
+        })?;
+    }
     runner.execute(Step::Deno, "deno", || node::deno_upgrade(&ctx))?;
     runner.execute(Step::Composer, "composer", || generic::run_composer_update(&ctx))?;
     runner.execute(Step::Krew, "krew", || generic::run_krew_upgrade(&ctx))?;
+    runner.execute(Step::AnsibleGalaxy, "ansible-galaxy", || {
+        generic::run_ansible_galaxy(&ctx)
+    })?;
+    for manifests in config.kubectl_manifests() {
+        runner.execute(
+            Step::KubectlManifests,
+            format!("kubectl manifests ({})", manifests.path),
+            || generic::run_kubectl_manifests(&ctx, manifests),
+        )?;
+    }
+    for project in &config.terraform_projects() {
+        runner.execute(
+            Step::TerraformProjects,
+            format!("Terraform providers ({})", project.display()),
+            || generic::run_terraform_project(&ctx, project),
+        )?;
+    }
     runner.execute(Step::Helm, "helm", || generic::run_helm_repo_update(&ctx))?;
     runner.execute(Step::Gem, "gem", || generic::run_gem(&ctx))?;
     runner.execute(Step::RubyGems, "rubygems", || generic::run_rubygems(&ctx))?;
     runner.execute(Step::Julia, "julia", || generic::update_julia_packages(&ctx))?;
     runner.execute(Step::Haxelib, "haxelib", || generic::run_haxelib_update(&ctx))?;
     runner.execute(Step::Sheldon, "sheldon", || generic::run_sheldon(&ctx))?;
+    runner.execute(Step::Zellij, "zellij plugins", || generic::run_zellij_plugins(&ctx))?;
     runner.execute(Step::Stew, "stew", || generic::run_stew(&ctx))?;
     runner.execute(Step::Rtcl, "rtcl", || generic::run_rtcl(&ctx))?;
     runner.execute(Step::Bin, "bin", || generic::bin_update(&ctx))?;
     runner.execute(Step::Gcloud, "gcloud", || generic::run_gcloud_components_update(&ctx))?;
+    pull_editor_config_repos(&mut runner, &ctx, Step::Micro)?;
     runner.execute(Step::Micro, "micro", || generic::run_micro(&ctx))?;
     runner.execute(Step::Raco, "raco", || generic::run_raco_update(&ctx))?;
     runner.execute(Step::Spicetify, "spicetify", || generic::spicetify_upgrade(&ctx))?;
@@ -407,7 +626,15 @@ fn run() -> Result<()> {
     })?;
     runner.execute(Step::Bob, "Bob", || generic::run_bob(&ctx))?;
     runner.execute(Step::Certbot, "Certbot", || generic::run_certbot(&ctx))?;
+    runner.execute(Step::PasswordManagers, "Password managers", || {
+        generic::run_password_managers_sync(&ctx)
+    })?;
     runner.execute(Step::GitRepos, "Git Repositories", || git::run_git_pull(&ctx))?;
+    for build in config.git_builds() {
+        runner.execute(Step::GitBuilds, format!("Git build ({})", build.path), || {
+            git::run_git_build(&ctx, build)
+        })?;
+    }
     runner.execute(Step::ClamAvDb, "ClamAV Databases", || generic::run_freshclam(&ctx))?;
     runner.execute(Step::PlatformioCore, "PlatformIO Core", || {
         generic::run_platform_io(&ctx)
@@ -429,6 +656,67 @@ fn run() -> Result<()> {
         }
     }
 
+    for plugin in config.plugin_updates() {
+        runner.execute(Step::PluginUpdates, plugin.name.as_str(), || {
+            generic::run_plugin_update(plugin, &ctx)
+        })?;
+    }
+
+    for tool in config.self_updating_tools() {
+        runner.execute(Step::SelfUpdatingTools, tool.binary.as_str(), || {
+            generic::run_self_updating_tool(tool, &ctx)
+        })?;
+    }
+
+    for (binary, update_args) in generic::BUILTIN_SELF_UPDATING_TOOLS {
+        // A tool configured in `[[self_updating_tools]]` is updated the way the user wants
+        let configured = config.self_updating_tools().iter().any(|tool| tool.binary == *binary);
+        if configured
+            || config
+                .self_updating_tools_ignore()
+                .iter()
+                .any(|ignored| ignored == binary)
+        {
+            continue;
+        }
+        runner.execute(Step::SelfUpdatingTools, *binary, || {
+            generic::run_builtin_self_updating_tool(binary, update_args, &ctx)
+        })?;
+    }
+
+    for tool in config.installer_script_tools() {
+        runner.execute(Step::InstallerScripts, tool.tool.as_str(), || {
+            generic::run_installer_script(&tool.tool, &tool.script, &tool.args, &ctx)
+        })?;
+    }
+
+    for (tool, script, args) in generic::BUILTIN_INSTALLER_SCRIPT_TOOLS {
+        // A tool configured in `[[installer_script_tools]]` is updated with the script the user wants
+        let configured = config.installer_script_tools().iter().any(|t| t.tool == *tool);
+        if configured
+            || config
+                .installer_script_tools_ignore()
+                .iter()
+                .any(|ignored| ignored == tool)
+        {
+            continue;
+        }
+        runner.execute(Step::InstallerScripts, *tool, || {
+            generic::run_installer_script(tool, script, args, &ctx)
+        })?;
+    }
+
+    for update in config.cargo_subcommand_updates() {
+        runner.execute(Step::CargoSubcommands, format!("cargo {}", update.subcommand), || {
+            generic::run_cargo_subcommand_update(update, &ctx)
+        })?;
+    }
+
+    runner.execute(Step::Completions, "Completions", || {
+        generic::run_regenerate_completions(&ctx)
+    })?;
+    runner.execute(Step::CacheRefresh, "Cache refresh", || generic::run_cache_refresh(&ctx))?;
+
     if config.should_run(Step::Vagrant) {
         if let Ok(boxes) = vagrant::collect_boxes(&ctx) {
             for vagrant_box in boxes {
@@ -440,12 +728,25 @@ fn run() -> Result<()> {
     }
     runner.execute(Step::Vagrant, "Vagrant boxes", || vagrant::upgrade_vagrant_boxes(&ctx))?;
 
-    if !runner.report().data().is_empty() {
-        print_separator("Summary");
-
+    if config.explain().is_some() {
         for (key, result) in runner.report().data() {
-            print_result(key, result);
+            if let report::StepResult::Skipped(reason) = result {
+                println!("{key} would be skipped: {reason}");
+            }
         }
+        return Ok(());
+    }
+    runner::clear_interrupted_step();
+
+    if config.summary_format() == ReportFormat::Json {
+        println!("{}", webhook::report_json(runner.report(), runner.elapsed()));
+    } else if !runner.report().data().is_empty() {
+        show_summary(
+            runner.report(),
+            config.summary_output(),
+            config.summary_file().as_deref(),
+            config.summary_collapse_success(),
+        );
 
         #[cfg(target_os = "linux")]
         {
@@ -455,6 +756,14 @@ fn run() -> Result<()> {
         }
     }
 
+    if let Some(url) = config.webhook_url() {
+        webhook::send_run_complete(url, runner.report(), runner.elapsed());
+    }
+
+    if let Some(email) = config.email_report() {
+        email::send_report(email, runner.report(), runner.elapsed());
+    }
+
     let mut post_command_failed = false;
     if let Some(commands) = config.post_commands() {
         for (name, command) in commands {
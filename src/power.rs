@@ -0,0 +1,115 @@
+//! Detection of whether the system is running on battery, or busy.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", windows))]
+use std::process::Command;
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", windows))]
+use crate::command::CommandExt;
+use tracing::debug;
+
+/// Tell whether the system is running on battery.
+///
+/// Systems without a battery, and systems whose power state cannot be read, are considered to be
+/// plugged in.
+pub fn on_battery() -> bool {
+    let on_battery = platform_on_battery();
+    debug!("On battery: {on_battery}");
+    on_battery
+}
+
+#[cfg(target_os = "linux")]
+fn platform_on_battery() -> bool {
+    use std::fs;
+    use std::path::Path;
+
+    let read = |supply: &Path, attribute: &str| {
+        fs::read_to_string(supply.join(attribute))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let supply = supply.path();
+        match read(&supply, "type").as_str() {
+            "Mains" if read(&supply, "online") == "1" => return false,
+            "Battery" if read(&supply, "status") == "Discharging" => discharging = true,
+            _ => (),
+        }
+    }
+
+    discharging
+}
+
+#[cfg(target_os = "macos")]
+fn platform_on_battery() -> bool {
+    // Now drawing from 'Battery Power'
+    Command::new("pmset")
+        .args(["-g", "batt"])
+        .output_checked_utf8()
+        .map(|output| output.stdout.contains("'Battery Power'"))
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn platform_on_battery() -> bool {
+    // A `BatteryStatus` of 1 means that the battery is discharging
+    Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "(Get-CimInstance -ClassName Win32_Battery).BatteryStatus",
+        ])
+        .output_checked_utf8()
+        .map(|output| output.stdout.lines().any(|status| status.trim() == "1"))
+        .unwrap_or(false)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn platform_on_battery() -> bool {
+    false
+}
+
+/// The 1-minute load average, `None` where the platform has none or it cannot be read.
+pub fn load_average() -> Option<f64> {
+    let load = platform_load_average();
+    debug!("Load average: {load:?}");
+    load
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn platform_load_average() -> Option<f64> {
+    // 0.52 0.58 0.59 1/467 12345
+    std::fs::read_to_string("/proc/loadavg")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+fn platform_load_average() -> Option<f64> {
+    // { 0.52 0.58 0.59 }
+    Command::new("sysctl")
+        .args(["-n", "vm.loadavg"])
+        .output_checked_utf8()
+        .ok()?
+        .stdout
+        .split_whitespace()
+        .find_map(|value| value.parse().ok())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "openbsd"
+)))]
+fn platform_load_average() -> Option<f64> {
+    None
+}
@@ -1,8 +1,10 @@
 use std::borrow::Cow;
 
+use crate::error::FailureKind;
+
 pub enum StepResult {
     Success,
-    Failure,
+    Failure(FailureKind),
     Ignored,
     Skipped(String),
 }
@@ -11,7 +13,7 @@ impl StepResult {
     pub fn failed(&self) -> bool {
         match self {
             StepResult::Success | StepResult::Ignored | StepResult::Skipped(_) => false,
-            StepResult::Failure => true,
+            StepResult::Failure(_) => true,
         }
     }
 }
@@ -1,17 +1,136 @@
+use crate::breaking_changes::data_dir;
+use crate::config::{StepFrequency, StepVerbosity};
 use crate::ctrlc;
-use crate::error::{DryRun, SkipStep};
+use crate::error::{DryRun, FailureKind, SkipStep};
 use crate::execution_context::ExecutionContext;
+use crate::executor::{pty_available, set_in_pty, start_capturing, start_step_log, stop_capturing, stop_step_log};
+use crate::power::{load_average, on_battery};
 use crate::report::{Report, StepResult};
-use crate::terminal::print_error;
+use crate::terminal::{print_error, print_info, print_warning};
+use crate::webhook;
 use crate::{config::Step, terminal::should_retry};
+use clap::ValueEnum;
 use color_eyre::eyre::Result;
 use std::borrow::Cow;
+use std::cell::{Cell, OnceCell};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::debug;
 
 pub struct Runner<'a> {
     ctx: &'a ExecutionContext<'a>,
     report: Report<'a>,
+    started: Instant,
+    /// Set once `--max-run-time` has been exceeded, no more steps are started afterwards.
+    out_of_time: bool,
+    /// Whether the system runs on battery, checked the first time it is needed.
+    on_battery: OnceCell<bool>,
+    /// The load average, read the first time it is needed.
+    load_average: OnceCell<Option<f64>>,
+    /// Set once the `network_jitter` delay has been waited.
+    jittered: bool,
+    /// Set once the step given with `--until` is reached, the steps after it are skipped.
+    until_reached: bool,
+    /// Set once a step has failed with `--strict`, no more steps are started afterwards.
+    failed_strict: bool,
+    /// Set once the step given with `--resume-from` is reached, the steps before are skipped.
+    resume_reached: bool,
+    /// The step logs written during this run, they are only truncated the first time.
+    step_logs: HashSet<PathBuf>,
+    /// Whether the steps with a `step_frequency` are due, decided the first time they are reached.
+    due_steps: HashMap<Step, bool>,
+    /// The steps that were not due, counted once in the runs since they last succeeded.
+    not_due_counted: HashSet<Step>,
+}
+
+/// The file holding the step being run, left behind when a run is interrupted.
+fn current_step_file() -> PathBuf {
+    data_dir().join("topgrade_current_step")
+}
+
+/// The step the previous run was running when it was interrupted, if it was.
+pub fn interrupted_step() -> Option<String> {
+    fs::read_to_string(current_step_file())
+        .ok()
+        .map(|step| step.trim().to_string())
+        .filter(|step| !step.is_empty())
+}
+
+/// Forget the step being run, once the run is complete.
+pub fn clear_interrupted_step() {
+    fs::remove_file(current_step_file()).ok();
+}
+
+/// The name of the log file of the step `key`, such as `vagrant-default` for `Vagrant (default)`.
+fn step_log_name(key: &str) -> String {
+    key.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The file holding when the steps with a `step_frequency` last succeeded, and the runs since.
+fn step_runs_file() -> PathBuf {
+    data_dir().join("topgrade_step_runs")
+}
+
+fn step_name(step: Step) -> String {
+    step.to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// Tell whether a step with the `frequency` is due at `now`, from when it `last` succeeded and the
+/// number of runs since.
+fn is_due(last: Option<(u64, u32)>, frequency: StepFrequency, now: u64) -> bool {
+    match (last, frequency) {
+        (None, _) => true,
+        (Some((last_run, _)), StepFrequency::Interval(interval)) => {
+            // Some slack, so that a daily step isn't skipped when the run starts a bit earlier
+            let elapsed = now.saturating_sub(last_run);
+            elapsed + interval.as_secs() / 20 >= interval.as_secs()
+        }
+        (Some((_, runs_since)), StepFrequency::Runs(every)) => runs_since + 1 >= every,
+    }
+}
+
+/// The steps with the time they last succeeded and the number of runs they were not due since.
+fn read_step_runs() -> HashMap<String, (u64, u32)> {
+    // <step> <last run> <runs since>
+    fs::read_to_string(step_runs_file())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?.to_string();
+            Some((name, (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?)))
+        })
+        .collect()
+}
+
+fn write_step_runs(runs: &HashMap<String, (u64, u32)>) {
+    let contents: String = runs
+        .iter()
+        .map(|(name, (last_run, runs_since))| format!("{name} {last_run} {runs_since}\n"))
+        .collect();
+    fs::create_dir_all(data_dir())
+        .and_then(|_| fs::write(step_runs_file(), contents))
+        .ok();
 }
 
 impl<'a> Runner<'a> {
@@ -19,7 +138,112 @@ impl<'a> Runner<'a> {
         Runner {
             ctx,
             report: Report::new(),
+            started: Instant::now(),
+            out_of_time: false,
+            on_battery: OnceCell::new(),
+            load_average: OnceCell::new(),
+            jittered: false,
+            until_reached: false,
+            failed_strict: false,
+            resume_reached: ctx.config().resume_from().is_none(),
+            step_logs: HashSet::new(),
+            due_steps: HashMap::new(),
+            not_due_counted: HashSet::new(),
+        }
+    }
+
+    /// Warn about the `--until` step when it is not enabled, all the steps before it still run.
+    pub fn check_until(&self) {
+        if let Some(step) = self
+            .ctx
+            .config()
+            .until()
+            .filter(|step| !self.ctx.config().should_run(*step))
+        {
+            print_warning(format!(
+                "`--until {}` is not enabled, the steps before it are run but not the step itself",
+                step_name(step)
+            ));
+        }
+    }
+
+    /// Tell whether `step` is due according to its `step_frequency`, decided the first time it is asked.
+    fn step_due(&mut self, step: Step) -> bool {
+        if let Some(due) = self.due_steps.get(&step) {
+            return *due;
         }
+
+        let due = match self.ctx.config().step_frequency(step) {
+            Some(frequency) if !self.ctx.config().force_run(step) => {
+                is_due(read_step_runs().get(&step_name(step)).copied(), frequency, unix_time())
+            }
+            _ => true,
+        };
+        self.due_steps.insert(step, due);
+        due
+    }
+
+    /// Count this run as one `step` was not run in, once per run.
+    fn count_not_due(&mut self, step: Step) {
+        if self.ctx.run_type().dry() || !self.not_due_counted.insert(step) {
+            return;
+        }
+
+        let mut runs = read_step_runs();
+        if let Some((_, runs_since)) = runs.get_mut(&step_name(step)) {
+            *runs_since += 1;
+            write_step_runs(&runs);
+        }
+    }
+
+    /// The log file of the step `key` in `per_step_logs_dir` if it is set, and whether to truncate it.
+    fn step_log_path(&self, key: &str) -> Option<(PathBuf, bool)> {
+        let dir = self.ctx.config().per_step_logs_dir()?;
+        // Dry runs don't run the commands, keep the logs of the previous run
+        if self.ctx.run_type().dry() {
+            return None;
+        }
+
+        let path = dir.join(format!("{}.log", step_log_name(key)));
+        let truncate = !self.step_logs.contains(&path);
+        Some((path, truncate))
+    }
+
+    /// Wait a random delay of up to `network_jitter` seconds, once per run.
+    fn wait_network_jitter(&mut self) {
+        if self.jittered {
+            return;
+        }
+        self.jittered = true;
+
+        let max = self.ctx.config().network_jitter();
+        if max == 0 || self.ctx.run_type().dry() {
+            return;
+        }
+
+        // A randomly seeded hasher is enough randomness to spread the runs of a fleet
+        let delay = RandomState::new().build_hasher().finish() % (max + 1);
+        print_info(format!(
+            "Waiting {delay}s before the first step (network_jitter = {max})"
+        ));
+        thread::sleep(Duration::from_secs(delay));
+    }
+
+    /// Tell whether the time budget given by `--max-run-time` has been used up.
+    fn time_budget_exceeded(&mut self) -> bool {
+        if !self.out_of_time {
+            if let Some(max_run_time) = self.ctx.config().max_run_time() {
+                if self.started.elapsed() > max_run_time {
+                    print_warning(format!(
+                        "\nThe time budget of {}s has been exceeded, no more steps will be run",
+                        max_run_time.as_secs()
+                    ));
+                    self.out_of_time = true;
+                }
+            }
+        }
+
+        self.out_of_time
     }
 
     pub fn execute<F, M>(&mut self, step: Step, key: M, func: F) -> Result<()>
@@ -27,6 +251,15 @@ impl<'a> Runner<'a> {
         F: Fn() -> Result<()>,
         M: Into<Cow<'a, str>> + Debug,
     {
+        // Also when the step is disabled or skipped, the steps after it are not run
+        if self.ctx.config().until() == Some(step) {
+            self.until_reached = true;
+        }
+        // The steps after a disabled resume point are run too
+        if self.ctx.config().resume_from() == Some(step) {
+            self.resume_reached = true;
+        }
+
         if !self.ctx.config().should_run(step) {
             return Ok(());
         }
@@ -34,14 +267,132 @@ impl<'a> Runner<'a> {
         let key = key.into();
         debug!("Step {:?}", key);
 
+        if self.time_budget_exceeded() {
+            self.report.push_result(Some((
+                key,
+                StepResult::Skipped(String::from("not run (time budget exceeded)")),
+            )));
+            return Ok(());
+        }
+
+        if self.ctx.config().metadata_only() && !step.supports_metadata_only() {
+            self.report.push_result(Some((
+                key,
+                StepResult::Skipped(String::from("no metadata to refresh (--metadata-only)")),
+            )));
+            return Ok(());
+        }
+
+        if !self.resume_reached {
+            self.report
+                .push_result(Some((key, StepResult::Skipped(String::from("before resume point")))));
+            return Ok(());
+        }
+
+        if self.failed_strict {
+            self.report
+                .push_result(Some((key, StepResult::Skipped(String::from("not run (--strict)")))));
+            return Ok(());
+        }
+
+        // A step can be registered several times, all of them run before stopping
+        if self.until_reached && self.ctx.config().until() != Some(step) {
+            self.report
+                .push_result(Some((key, StepResult::Skipped(String::from("not run (--until)")))));
+            return Ok(());
+        }
+
+        if !self.step_due(step) {
+            self.count_not_due(step);
+            self.report
+                .push_result(Some((key, StepResult::Skipped(String::from("not due")))));
+            return Ok(());
+        }
+
+        let quiet = self.ctx.config().step_verbosity(step) == StepVerbosity::Quiet;
+        let in_pty = self.ctx.config().needs_tty().contains(&step) && !io::stdout().is_terminal();
+        if in_pty && !pty_available() {
+            print_warning(format!(
+                "No pseudo-terminal available, running {key} without a terminal"
+            ));
+        }
+        let in_pty = in_pty && pty_available();
+
+        if self.ctx.config().skip_on_battery().contains(&step) && *self.on_battery.get_or_init(on_battery) {
+            self.report
+                .push_result(Some((key, StepResult::Skipped(String::from("on battery")))));
+            return Ok(());
+        }
+
+        if let Some(busy) = self
+            .ctx
+            .config()
+            .skip_if_busy()
+            .filter(|busy| busy.steps.contains(&step))
+        {
+            if let Some(load) = self
+                .load_average
+                .get_or_init(load_average)
+                .filter(|load| *load > busy.load)
+            {
+                self.report.push_result(Some((
+                    key,
+                    StepResult::Skipped(format!("system busy (load {load:.2} > {})", busy.load)),
+                )));
+                return Ok(());
+            }
+        }
+
+        self.wait_network_jitter();
+
+        if !self.ctx.run_type().dry() {
+            if let Some(name) = step.to_possible_value() {
+                fs::create_dir_all(data_dir())
+                    .and_then(|_| fs::write(current_step_file(), name.get_name()))
+                    .ok();
+            }
+        }
+
+        let step_log = self.step_log_path(&key);
+        let step_log_written = Cell::new(false);
+
         // alter the `func` to put it in a span
         let func = || {
             let span =
                 tracing::span!(parent: tracing::Span::none(), tracing::Level::TRACE, "step", step = ?step, key = %key);
             let _guard = span.enter();
-            func()
+            if in_pty {
+                set_in_pty(true);
+            }
+            if let Some((path, truncate)) = step_log.clone() {
+                // A retry appends to the log of the failed attempt
+                start_step_log(path, truncate && !step_log_written.get());
+            }
+            if !quiet {
+                let result = func();
+                set_in_pty(false);
+                step_log_written.set(stop_step_log() || step_log_written.get());
+                return result;
+            }
+
+            start_capturing();
+            let result = func();
+            let captured = stop_capturing().unwrap_or_default();
+            set_in_pty(false);
+            step_log_written.set(stop_step_log() || step_log_written.get());
+            match &result {
+                Ok(()) => print_info(format!("{key} succeeded, output hidden")),
+                // Only show the output of failed steps
+                Err(e) if e.downcast_ref::<DryRun>().is_none() && e.downcast_ref::<SkipStep>().is_none() => {
+                    io::stdout().write_all(&captured).ok();
+                }
+                Err(_) => (),
+            }
+            result
         };
 
+        let step_started = Instant::now();
+        let reported = self.report.data().len();
         loop {
             match func() {
                 Ok(()) => {
@@ -63,7 +414,8 @@ impl<'a> Runner<'a> {
                     }
 
                     let ignore_failure = self.ctx.config().ignore_failure(step);
-                    let should_ask = interrupted || !(self.ctx.config().no_retry() || ignore_failure);
+                    let fail_fast = self.ctx.config().fail_fast();
+                    let should_ask = interrupted || !(self.ctx.config().no_retry() || ignore_failure || fail_fast);
                     let should_retry = if should_ask {
                         print_error(&key, format!("{e:?}"));
                         should_retry(interrupted, key.as_ref())?
@@ -72,12 +424,16 @@ impl<'a> Runner<'a> {
                     };
 
                     if !should_retry {
+                        if fail_fast && !ignore_failure {
+                            print_warning(format!("\n{key} failed, no more steps will be run (--strict)"));
+                            self.failed_strict = true;
+                        }
                         self.report.push_result(Some((
                             key,
                             if ignore_failure {
                                 StepResult::Ignored
                             } else {
-                                StepResult::Failure
+                                StepResult::Failure(FailureKind::classify(&e))
                             },
                         )));
                         break;
@@ -86,10 +442,178 @@ impl<'a> Runner<'a> {
             }
         }
 
+        if let Some((path, _)) = step_log.filter(|_| step_log_written.get()) {
+            self.step_logs.insert(path);
+        }
+
+        let succeeded = matches!(self.report.data().get(reported), Some((_, StepResult::Success)));
+        // A forced run counts as a run too
+        if succeeded && !self.ctx.run_type().dry() && self.ctx.config().step_frequency(step).is_some() {
+            let mut runs = read_step_runs();
+            runs.insert(step_name(step), (unix_time(), 0));
+            write_step_runs(&runs);
+        }
+
+        if let (Some(url), Some((key, result))) = (self.ctx.config().webhook_url(), self.report.data().get(reported)) {
+            webhook::send_step_result(url, key, result, step_started.elapsed());
+        }
+
         Ok(())
     }
 
-    pub fn report(&self) -> &Report {
+    /// How long the run has taken so far.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn report(&self) -> &Report<'_> {
         &self.report
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::executor::RunType;
+    use color_eyre::eyre::eyre;
+
+    /// The results of the run with the command line `args` of the steps with their keys.
+    fn run(args: &[&str], steps: &[(Step, &'static str)]) -> Vec<String> {
+        let config = Config::for_tests(args, "");
+        let ctx = ExecutionContext::new(RunType::Dry, None, &config);
+        let mut runner = Runner::new(&ctx);
+        for (step, key) in steps {
+            runner.execute(*step, *key, || Ok(())).unwrap();
+        }
+        results(&runner)
+    }
+
+    fn results(runner: &Runner) -> Vec<String> {
+        runner
+            .report()
+            .data()
+            .iter()
+            .map(|(key, result)| match result {
+                StepResult::Success => format!("{key}: success"),
+                StepResult::Failure(kind) => format!("{key}: failed ({kind})"),
+                StepResult::Ignored => format!("{key}: ignored"),
+                StepResult::Skipped(reason) => format!("{key}: {reason}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_from() {
+        let steps = [(Step::Rustup, "rustup"), (Step::Cargo, "cargo"), (Step::Pip3, "pip3")];
+        assert_eq!(
+            run(&["--resume-from", "cargo"], &steps),
+            ["rustup: before resume point", "cargo: success", "pip3: success"]
+        );
+    }
+
+    #[test]
+    fn test_resume_from_disabled_step() {
+        let steps = [(Step::Rustup, "rustup"), (Step::Cargo, "cargo"), (Step::Pip3, "pip3")];
+        assert_eq!(
+            run(&["--resume-from", "cargo", "--disable", "cargo"], &steps),
+            ["rustup: before resume point", "pip3: success"]
+        );
+    }
+
+    #[test]
+    fn test_is_due() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = 100 * day.as_secs();
+        let daily = StepFrequency::Interval(day);
+        assert!(is_due(None, daily, now));
+        assert!(is_due(Some((now - day.as_secs(), 0)), daily, now));
+        // A run starting a bit earlier than a day after the last one
+        assert!(is_due(Some((now - day.as_secs() + 60 * 60, 0)), daily, now));
+        assert!(!is_due(Some((now - day.as_secs() / 2, 0)), daily, now));
+
+        let every_3_runs = StepFrequency::Runs(3);
+        assert!(is_due(None, every_3_runs, now));
+        assert!(!is_due(Some((now, 0)), every_3_runs, now));
+        assert!(!is_due(Some((now, 1)), every_3_runs, now));
+        assert!(is_due(Some((now, 2)), every_3_runs, now));
+    }
+
+    #[test]
+    fn test_metadata_only() {
+        let steps = [(Step::System, "system"), (Step::Pip3, "pip3")];
+        assert_eq!(
+            run(&["--metadata-only"], &steps),
+            ["system: success", "pip3: no metadata to refresh (--metadata-only)"]
+        );
+    }
+
+    #[test]
+    fn test_until() {
+        let steps = [
+            (Step::Rustup, "rustup"),
+            (Step::Cargo, "cargo 1"),
+            (Step::Cargo, "cargo 2"),
+            (Step::Pip3, "pip3"),
+        ];
+        assert_eq!(
+            run(&["--until", "cargo"], &steps),
+            [
+                "rustup: success",
+                "cargo 1: success",
+                "cargo 2: success",
+                "pip3: not run (--until)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_until_disabled_step() {
+        let steps = [(Step::Rustup, "rustup"), (Step::Cargo, "cargo"), (Step::Pip3, "pip3")];
+        assert_eq!(
+            run(&["--until", "cargo", "--disable", "cargo"], &steps),
+            ["rustup: success", "pip3: not run (--until)"]
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let config = Config::for_tests(&["--strict"], "");
+        let ctx = ExecutionContext::new(RunType::Dry, None, &config);
+        let mut runner = Runner::new(&ctx);
+        runner.execute(Step::Rustup, "rustup", || Ok(())).unwrap();
+        runner
+            .execute(Step::Cargo, "cargo", || Err(eyre!("cargo failed")))
+            .unwrap();
+        runner.execute(Step::Pip3, "pip3", || Ok(())).unwrap();
+        assert_eq!(
+            results(&runner),
+            ["rustup: success", "cargo: failed (other)", "pip3: not run (--strict)"]
+        );
+    }
+
+    #[test]
+    fn test_skip_if_busy() {
+        let config = Config::for_tests(&[], "[misc]\nskip_if_busy = { load = 4.0, steps = [\"cargo\"] }");
+        let ctx = ExecutionContext::new(RunType::Dry, None, &config);
+        let mut runner = Runner::new(&ctx);
+        runner.load_average.set(Some(6.5)).unwrap();
+        runner.execute(Step::Rustup, "rustup", || Ok(())).unwrap();
+        runner.execute(Step::Cargo, "cargo", || Ok(())).unwrap();
+        assert_eq!(
+            results(&runner),
+            ["rustup: success", "cargo: system busy (load 6.50 > 4)"]
+        );
+    }
+
+    #[test]
+    fn test_skip_on_battery() {
+        let config = Config::for_tests(&[], "[misc]\nskip_on_battery = [\"cargo\"]");
+        let ctx = ExecutionContext::new(RunType::Dry, None, &config);
+        let mut runner = Runner::new(&ctx);
+        runner.on_battery.set(true).unwrap();
+        runner.execute(Step::Rustup, "rustup", || Ok(())).unwrap();
+        runner.execute(Step::Cargo, "cargo", || Ok(())).unwrap();
+        assert_eq!(results(&runner), ["rustup: success", "cargo: on battery"]);
+    }
+}
@@ -0,0 +1,322 @@
+//! `--schedule` and `--unschedule`: run Topgrade periodically with the scheduler of the platform.
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use std::env;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::fs;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::path::{Path, PathBuf};
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use std::process::Command;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::time::Duration;
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+use color_eyre::eyre::bail;
+use color_eyre::eyre::Result;
+#[cfg(target_os = "linux")]
+use etcetera::base_strategy::BaseStrategy;
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use crate::command::CommandExt;
+use crate::config::Config;
+#[cfg(target_os = "macos")]
+use crate::HOME_DIR;
+
+/// The arguments of the scheduled runs, nobody is there to answer prompts.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+const SCHEDULED_ARGS: &[&str] = &["--yes", "--no-retry"];
+
+/// The arguments of the scheduled runs, with the configuration files of this run.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn scheduled_args(config: &Config) -> Vec<String> {
+    let mut args: Vec<String> = SCHEDULED_ARGS.iter().map(|arg| arg.to_string()).collect();
+    for path in config.config_paths() {
+        // The scheduler doesn't run Topgrade from the current directory
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        args.push(String::from("--config"));
+        args.push(path.to_string_lossy().into_owned());
+    }
+    args
+}
+
+#[cfg(target_os = "linux")]
+fn systemd_user_dir() -> PathBuf {
+    crate::XDG_DIRS.config_dir().join("systemd").join("user")
+}
+
+/// The service running `exe` with `args`.
+#[cfg(target_os = "linux")]
+fn systemd_service(exe: &Path, args: &[String]) -> String {
+    let command_line = shell_words::join(std::iter::once(exe.to_string_lossy()).chain(args.iter().map(Into::into)));
+    format!(
+        "[Unit]\nDescription=Upgrade everything with Topgrade\n\n\
+         [Service]\nType=oneshot\nEnvironment=TOPGRADE_SKIP_BRKC_NOTIFY=true\nExecStart={command_line}\n"
+    )
+}
+
+/// The timer starting the service every `interval`.
+#[cfg(target_os = "linux")]
+fn systemd_timer(interval: Duration) -> String {
+    format!(
+        "[Unit]\nDescription=Run Topgrade periodically\n\n\
+         [Timer]\nOnBootSec=15min\nOnUnitActiveSec={}s\nPersistent=true\n\n\
+         [Install]\nWantedBy=timers.target\n",
+        interval.as_secs()
+    )
+}
+
+/// Install the scheduled run, replacing the one installed before if any.
+#[cfg(target_os = "linux")]
+pub fn schedule(config: &Config) -> Result<()> {
+    let interval = config.schedule_interval()?;
+    let exe = env::current_exe()?;
+    let dir = systemd_user_dir();
+    fs::create_dir_all(&dir)?;
+
+    fs::write(
+        dir.join("topgrade.service"),
+        systemd_service(&exe, &scheduled_args(config)),
+    )?;
+    fs::write(dir.join("topgrade.timer"), systemd_timer(interval))?;
+
+    Command::new("systemctl")
+        .args(["--user", "daemon-reload"])
+        .status_checked()?;
+    Command::new("systemctl")
+        .args(["--user", "enable", "--now", "topgrade.timer"])
+        .status_checked()?;
+    // Restarting applies a changed interval to an already running timer
+    Command::new("systemctl")
+        .args(["--user", "restart", "topgrade.timer"])
+        .status_checked()?;
+
+    let next_run = Command::new("systemctl")
+        .args([
+            "--user",
+            "show",
+            "topgrade.timer",
+            "--property=NextElapseUSecRealtime",
+            "--value",
+        ])
+        .output_checked_utf8()?
+        .stdout;
+    println!("Topgrade is scheduled, next run: {}", next_run.trim());
+
+    Ok(())
+}
+
+/// Remove the scheduled run.
+#[cfg(target_os = "linux")]
+pub fn unschedule() -> Result<()> {
+    let dir = systemd_user_dir();
+    let timer = dir.join("topgrade.timer");
+    if !timer.exists() {
+        println!("Topgrade is not scheduled");
+        return Ok(());
+    }
+
+    Command::new("systemctl")
+        .args(["--user", "disable", "--now", "topgrade.timer"])
+        .status_checked()?;
+    fs::remove_file(timer)?;
+    fs::remove_file(dir.join("topgrade.service")).ok();
+    Command::new("systemctl")
+        .args(["--user", "daemon-reload"])
+        .status_checked()?;
+
+    println!("Topgrade is not scheduled anymore");
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn launch_agent_path() -> PathBuf {
+    HOME_DIR.join("Library/LaunchAgents/com.github.topgrade-rs.topgrade.plist")
+}
+
+/// The launch agent running `exe` with `args` every `interval`.
+#[cfg(target_os = "macos")]
+fn launch_agent(exe: &Path, args: &[String], interval: Duration) -> String {
+    let xml_escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let arguments: String = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(&arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.github.topgrade-rs.topgrade</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>TOPGRADE_SKIP_BRKC_NOTIFY</key>
+        <string>true</string>
+    </dict>
+    <key>StartInterval</key>
+    <integer>{}</integer>
+</dict>
+</plist>
+"#,
+        interval.as_secs()
+    )
+}
+
+/// Install the scheduled run, replacing the one installed before if any.
+#[cfg(target_os = "macos")]
+pub fn schedule(config: &Config) -> Result<()> {
+    let interval = config.schedule_interval()?;
+    let exe = env::current_exe()?;
+    let plist = launch_agent_path();
+    fs::create_dir_all(plist.parent().unwrap())?;
+
+    fs::write(&plist, launch_agent(&exe, &scheduled_args(config), interval))?;
+
+    // Unloading fails when the agent wasn't loaded yet
+    Command::new("launchctl")
+        .arg("unload")
+        .arg(&plist)
+        .output_checked()
+        .ok();
+    Command::new("launchctl")
+        .args(["load", "-w"])
+        .arg(&plist)
+        .status_checked()?;
+
+    let next_run = chrono::Local::now() + chrono::Duration::seconds(interval.as_secs() as i64);
+    println!("Topgrade is scheduled, next run: {}", next_run.format("%Y-%m-%d %H:%M"));
+
+    Ok(())
+}
+
+/// Remove the scheduled run.
+#[cfg(target_os = "macos")]
+pub fn unschedule() -> Result<()> {
+    let plist = launch_agent_path();
+    if !plist.exists() {
+        println!("Topgrade is not scheduled");
+        return Ok(());
+    }
+
+    Command::new("launchctl")
+        .args(["unload", "-w"])
+        .arg(&plist)
+        .status_checked()?;
+    fs::remove_file(plist)?;
+
+    println!("Topgrade is not scheduled anymore");
+    Ok(())
+}
+
+/// The command line of the task running `exe` with `args`, each of them quoted.
+#[cfg(windows)]
+fn task_command(exe: &str, args: &[String]) -> String {
+    std::iter::once(exe)
+        .chain(args.iter().map(String::as_str))
+        .map(|arg| format!("\"{arg}\""))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Install the scheduled run, replacing the one installed before if any.
+#[cfg(windows)]
+pub fn schedule(config: &Config) -> Result<()> {
+    let interval = config.schedule_interval()?;
+    let exe = env::current_exe()?;
+    let minutes = (interval.as_secs() / 60).max(1);
+
+    // `/F` replaces the task if it exists
+    Command::new("schtasks")
+        .args(["/Create", "/F", "/TN", "topgrade", "/SC", "MINUTE", "/MO"])
+        .arg(minutes.to_string())
+        .arg("/TR")
+        .arg(task_command(&exe.to_string_lossy(), &scheduled_args(config)))
+        .output_checked()?;
+
+    let task = Command::new("schtasks")
+        .args(["/Query", "/TN", "topgrade", "/FO", "LIST"])
+        .output_checked_utf8()?
+        .stdout;
+    let next_run = task
+        .lines()
+        .find_map(|line| line.strip_prefix("Next Run Time:"))
+        .map_or("unknown", str::trim);
+    println!("Topgrade is scheduled, next run: {next_run}");
+
+    Ok(())
+}
+
+/// Remove the scheduled run.
+#[cfg(windows)]
+pub fn unschedule() -> Result<()> {
+    let exists = Command::new("schtasks")
+        .args(["/Query", "/TN", "topgrade"])
+        .output_checked()
+        .is_ok();
+    if !exists {
+        println!("Topgrade is not scheduled");
+        return Ok(());
+    }
+
+    Command::new("schtasks")
+        .args(["/Delete", "/F", "/TN", "topgrade"])
+        .output_checked()?;
+
+    println!("Topgrade is not scheduled anymore");
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn schedule(_config: &Config) -> Result<()> {
+    bail!("Scheduling Topgrade is not supported on this platform, use cron instead")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn unschedule() -> Result<()> {
+    bail!("Scheduling Topgrade is not supported on this platform, use cron instead")
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn test_task_command() {
+        let args = [String::from("--config"), String::from(r"C:\my config.toml")];
+        assert_eq!(
+            super::task_command(r"C:\topgrade.exe", &args),
+            r#""C:\topgrade.exe" "--config" "C:\my config.toml""#
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_systemd_units() {
+        let args = [
+            String::from("--yes"),
+            String::from("--config"),
+            String::from("/my config.toml"),
+        ];
+        let service = systemd_service(Path::new("/usr/bin/topgrade"), &args);
+        assert!(service.contains("\nExecStart=/usr/bin/topgrade --yes --config '/my config.toml'\n"));
+        let timer = systemd_timer(Duration::from_secs(12 * 60 * 60));
+        assert!(timer.contains("\nOnUnitActiveSec=43200s\n"));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_launch_agent() {
+        let args = [String::from("--config"), String::from("/a&b.toml")];
+        let plist = launch_agent(Path::new("/usr/local/bin/topgrade"), &args, Duration::from_secs(3600));
+        assert!(plist.contains(
+            "        <string>/usr/local/bin/topgrade</string>\n        <string>--config</string>\n        <string>/a&amp;b.toml</string>\n"
+        ));
+        assert!(plist.contains("<integer>3600</integer>"));
+    }
+}
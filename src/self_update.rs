@@ -3,7 +3,9 @@ use std::env;
 use std::os::unix::process::CommandExt as _;
 use std::process::Command;
 
-use crate::config::Step;
+use crate::config::{SelfUpdateStrategy, Step};
+use crate::error::SkipStep;
+use crate::utils::installing_package_manager;
 use color_eyre::eyre::{bail, Result};
 use self_update_crate::backends::github::Update;
 use self_update_crate::update::UpdateStatus;
@@ -17,6 +19,17 @@ use crate::execution_context::ExecutionContext;
 pub fn self_update(ctx: &ExecutionContext) -> Result<()> {
     print_separator("Self update");
 
+    // Replacing a binary owned by a package manager would fight with it
+    let package_manager = match ctx.config().self_update_strategy() {
+        SelfUpdateStrategy::Builtin => None,
+        SelfUpdateStrategy::PackageManager => Some("the package manager that installed it"),
+        SelfUpdateStrategy::Auto => env::current_exe().ok().and_then(|exe| installing_package_manager(&exe)),
+    };
+    if let Some(package_manager) = package_manager {
+        println!("Topgrade is updated by {package_manager}, not by itself");
+        return Err(SkipStep(format!("Topgrade is updated by {package_manager}")).into());
+    }
+
     if ctx.run_type().dry() {
         println!("Would self-update");
         Ok(())
@@ -11,6 +11,8 @@ use wildmatch::WildMatch;
 use crate::command::CommandExt;
 use crate::error::{self, TopgradeError};
 use crate::terminal::print_separator;
+use crate::utils::PathExt;
+use crate::Step;
 use crate::{execution_context::ExecutionContext, utils::require};
 
 // A string found in the output of docker for containers that weren't found in
@@ -166,15 +168,25 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
 
     if ctx.config().cleanup() {
         // Remove dangling images
-        debug!("Removing dangling images");
-        if let Err(e) = ctx
-            .run_type()
-            .execute(&crt)
-            .args(["image", "prune", "-f"])
-            .status_checked()
-        {
-            error!("Removing dangling images failed: {}", e);
-            success = false;
+        let dangling = Command::new(&crt)
+            .args(["image", "ls", "--quiet", "--filter", "dangling=true"])
+            .output_checked_utf8()
+            .map(|output| output.stdout.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let what = format!("the dangling images {}", dangling.join(", "));
+        if (dangling.is_empty() && !ctx.run_type().dry()) || !ctx.confirm_destructive(Step::Containers, &what)? {
+            debug!("Not removing dangling images");
+        } else {
+            debug!("Removing dangling images");
+            if let Err(e) = ctx
+                .run_type()
+                .execute(&crt)
+                .args(["image", "prune", "-f"])
+                .status_checked()
+            {
+                error!("Removing dangling images failed: {}", e);
+                success = false;
+            }
         }
     }
 
@@ -184,3 +196,172 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
         Err(eyre!(error::StepFailed))
     }
 }
+
+/// The names of the buildx builders, the current one when they can't be listed.
+fn buildx_builders(docker: &Path) -> Vec<Option<String>> {
+    let builders: Vec<Option<String>> = Command::new(docker)
+        .args(["buildx", "ls", "--format", "{{.Name}}"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| Some(name.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if builders.is_empty() {
+        vec![None]
+    } else {
+        builders
+    }
+}
+
+/// The files docker compose looks for in a project directory.
+const COMPOSE_FILES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// The container of each running service of the compose project in `project`.
+fn compose_containers(docker: &Path, project: &Path) -> Result<Vec<(String, String)>> {
+    Ok(Command::new(docker)
+        .current_dir(project)
+        .args(["compose", "ps", "--format", "{{.Service}} {{.ID}}"])
+        .output_checked_utf8()?
+        .stdout
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(service, id)| (service.to_string(), id.to_string()))
+        .collect())
+}
+
+/// Pull the images of a compose project of `compose_projects`, and recreate its services which
+/// changed.
+pub fn run_compose_project(ctx: &ExecutionContext, project: &Path) -> Result<()> {
+    let docker = require("docker")?;
+    if Command::new(&docker)
+        .args(["compose", "version"])
+        .output_checked()
+        .is_err()
+    {
+        return Err(error::SkipStep(String::from("docker compose is not installed")).into());
+    }
+    let project = project.require()?;
+    if !COMPOSE_FILES.iter().any(|file| project.join(file).exists()) {
+        return Err(error::SkipStep(format!("There is no compose file in {}", project.display())).into());
+    }
+
+    print_separator(format!("Compose ({})", project.display()));
+
+    if ctx.run_type().dry() {
+        println!("Would pull these images and recreate the services using them:");
+        Command::new(&docker)
+            .current_dir(project)
+            .args(["compose", "config", "--images"])
+            .status_checked()?;
+    }
+
+    let before = compose_containers(&docker, project)?;
+    ctx.run_type()
+        .execute(&docker)
+        .current_dir(project)
+        .args(["compose", "pull"])
+        .status_checked()?;
+    if ctx.config().compose_prune() {
+        ctx.run_type()
+            .execute(&docker)
+            .current_dir(project)
+            .args(["compose", "down", "--remove-orphans"])
+            .status_checked()?;
+    }
+    ctx.run_type()
+        .execute(&docker)
+        .current_dir(project)
+        .args(["compose", "up", "-d"])
+        .status_checked()?;
+
+    if !ctx.run_type().dry() {
+        // The recreated services run in a new container
+        let after = compose_containers(&docker, project)?;
+        let updated: Vec<&str> = after
+            .iter()
+            .filter(|service| !before.contains(service))
+            .map(|(service, _)| service.as_str())
+            .collect();
+        if updated.is_empty() {
+            println!("No service changed");
+        } else {
+            println!("Updated services: {}", updated.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_buildx_prune(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().buildx_prune() {
+        return Err(error::SkipStep(String::from("`buildx_prune` is not enabled")).into());
+    }
+    let docker = require("docker")?;
+    if Command::new(&docker)
+        .args(["buildx", "version"])
+        .output_checked()
+        .is_err()
+    {
+        return Err(error::SkipStep(String::from("docker buildx is not installed")).into());
+    }
+
+    print_separator("Buildx");
+
+    let builders = buildx_builders(&docker);
+    fn builder_args(builder: &Option<String>) -> Vec<&str> {
+        match builder {
+            Some(builder) => vec!["--builder", builder.as_str()],
+            None => Vec::new(),
+        }
+    }
+
+    if ctx.run_type().dry() {
+        for builder in &builders {
+            println!(
+                "Would prune the build cache of {}:",
+                builder.as_deref().unwrap_or("the current builder")
+            );
+            Command::new(&docker)
+                .args(["buildx", "du"])
+                .args(builder_args(builder))
+                .status_checked()?;
+        }
+        return Ok(());
+    }
+
+    if !ctx.confirm_destructive(Step::Buildx, "the build cache of the buildx builders")? {
+        return Ok(());
+    }
+
+    for builder in &builders {
+        let output = ctx
+            .run_type()
+            .execute(&docker)
+            .args(["buildx", "prune", "--force"])
+            .args(builder_args(builder))
+            .output_checked_utf8()?;
+        // The last line is `Total:\t<size>`
+        let reclaimed = output
+            .stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("Total:"))
+            .map_or("0B", str::trim);
+        println!(
+            "Reclaimed {reclaimed} from {}",
+            builder.as_deref().unwrap_or("the current builder")
+        );
+    }
+
+    Ok(())
+}
@@ -1,17 +1,23 @@
 #[cfg(windows)]
 use std::env;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 
 use color_eyre::eyre::Result;
 use etcetera::base_strategy::BaseStrategy;
 
 use crate::command::CommandExt;
+use crate::config::EmacsPackageManager;
 use crate::execution_context::ExecutionContext;
+use crate::executor::RunType;
 use crate::terminal::print_separator;
 use crate::utils::{require, require_option, PathExt};
 use crate::Step;
 
 const EMACS_UPGRADE: &str = include_str!("emacs.el");
+const EMACS_PACKAGE_UPGRADE: &str = include_str!("emacs_package.el");
+const EMACS_PACKAGE_LIST: &str = include_str!("emacs_package_list.el");
+const EMACS_STRAIGHT_UPGRADE: &str = include_str!("emacs_straight.el");
 #[cfg(windows)]
 const DOOM_PATH: &str = "bin/doom.cmd";
 #[cfg(unix)]
@@ -80,24 +86,41 @@ impl Emacs {
 
         print_separator("Emacs");
 
-        let mut command = ctx.run_type().execute(emacs);
+        let package_manager = ctx.config().emacs_package_manager();
+        let script = match package_manager {
+            EmacsPackageManager::Paradox => EMACS_UPGRADE,
+            EmacsPackageManager::Package if ctx.run_type().dry() => EMACS_PACKAGE_LIST,
+            EmacsPackageManager::Package => EMACS_PACKAGE_UPGRADE,
+            EmacsPackageManager::Straight => EMACS_STRAIGHT_UPGRADE,
+        };
+        let args = Emacs::batch_args(&init_file, script);
 
-        command
-            .args(["--batch", "--debug-init", "-l"])
-            .arg(init_file)
-            .arg("--eval");
+        // package.el can tell which packages would be upgraded without upgrading them
+        let run_type = if ctx.run_type().dry() && package_manager == EmacsPackageManager::Package {
+            RunType::Wet
+        } else {
+            ctx.run_type()
+        };
+        run_type
+            .execute(emacs)
+            .args(args)
+            .status_checked_within(ctx.config().emacs_timeout())
+    }
 
+    fn batch_args(init_file: &Path, script: &str) -> Vec<OsString> {
         #[cfg(unix)]
-        command.arg(
-            EMACS_UPGRADE
-                .chars()
-                .map(|c| if c.is_whitespace() { '\u{00a0}' } else { c })
-                .collect::<String>(),
-        );
-
-        #[cfg(not(unix))]
-        command.arg(EMACS_UPGRADE);
-
-        command.status_checked()
+        let script = script
+            .chars()
+            .map(|c| if c.is_whitespace() { '\u{00a0}' } else { c })
+            .collect::<String>();
+
+        vec![
+            "--batch".into(),
+            "--debug-init".into(),
+            "-l".into(),
+            init_file.into(),
+            "--eval".into(),
+            script.into(),
+        ]
     }
 }
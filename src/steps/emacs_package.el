@@ -0,0 +1,8 @@
+(progn
+  (package-refresh-contents)
+  (let ((upgradeable (package--upgradeable-packages)))
+    (if upgradeable
+        (progn
+          (package-upgrade-all nil)
+          (princ (format "\nUpgraded packages: %s\n" (mapconcat #'symbol-name upgradeable ", "))))
+      (princ "\nNothing to upgrade\n"))))
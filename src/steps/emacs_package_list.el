@@ -0,0 +1,7 @@
+(progn
+  (package-refresh-contents)
+  (let ((upgradeable (package--upgradeable-packages)))
+    (princ
+     (if upgradeable
+         (format "\nUpgradable packages: %s\n" (mapconcat #'symbol-name upgradeable ", "))
+       "\nNothing to upgrade\n"))))
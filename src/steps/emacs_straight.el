@@ -0,0 +1,4 @@
+(progn
+  (straight-pull-all)
+  (straight-check-all)
+  (princ "\nPulled and rebuilt all straight.el packages\n"))
@@ -1,5 +1,7 @@
 #![allow(unused_imports)]
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::process::Command;
 use std::{env, path::Path};
@@ -9,14 +11,18 @@ use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use semver::Version;
+use serde::Deserialize;
 use tempfile::tempfile_in;
 use tracing::{debug, error};
 
 use crate::command::{CommandExt, Utf8Output};
+use crate::config::{CargoSubcommandUpdate, KubectlManifests, PluginUpdate, SelfUpdatingTool, TerraformFlavor};
 use crate::execution_context::ExecutionContext;
-use crate::executor::ExecutorOutput;
+use crate::executor::{Executor, ExecutorOutput, RunType};
 use crate::terminal::{print_separator, shell};
-use crate::utils::{self, check_is_python_2_or_shim, require, require_option, which, PathExt, REQUIRE_SUDO};
+use crate::utils::{
+    self, check_is_python_2_or_shim, installing_package_manager, require, require_option, which, PathExt, REQUIRE_SUDO,
+};
 use crate::Step;
 use crate::HOME_DIR;
 use crate::{
@@ -36,6 +42,304 @@ pub fn is_wsl() -> Result<bool> {
     Ok(false)
 }
 
+/// The crates installed with cargo, keyed by `name version (source)` with their binaries.
+fn cargo_installed_crates(toml_file: &Path) -> toml::Table {
+    // [v1]
+    // "name 0.1.0 (git+https://github.com/owner/name#0123abcd)" = ["name"]
+    fs::read_to_string(toml_file)
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .and_then(|mut crates| match crates.remove("v1") {
+            Some(toml::Value::Table(installed)) => Some(installed),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// The crates installed from git, with the commit they were built from.
+fn cargo_git_crates(toml_file: &Path) -> BTreeMap<String, String> {
+    cargo_installed_crates(toml_file)
+        .keys()
+        .filter_map(|key| {
+            let (name, source) = key.split_once(' ')?;
+            let commit = source.strip_suffix(')')?.split_once("(git+")?.1.rsplit_once('#')?.1;
+            Some((name.to_string(), commit.chars().take(10).collect()))
+        })
+        .collect()
+}
+
+/// The installed crates with a binary starting with `prefix`, with their version.
+fn cargo_companions(toml_file: &Path, bin_dir: &Path, prefix: &str) -> BTreeMap<String, String> {
+    cargo_installed_crates(toml_file)
+        .iter()
+        .filter(|(_, binaries)| {
+            binaries.as_array().is_some_and(|binaries| {
+                binaries.iter().filter_map(toml::Value::as_str).any(|binary| {
+                    binary.starts_with(prefix) && bin_dir.join(format!("{binary}{}", env::consts::EXE_SUFFIX)).exists()
+                })
+            })
+        })
+        .filter_map(|(key, _)| {
+            let mut fields = key.split(' ');
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// The version of each crate installed with cargo.
+fn cargo_installed_versions(toml_file: &Path) -> BTreeMap<String, String> {
+    cargo_installed_crates(toml_file)
+        .keys()
+        .filter_map(|key| {
+            let mut fields = key.split(' ');
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Install the crates of `cargo_tools_manifest` at their listed version.
+pub fn run_cargo_tools(ctx: &ExecutionContext) -> Result<()> {
+    let Some(manifest) = ctx.config().cargo_tools_manifest() else {
+        return Err(SkipStep(String::from("`cargo_tools_manifest` is not set")).into());
+    };
+    let cargo = require("cargo")?;
+    let manifest = manifest.require()?;
+    let tools: toml::Table = fs::read_to_string(&manifest)?
+        .parse()
+        .wrap_err_with(|| format!("Failed to parse {}", manifest.display()))?;
+    let toml_file = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| HOME_DIR.join(".cargo"))
+        .join(".crates.toml");
+
+    print_separator("Cargo tools");
+
+    let before = cargo_installed_versions(&toml_file);
+    let mut failed = Vec::new();
+    for (name, version) in &tools {
+        let version = version
+            .as_str()
+            .ok_or_else(|| eyre!("The version of {name} in {} isn't a string", manifest.display()))?;
+        let pinned = Some(version).filter(|version| *version != "latest");
+        let installed = before.get(name);
+        if pinned.is_some() && pinned == installed.map(String::as_str) {
+            continue;
+        }
+
+        if ctx.run_type().dry() {
+            // Without a pinned version, `cargo install` only replaces an older release
+            match (installed, pinned) {
+                (Some(installed), Some(version)) => println!("Would install {name} {version} over {installed}"),
+                (Some(_), None) => println!("Would update {name} if a newer release exists"),
+                (None, Some(version)) => println!("Would install {name} {version}"),
+                (None, None) => println!("Would install the latest release of {name}"),
+            }
+            continue;
+        }
+
+        let mut command = ctx.run_type().execute(&cargo);
+        command.args(["install", "--locked", name]);
+        if let Some(version) = pinned {
+            command.args(["--version", version]);
+        }
+        if let Err(e) = command.status_checked() {
+            print_warning(format!("{name}: {e}"));
+            failed.push(name.as_str());
+        }
+    }
+
+    if !ctx.run_type().dry() {
+        let after = cargo_installed_versions(&toml_file);
+        let changed: Vec<String> = after
+            .iter()
+            .filter_map(|(name, version)| match before.get(name) {
+                Some(old) if old != version => Some(format!("{name} ({old} -> {version})")),
+                None => Some(format!("{name} ({version})")),
+                _ => None,
+            })
+            .collect();
+        if changed.is_empty() {
+            println!("The tools match the manifest");
+        } else {
+            println!("Installed {}", changed.join(", "));
+        }
+    }
+
+    let unlisted: Vec<&str> = before
+        .keys()
+        .filter(|name| !tools.contains_key(name.as_str()))
+        .map(String::as_str)
+        .collect();
+    if !unlisted.is_empty() {
+        print_warning(format!(
+            "Installed but not in {}: {}",
+            manifest.display(),
+            unlisted.join(", ")
+        ));
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to install {}", failed.join(", ")))
+    }
+}
+
+pub fn run_cargo_companions(ctx: &ExecutionContext) -> Result<()> {
+    let Some(prefix) = ctx.config().cargo_companion_prefix() else {
+        return Err(SkipStep(String::from("`cargo_companion_prefix` is not set")).into());
+    };
+    let cargo_dir = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| HOME_DIR.join(".cargo"))
+        .require()?;
+    let toml_file = cargo_dir.join(".crates.toml").require()?;
+    let bin_dir = cargo_dir.join("bin");
+    let before = cargo_companions(&toml_file, &bin_dir, prefix);
+    if before.is_empty() {
+        return Err(SkipStep(format!("No cargo installed binary starts with `{prefix}`")).into());
+    }
+    let cargo_update = require_option(
+        which("cargo-install-update").or_else(|| bin_dir.join("cargo-install-update").if_exists()),
+        String::from("cargo-update is not installed"),
+    )?;
+
+    print_separator("Cargo companions");
+
+    let mut command = if ctx.run_type().dry() {
+        // `--list` only checks which of them need an update
+        let mut command = RunType::Wet.execute(&cargo_update);
+        command.args(["install-update", "--list"]);
+        command
+    } else {
+        let mut command = ctx.run_type().execute(&cargo_update);
+        command.arg("install-update");
+        command
+    };
+    command.args(before.keys()).status_checked()?;
+
+    if !ctx.run_type().dry() {
+        let after = cargo_companions(&toml_file, &bin_dir, prefix);
+        for (name, version) in &before {
+            match after.get(name) {
+                Some(new) if new != version => println!("  {name} ({version} -> {new})"),
+                _ => println!("  {name} ({version}, up to date)"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_cargo_projects_update(ctx: &ExecutionContext) -> Result<()> {
+    let projects = ctx.config().cargo_projects();
+    if projects.is_empty() {
+        return Err(SkipStep(String::from("No cargo project is configured")).into());
+    }
+    let cargo = require("cargo")?;
+
+    print_separator("Cargo projects");
+
+    let upgrade = ctx.config().cargo_projects_upgrade() && which("cargo-upgrade").is_some();
+    for project in projects {
+        if !project.join("Cargo.toml").exists() {
+            print_warning(format!("{} is not a cargo project", project.display()));
+            continue;
+        }
+
+        // Projects outside of a git repository are never considered dirty
+        let dirty = Command::new("git")
+            .arg("-C")
+            .arg(&project)
+            .args(["status", "--porcelain"])
+            .output_checked_utf8()
+            .map(|output| !output.stdout.trim().is_empty())
+            .unwrap_or(false);
+        if dirty {
+            println!("Skipping {}: the working tree is dirty", project.display());
+            continue;
+        }
+
+        println!("Updating {}", project.display());
+        if ctx.run_type().dry() {
+            // Show what would be updated
+            RunType::Wet
+                .execute(&cargo)
+                .args(["update", "--dry-run"])
+                .current_dir(&project)
+                .status_checked()?;
+            continue;
+        }
+
+        let lock_file = project.join("Cargo.lock");
+        let before = cargo_lock_versions(&lock_file);
+        if upgrade {
+            ctx.run_type()
+                .execute(&cargo)
+                .arg("upgrade")
+                .current_dir(&project)
+                .status_checked()?;
+        }
+        ctx.run_type()
+            .execute(&cargo)
+            .arg("update")
+            .current_dir(&project)
+            .status_checked()?;
+
+        let after = cargo_lock_versions(&lock_file);
+        for (name, versions) in &after {
+            match before.get(name) {
+                Some(old) if old != versions => println!(
+                    "  {name} ({} -> {})",
+                    old.iter().cloned().collect::<Vec<_>>().join(", "),
+                    versions.iter().cloned().collect::<Vec<_>>().join(", ")
+                ),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The versions of the packages in a `Cargo.lock`, a package can appear in several versions.
+fn cargo_lock_versions(lock_file: &Path) -> BTreeMap<String, BTreeSet<String>> {
+    let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let Some(lock) = fs::read_to_string(lock_file)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+    else {
+        return versions;
+    };
+
+    let packages = lock.get("package").and_then(|packages| packages.as_array());
+    for package in packages.into_iter().flatten() {
+        if let (Some(name), Some(version)) = (
+            package.get("name").and_then(|name| name.as_str()),
+            package.get("version").and_then(|version| version.as_str()),
+        ) {
+            versions
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+        }
+    }
+
+    versions
+}
+
+/// The crates `cargo install-update --list` reports as needing an update, as `name (installed -> latest)`.
+fn cargo_outdated_crates(list: &str) -> Vec<String> {
+    // Package         Installed  Latest   Needs update
+    // cargo-update    v13.4.0    v14.0.0  Yes
+    list.lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, installed, latest, "Yes"] => Some(format!("{name} ({installed} -> {latest})")),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     let cargo_dir = env::var_os("CARGO_HOME")
         .map(PathBuf::from)
@@ -67,10 +371,49 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
         }
     };
 
-    ctx.run_type()
-        .execute(cargo_update)
-        .args(["install-update", "--git", "--all"])
-        .status_checked()?;
+    let git = ctx.config().cargo_update_git();
+    let mut args = vec!["install-update"];
+    if git {
+        args.push("--git");
+    }
+
+    if ctx.config().metadata_only() {
+        // `--list` refreshes the registry index and only checks which packages need an update
+        args.push("--list");
+        let output = Command::new(&cargo_update).args(&args).output_checked_utf8()?;
+        let outdated = cargo_outdated_crates(&output.stdout);
+        if outdated.is_empty() {
+            println!("The cargo crates are up to date");
+        } else {
+            println!("Outdated cargo crates:");
+            for outdated in outdated {
+                println!("  {outdated}");
+            }
+        }
+        return Ok(());
+    } else if ctx.run_type().dry() {
+        // `--list` only checks which packages need an update
+        args.push("--list");
+        Command::new(&cargo_update).args(&args).status_checked()?;
+    } else {
+        let git_crates_before = cargo_git_crates(&toml_file);
+        args.push("--all");
+        ctx.run_type().execute(&cargo_update).args(&args).status_checked()?;
+
+        if git {
+            let updated: Vec<String> = cargo_git_crates(&toml_file)
+                .into_iter()
+                .filter_map(|(name, commit)| match git_crates_before.get(&name) {
+                    Some(previous) if *previous == commit => None,
+                    Some(previous) => Some(format!("{name} ({previous} -> {commit})")),
+                    None => Some(format!("{name} ({commit})")),
+                })
+                .collect();
+            if !updated.is_empty() {
+                println!("Updated git crates: {}", updated.join(", "));
+            }
+        }
+    }
 
     if ctx.config().cleanup() {
         let cargo_cache = require("cargo-cache")
@@ -90,28 +433,150 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The global dart packages with their versions, from `dart pub global list`.
+///
+/// Each line is `<package> <version>`, followed by the source for packages that weren't activated
+/// from pub.dev, which can't be upgraded by activating them again.
+fn dart_global_packages(list: &str) -> Vec<(String, String)> {
+    list.lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [package, version] => Some((package.to_string(), version.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn run_flutter_upgrade(ctx: &ExecutionContext) -> Result<()> {
-    let flutter = require("flutter")?;
+    let flutter = which("flutter");
+    let dart = which("dart");
+    if flutter.is_none() && dart.is_none() {
+        return Err(SkipStep(String::from("Neither flutter nor dart is installed")).into());
+    }
 
     print_separator("Flutter");
-    ctx.run_type().execute(flutter).arg("upgrade").status_checked()
+
+    if let Some(flutter) = flutter {
+        if let Some(channel) = ctx.config().flutter_channel() {
+            ctx.run_type()
+                .execute(&flutter)
+                .args(["channel", channel])
+                .status_checked()?;
+        }
+
+        if ctx.run_type().dry() {
+            // `--verify-only` only checks for a newer version, so it is safe to really run it
+            Command::new(&flutter)
+                .args(["upgrade", "--verify-only"])
+                .status_checked()?;
+        } else {
+            ctx.run_type().execute(&flutter).arg("upgrade").status_checked()?;
+        }
+    }
+
+    if let Some(dart) = dart {
+        let list = || -> Result<Vec<(String, String)>> {
+            let output = Command::new(&dart)
+                .args(["pub", "global", "list"])
+                .output_checked_utf8()?;
+            Ok(dart_global_packages(&output.stdout))
+        };
+        let packages = list()?;
+
+        if packages.is_empty() {
+            println!("No global dart packages installed");
+        } else {
+            for (package, _) in &packages {
+                ctx.run_type()
+                    .execute(&dart)
+                    .args(["pub", "global", "activate", package])
+                    .status_checked()?;
+            }
+
+            if !ctx.run_type().dry() {
+                let upgraded: Vec<String> = list()?
+                    .into_iter()
+                    .filter(|(package, version)| {
+                        packages
+                            .iter()
+                            .any(|(before, previous)| before == package && previous != version)
+                    })
+                    .map(|(package, version)| format!("{package} {version}"))
+                    .collect();
+                if upgraded.is_empty() {
+                    println!("The global dart packages are up to date");
+                } else {
+                    println!("Upgraded global dart packages: {}", upgraded.join(", "));
+                }
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub fn run_gem(ctx: &ExecutionContext) -> Result<()> {
     let gem = require("gem")?;
-    HOME_DIR.join(".gem").require()?;
+    let user_install = match ctx.config().gem_user_install() {
+        Some(user_install) => user_install,
+        None => {
+            let rbenv = env::var_os("RBENV_SHELL").is_some();
+            if rbenv {
+                debug!("Detected rbenv. Avoiding --user-install");
+            }
+            !rbenv
+        }
+    };
+    if user_install || ctx.config().gem_user_install().is_none() {
+        HOME_DIR.join(".gem").require()?;
+    }
+
+    // Gems of a Ruby version manager belong to the user even without `--user-install`
+    let managed = ["asdf", ".rbenv", ".rvm"]
+        .iter()
+        .any(|manager| gem.to_string_lossy().contains(manager));
+    let sudo = if ctx.config().gem_user_install() == Some(false) && !managed {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    } else {
+        None
+    };
 
     print_separator("Gems");
 
-    let mut command = ctx.run_type().execute(gem);
+    let outdated: Vec<String> = Command::new(&gem)
+        .arg("outdated")
+        .output_checked_utf8()
+        .map(|output| output.stdout.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let mut command = match sudo {
+        Some(sudo) => {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(&gem);
+            command
+        }
+        None => ctx.run_type().execute(&gem),
+    };
     command.arg("update");
-
-    if env::var_os("RBENV_SHELL").is_none() {
-        debug!("Detected rbenv. Avoiding --user-install");
+    if user_install {
         command.arg("--user-install");
     }
+    command.status_checked()?;
 
-    command.status_checked()
+    if !outdated.is_empty() {
+        println!(
+            "{}",
+            if ctx.run_type().dry() {
+                "Gems that would be upgraded:"
+            } else {
+                "Upgraded gems:"
+            }
+        );
+        for gem in outdated {
+            println!("  {gem}");
+        }
+    }
+
+    Ok(())
 }
 
 pub fn run_rubygems(ctx: &ExecutionContext) -> Result<()> {
@@ -167,15 +632,149 @@ pub fn run_haxelib_update(ctx: &ExecutionContext) -> Result<()> {
     command.arg("update").status_checked()
 }
 
+/// The git repositories of the plugins sheldon manages, with their checked out commit.
+fn sheldon_plugin_revisions() -> BTreeMap<PathBuf, String> {
+    let data_dir = env::var_os("SHELDON_DATA_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_DATA_HOME").map(|dir| PathBuf::from(dir).join("sheldon")))
+        .unwrap_or_else(|| HOME_DIR.join(".local/share/sheldon"));
+
+    // The repositories are cloned to `repos/<host>/<owner>/<name>`
+    walkdir::WalkDir::new(data_dir.join("repos"))
+        .min_depth(3)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let revision = Command::new("git")
+                .arg("-C")
+                .arg(entry.path())
+                .args(["rev-parse", "HEAD"])
+                .output_checked_utf8()
+                .ok()?;
+            Some((entry.into_path(), revision.stdout.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The name of a plugin repository, `<owner>/<name>`.
+fn sheldon_plugin_name(repo: &Path) -> String {
+    let mut components = repo.components().rev().take(2).collect::<Vec<_>>();
+    components.reverse();
+    components
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 pub fn run_sheldon(ctx: &ExecutionContext) -> Result<()> {
     let sheldon = require("sheldon")?;
 
     print_separator("Sheldon");
 
+    let before = sheldon_plugin_revisions();
     ctx.run_type()
-        .execute(sheldon)
+        .execute(&sheldon)
         .args(["lock", "--update"])
-        .status_checked()
+        .status_checked()?;
+
+    if ctx.run_type().dry() {
+        // Compare with the upstream commits without fetching, the lock file is left as is
+        for (repo, revision) in &before {
+            let latest = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["ls-remote", "origin", "HEAD"])
+                .output_checked_utf8();
+            match latest {
+                Ok(latest) if !latest.stdout.starts_with(revision.as_str()) => {
+                    println!("{} would be updated", sheldon_plugin_name(repo))
+                }
+                Ok(_) => (),
+                Err(_) => println!("{}: could not check for updates", sheldon_plugin_name(repo)),
+            }
+        }
+        return Ok(());
+    }
+
+    let updated: Vec<String> = sheldon_plugin_revisions()
+        .into_iter()
+        .filter(|(repo, revision)| before.get(repo).is_some_and(|old| old != revision))
+        .map(|(repo, _)| sheldon_plugin_name(&repo))
+        .collect();
+    if updated.is_empty() {
+        println!("The plugins are up to date");
+    } else {
+        println!("Updated plugins: {}", updated.join(", "));
+    }
+
+    Ok(())
+}
+
+pub fn run_zellij_plugins(ctx: &ExecutionContext) -> Result<()> {
+    require("zellij")?;
+    let plugins = ctx.config().zellij_plugins();
+    if plugins.is_empty() {
+        return Err(SkipStep(String::from("No zellij_plugins configured")).into());
+    }
+    let curl = require("curl")?;
+    let dir = env::var_os("ZELLIJ_CONFIG_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| HOME_DIR.join(".config/zellij"))
+        .join("plugins");
+
+    print_separator("zellij plugins");
+
+    let mut failed = false;
+    for url in plugins {
+        let Some(name) = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.rsplit('/').next())
+            .filter(|name| !name.is_empty() && url.contains("://"))
+        else {
+            print_warning(format!("{url} is not a plugin URL"));
+            failed = true;
+            continue;
+        };
+        let path = dir.join(name);
+
+        if ctx.run_type().dry() {
+            println!("Would fetch {url} to {}", path.display());
+            continue;
+        }
+
+        fs::create_dir_all(&dir)?;
+        let partial = dir.join(format!("{name}.part"));
+        let fetched = ctx
+            .run_type()
+            .execute(&curl)
+            .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+            .arg(&partial)
+            .arg(url)
+            .status_checked();
+        if let Err(e) = fetched {
+            print_warning(format!("Failed to fetch {url}: {e}"));
+            fs::remove_file(&partial).ok();
+            failed = true;
+            continue;
+        }
+
+        if fs::read(&path).ok() == fs::read(&partial).ok() {
+            fs::remove_file(&partial)?;
+            println!("{name} is up to date");
+        } else {
+            fs::rename(&partial, &path)?;
+            println!("{name} updated");
+        }
+    }
+
+    if failed {
+        Err(eyre!("Some zellij plugins could not be fetched"))
+    } else {
+        Ok(())
+    }
 }
 
 pub fn run_fossil(ctx: &ExecutionContext) -> Result<()> {
@@ -227,14 +826,264 @@ pub fn run_rustup(ctx: &ExecutionContext) -> Result<()> {
     let rustup = require("rustup")?;
 
     print_separator("rustup");
-    ctx.run_type().execute(rustup).arg("update").status_checked()
+
+    let version = || -> Result<String> {
+        // rustup 1.27.1 (54dd3d00f 2024-04-24)
+        let output = Command::new(&rustup).arg("--version").output_checked_utf8()?;
+        Ok(output.stdout.split_whitespace().nth(1).unwrap_or_default().to_string())
+    };
+    let version_before = version()?;
+
+    if ctx.config().rustup_self_update() {
+        // rustup installed by a package manager can't update itself
+        if let Err(e) = ctx
+            .run_type()
+            .execute(&rustup)
+            .args(["self", "update"])
+            .status_checked()
+        {
+            print_warning(format!("rustup failed to update itself: {e}"));
+        }
+    }
+
+    ctx.run_type().execute(&rustup).arg("update").status_checked()?;
+
+    // Make sure the rust-analyzer proxy has a component to run with the updated toolchain. The
+    // components can't be listed without a default toolchain, there is no rust-analyzer then.
+    let rust_analyzer_installed = Command::new(&rustup)
+        .args(["component", "list", "--installed"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .any(|component| component.starts_with("rust-analyzer"))
+        })
+        .unwrap_or(false);
+    if rust_analyzer_installed {
+        ctx.run_type()
+            .execute(&rustup)
+            .args(["component", "add", "rust-analyzer"])
+            .status_checked()?;
+        if !ctx.run_type().dry() {
+            Command::new(&rustup)
+                .args(["which", "rust-analyzer"])
+                .output_checked_utf8()
+                .with_context(|| "rust-analyzer is not available for the default toolchain")?;
+        }
+    }
+
+    let version_after = version()?;
+    if version_before == version_after {
+        println!("rustup {version_after} is up to date");
+    } else {
+        println!("rustup updated from {version_before} to {version_after}");
+    }
+
+    Ok(())
 }
 
 pub fn run_rye(ctx: &ExecutionContext) -> Result<()> {
     let rye = require("rye")?;
+    let projects = ctx.config().rye_projects();
+    if !ctx.config().rye_self_update() && projects.is_empty() {
+        return Err(SkipStep(String::from("Rye self update is disabled and no project is configured")).into());
+    }
 
     print_separator("Rye");
-    ctx.run_type().execute(rye).args(["self", "update"]).status_checked()
+
+    if ctx.config().rye_self_update() {
+        ctx.run_type().execute(&rye).args(["self", "update"]).status_checked()?;
+    }
+
+    for project in projects {
+        if !project.join("pyproject.toml").exists() {
+            print_warning(format!("{} is not a Rye project", project.display()));
+            continue;
+        }
+
+        println!("Syncing {}", project.display());
+        let lock_file = project.join("requirements.lock");
+        let before = rye_locked_versions(&lock_file);
+        ctx.run_type()
+            .execute(&rye)
+            .args(["sync", "--update-all"])
+            .current_dir(&project)
+            .status_checked()?;
+
+        if ctx.run_type().dry() {
+            // Rye can't tell what a sync would change without doing it
+            println!(
+                "  The {} locked packages would be updated to their latest versions",
+                before.len()
+            );
+            continue;
+        }
+
+        let after = rye_locked_versions(&lock_file);
+        for (name, version) in &after {
+            match before.get(name) {
+                Some(old) if old != version => println!("  {name} ({old} -> {version})"),
+                None => println!("  {name} ({version}, added)"),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The `name==version` pins of a Rye lock file.
+fn rye_locked_versions(lock_file: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(lock_file)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.trim().split_once("=="))
+        .filter_map(|(name, version)| Some((name.to_string(), version.split_whitespace().next()?.to_string())))
+        .collect()
+}
+
+pub fn run_pixi(ctx: &ExecutionContext) -> Result<()> {
+    let pixi = require("pixi")?;
+    let projects = ctx.config().pixi_projects();
+    if !ctx.config().pixi_self_update() && projects.is_empty() {
+        return Err(SkipStep(String::from(
+            "pixi self-update is disabled and no project is configured",
+        ))
+        .into());
+    }
+
+    print_separator("pixi");
+
+    if ctx.config().pixi_self_update() {
+        ctx.run_type().execute(&pixi).arg("self-update").status_checked()?;
+    }
+
+    for project in projects {
+        if !project.join("pixi.toml").exists() && !project.join("pyproject.toml").exists() {
+            print_warning(format!("{} is not a pixi project", project.display()));
+            continue;
+        }
+
+        println!("Updating {}", project.display());
+        if ctx.run_type().dry() {
+            // `--dry-run` shows the changes without writing the lock file
+            RunType::Wet
+                .execute(&pixi)
+                .args(["update", "--dry-run"])
+                .current_dir(&project)
+                .status_checked()?;
+            continue;
+        }
+
+        let lock_file = project.join("pixi.lock");
+        let before = pixi_locked_versions(&lock_file);
+        ctx.run_type()
+            .execute(&pixi)
+            .arg("update")
+            .current_dir(&project)
+            .status_checked()?;
+
+        for (name, version) in &pixi_locked_versions(&lock_file) {
+            match before.get(name) {
+                Some(old) if old != version => println!("  {name} ({old} -> {version})"),
+                None => println!("  {name} ({version}, added)"),
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The package versions of a pixi lock file, for all the platforms.
+fn pixi_locked_versions(lock_file: &Path) -> BTreeMap<String, String> {
+    let mut versions = BTreeMap::new();
+    let mut name = None;
+    for line in fs::read_to_string(lock_file).unwrap_or_default().lines() {
+        let line = line.trim_start_matches([' ', '-']);
+        if let Some(url) = line.strip_prefix("conda: ") {
+            // https://conda.anaconda.org/conda-forge/linux-64/<name>-<version>-<build>.conda
+            let file = url.rsplit('/').next().unwrap_or_default();
+            let file = file
+                .strip_suffix(".conda")
+                .or_else(|| file.strip_suffix(".tar.bz2"))
+                .unwrap_or(file);
+            let mut parts = file.rsplitn(3, '-');
+            if let (Some(_build), Some(version), Some(package)) = (parts.next(), parts.next(), parts.next()) {
+                versions.insert(package.to_string(), version.to_string());
+            }
+        } else if let Some(package) = line.strip_prefix("name: ") {
+            name = Some(package.trim().to_string());
+        } else if let Some(version) = line.strip_prefix("version: ") {
+            if let Some(package) = name.take() {
+                versions.insert(package, version.trim().to_string());
+            }
+        }
+    }
+    versions
+}
+
+pub fn run_restic(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().restic_self_update() {
+        return Err(SkipStep(String::from("`restic_self_update` is not enabled")).into());
+    }
+    let restic = require("restic")?;
+    // Packaged builds leave the `self-update` command out
+    if Command::new(&restic)
+        .args(["self-update", "--help"])
+        .output_checked()
+        .is_err()
+    {
+        return Err(SkipStep(String::from("restic was built without `self-update`")).into());
+    }
+
+    print_separator("restic");
+
+    // Only the binary is updated, the repositories are never touched
+    ctx.run_type().execute(&restic).arg("self-update").status_checked()
+}
+
+pub fn run_just_update(ctx: &ExecutionContext) -> Result<()> {
+    let Some(recipe) = ctx.config().just_update_recipe() else {
+        return Err(SkipStep(String::from("`just_update_recipe` is not set")).into());
+    };
+    // ujust ships its own justfile, plain just uses the global one
+    let (just, justfile_args): (PathBuf, &[&str]) = match which("ujust") {
+        Some(ujust) => (ujust, &[]),
+        None => (require("just")?, &["--global-justfile"]),
+    };
+
+    let recipes = Command::new(&just)
+        .args(justfile_args)
+        .arg("--summary")
+        .output_checked_utf8()
+        .map(|output| output.stdout)
+        .unwrap_or_default();
+    if !recipes.split_whitespace().any(|name| name == recipe) {
+        return Err(SkipStep(format!("{} has no `{recipe}` recipe", just.display())).into());
+    }
+
+    print_separator(format!(
+        "{} {recipe}",
+        just.file_name().unwrap_or_default().to_string_lossy()
+    ));
+
+    if ctx.run_type().dry() {
+        // `--dry-run` prints the commands of the recipe without running them
+        return RunType::Wet
+            .execute(&just)
+            .args(justfile_args)
+            .args(["--dry-run", recipe])
+            .status_checked();
+    }
+
+    ctx.run_type()
+        .execute(&just)
+        .args(justfile_args)
+        .arg(recipe)
+        .status_checked()
 }
 
 pub fn run_elan(ctx: &ExecutionContext) -> Result<()> {
@@ -273,12 +1122,315 @@ pub fn run_choosenim(ctx: &ExecutionContext) -> Result<()> {
     run_type.execute(&choosenim).args(["update", "stable"]).status_checked()
 }
 
+/// Pull a repository of `kubectl_manifests`, show what changes in the cluster and apply it.
+pub fn run_kubectl_manifests(ctx: &ExecutionContext, manifests: &KubectlManifests) -> Result<()> {
+    let kubectl = require("kubectl")?;
+    let path = PathBuf::from(shellexpand::tilde(&manifests.path).as_ref()).require()?;
+    let context = manifests.context.as_str();
+
+    // Applying to another cluster than the intended one could break it
+    let contexts = Command::new(&kubectl)
+        .args(["config", "get-contexts", "--output=name"])
+        .output_checked_utf8()?
+        .stdout;
+    if !contexts.lines().any(|name| name.trim() == context) {
+        return Err(eyre!("The kubectl context {context} doesn't exist"));
+    }
+
+    print_separator(format!("kubectl manifests ({context})"));
+
+    if path.join(".git").exists() {
+        let git = require("git")?;
+        crate::steps::git::require_clean_tree(&git, &path)?;
+        ctx.run_type()
+            .execute(&git)
+            .current_dir(&path)
+            .args(["pull", "--ff-only"])
+            .status_checked()?;
+    }
+
+    // `kubectl diff` compares with a server-side dry run, it exits with 1 when something changes
+    let diff = Command::new(&kubectl)
+        .arg("--context")
+        .arg(context)
+        .args(["diff", "--recursive", "--filename"])
+        .arg(&path)
+        .output_checked_with_utf8(|output| {
+            if matches!(output.status.code(), Some(0 | 1)) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })?;
+    if diff.stdout.trim().is_empty() {
+        println!("The cluster already matches the manifests");
+        return Ok(());
+    }
+    print!("{}", diff.stdout);
+
+    ctx.run_type()
+        .execute(&kubectl)
+        .arg("--context")
+        .arg(context)
+        .args(["apply", "--recursive", "--filename"])
+        .arg(&path)
+        .status_checked()
+}
+
+/// The version of each provider of a Terraform lock file.
+fn terraform_locked_providers(lock_file: &str) -> BTreeMap<String, String> {
+    // provider "registry.terraform.io/hashicorp/aws" {
+    //   version     = "5.31.0"
+    let mut providers = BTreeMap::new();
+    let mut provider = None;
+    for line in lock_file.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("provider \"") {
+            provider = name.split('"').next().map(String::from);
+        } else if let Some(version) = line.strip_prefix("version") {
+            if let Some(name) = provider.take() {
+                let version = version.trim_start().trim_start_matches('=').trim().trim_matches('"');
+                providers.insert(name, version.to_string());
+            }
+        }
+    }
+    providers
+}
+
+/// Upgrade the providers of a Terraform project of `terraform_projects`, and list the ones
+/// which advanced in its lock file.
+pub fn run_terraform_project(ctx: &ExecutionContext, project: &Path) -> Result<()> {
+    let binary = match ctx.config().terraform_flavor() {
+        TerraformFlavor::Terraform => "terraform",
+        TerraformFlavor::OpenTofu => "tofu",
+    };
+    let terraform = require(binary)?;
+    let project = project.require()?;
+
+    if project.join(".terraform.tfstate.lock.info").exists() {
+        return Err(SkipStep(format!("The state of {} is locked", project.display())).into());
+    }
+    let lock_path = project.join(".terraform.lock.hcl");
+    if lock_path.exists() {
+        if let Some(git) = which("git") {
+            let changes = Command::new(git)
+                .current_dir(project)
+                .args(["status", "--porcelain", "--", ".terraform.lock.hcl"])
+                .output_checked_utf8()
+                .map(|output| output.stdout)
+                .unwrap_or_default();
+            if !changes.trim().is_empty() {
+                return Err(SkipStep(format!(
+                    "The lock file of {} has uncommitted changes",
+                    project.display()
+                ))
+                .into());
+            }
+        }
+    }
+
+    print_separator(format!("{binary} providers ({})", project.display()));
+
+    let lock_before = fs::read_to_string(&lock_path).ok();
+    // The upgrade also runs on dry runs to find the newer providers. The providers and modules are
+    // then downloaded to a scratch data directory without touching the backend, and the lock file
+    // is restored right after it.
+    let scratch = if ctx.run_type().dry() {
+        Some(tempfile::tempdir()?)
+    } else {
+        None
+    };
+    let mut command = match &scratch {
+        Some(scratch) => {
+            let mut command = RunType::Wet.execute(&terraform);
+            command.env("TF_DATA_DIR", scratch.path());
+            command
+        }
+        None => ctx.run_type().execute(&terraform),
+    };
+    command.current_dir(project).args(["init", "-upgrade", "-input=false"]);
+    if scratch.is_some() {
+        command.arg("-backend=false");
+    }
+    let result = command.status_checked();
+    let lock_after = fs::read_to_string(&lock_path).ok();
+    if ctx.run_type().dry() {
+        match &lock_before {
+            Some(contents) => fs::write(&lock_path, contents)?,
+            None if lock_after.is_some() => fs::remove_file(&lock_path)?,
+            None => (),
+        }
+    }
+    result?;
+
+    let before = terraform_locked_providers(lock_before.as_deref().unwrap_or_default());
+    let after = terraform_locked_providers(lock_after.as_deref().unwrap_or_default());
+    let advanced: Vec<String> = after
+        .iter()
+        .filter_map(|(provider, version)| match before.get(provider) {
+            Some(old) if old != version => Some(format!("{provider} ({old} -> {version})")),
+            None => Some(format!("{provider} ({version})")),
+            _ => None,
+        })
+        .collect();
+    let verb = if ctx.run_type().dry() {
+        "Would upgrade"
+    } else {
+        "Upgraded"
+    };
+    if advanced.is_empty() {
+        println!("The providers are up to date");
+    } else {
+        println!("{verb} {}", advanced.join(", "));
+    }
+
+    Ok(())
+}
+
+/// The option of `ansible-galaxy <kind>` selecting the installation directory.
+fn ansible_galaxy_path_arg(kind: &str) -> &'static str {
+    if kind == "collection" {
+        "--collections-path"
+    } else {
+        "--roles-path"
+    }
+}
+
+/// The version of each installed Ansible Galaxy `kind` (`role` or `collection`), only in `path`
+/// when it is set. A name installed in several paths has the version ansible uses, the first one.
+fn ansible_galaxy_installed(galaxy: &Path, kind: &str, path: Option<&Path>) -> BTreeMap<String, String> {
+    // # /home/user/.ansible/collections/ansible_collections
+    // Collection        Version
+    // ----------------- -------
+    // community.general 8.1.0
+    //
+    // # /home/user/.ansible/roles
+    // - geerlingguy.docker, 7.0.2
+    let mut command = Command::new(galaxy);
+    command.args([kind, "list"]);
+    if let Some(path) = path {
+        command.arg(ansible_galaxy_path_arg(kind)).arg(path);
+    }
+    let output = command
+        .output_checked_utf8()
+        .map(|output| output.stdout)
+        .unwrap_or_default();
+
+    let mut installed = BTreeMap::new();
+    let mut in_path = path.is_none();
+    for line in output.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix("# ") {
+            in_path = path.is_none_or(|path| header.starts_with(&*path.to_string_lossy()));
+            continue;
+        }
+        if !in_path {
+            continue;
+        }
+        let entry = match line.strip_prefix("- ") {
+            Some(role) => role.split_once(", "),
+            None => line.split_once(char::is_whitespace),
+        };
+        if let Some((name, version)) = entry {
+            if name != "Collection" && !name.starts_with('-') {
+                installed
+                    .entry(name.trim().to_string())
+                    .or_insert_with(|| version.trim().to_string());
+            }
+        }
+    }
+    installed
+}
+
+/// Upgrade the Ansible Galaxy roles and collections of `ansible_galaxy_requirements`.
+pub fn run_ansible_galaxy(ctx: &ExecutionContext) -> Result<()> {
+    let galaxy = require("ansible-galaxy")?;
+    let requirements = ctx.config().ansible_galaxy_requirements().require()?;
+
+    print_separator("ansible-galaxy");
+
+    let mut changes = Vec::new();
+    for kind in ["collection", "role"] {
+        let before = ansible_galaxy_installed(&galaxy, kind, None);
+        let upgrade = |command: &mut Executor| {
+            command.args([kind, "install", "-r"]).arg(&requirements);
+            if kind == "collection" {
+                command.arg("--upgrade");
+            } else {
+                command.arg("--force");
+            }
+        };
+
+        let after = if ctx.run_type().dry() {
+            // Installing into a temporary directory finds the newer versions without replacing
+            // the installed ones
+            let preview = tempfile::tempdir()?;
+            let mut command = RunType::Wet.execute(&galaxy);
+            upgrade(&mut command);
+            command
+                .arg(ansible_galaxy_path_arg(kind))
+                .arg(preview.path())
+                .output_checked()?;
+            ansible_galaxy_installed(&galaxy, kind, Some(preview.path()))
+        } else {
+            let mut command = ctx.run_type().execute(&galaxy);
+            upgrade(&mut command);
+            command.status_checked()?;
+            ansible_galaxy_installed(&galaxy, kind, None)
+        };
+
+        changes.extend(after.iter().filter_map(|(name, version)| match before.get(name) {
+            Some(old) if old != version => Some(format!("{name} ({old} -> {version})")),
+            None if !ctx.run_type().dry() => Some(format!("{name} ({version})")),
+            _ => None,
+        }));
+    }
+
+    let verb = if ctx.run_type().dry() {
+        "Would update"
+    } else {
+        "Updated"
+    };
+    if changes.is_empty() {
+        println!("The roles and collections are up to date");
+    } else {
+        println!("{verb} {}", changes.join(", "));
+    }
+
+    Ok(())
+}
+
 pub fn run_krew_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let krew = require("kubectl-krew")?;
 
     print_separator("Krew");
 
-    ctx.run_type().execute(krew).args(["upgrade"]).status_checked()
+    if ctx.run_type().dry() {
+        // Refreshing the plugin index is harmless, it also prints the plugins that can be upgraded.
+        let output = Command::new(&krew).arg("update").output_checked_utf8()?;
+        print!("{}{}", output.stdout, output.stderr);
+        return ctx.run_type().execute(&krew).arg("upgrade").status_checked();
+    }
+
+    ctx.run_type().execute(&krew).arg("update").status_checked()?;
+
+    let output = Command::new(&krew).arg("upgrade").output_checked_utf8()?;
+    // krew writes its progress messages to stderr
+    print!("{}{}", output.stdout, output.stderr);
+
+    let upgraded: Vec<&str> = output
+        .stderr
+        .lines()
+        .chain(output.stdout.lines())
+        .filter_map(|line| line.trim().strip_prefix("Upgraded plugin:"))
+        .map(str::trim)
+        .collect();
+
+    if upgraded.is_empty() {
+        println!("No plugins upgraded");
+    } else {
+        println!("Upgraded plugins: {}", upgraded.join(", "));
+    }
+
+    Ok(())
 }
 
 pub fn run_gcloud_components_update(ctx: &ExecutionContext) -> Result<()> {
@@ -322,12 +1474,44 @@ pub fn run_opam_update(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.run_type().execute(&opam).arg("update").status_checked()?;
 
-    let mut command = ctx.run_type().execute(&opam);
-    command.arg("upgrade");
-    if ctx.config().yes(Step::Opam) {
-        command.arg("--yes");
+    let switches: Vec<Option<String>> = if ctx.config().opam_all_switches() {
+        Command::new(&opam)
+            .args(["switch", "list", "--short"])
+            .output_checked_utf8()?
+            .stdout
+            .lines()
+            .map(|switch| Some(switch.trim().to_string()))
+            .collect()
+    } else {
+        // The current switch
+        vec![None]
+    };
+
+    for switch in switches {
+        if let Some(switch) = &switch {
+            println!("Upgrading switch {switch}");
+        }
+
+        if ctx.run_type().dry() {
+            // opam can simulate the upgrade by itself
+            let mut command = Command::new(&opam);
+            command.args(["upgrade", "--dry-run", "--yes"]);
+            if let Some(switch) = &switch {
+                command.args(["--switch", switch]);
+            }
+            command.status_checked()?;
+        } else {
+            let mut command = ctx.run_type().execute(&opam);
+            command.arg("upgrade");
+            if let Some(switch) = &switch {
+                command.args(["--switch", switch]);
+            }
+            if ctx.config().yes(Step::Opam) {
+                command.arg("--yes");
+            }
+            command.status_checked()?;
+        }
     }
-    command.status_checked()?;
 
     if ctx.config().cleanup() {
         ctx.run_type().execute(&opam).arg("clean").status_checked()?;
@@ -346,16 +1530,212 @@ pub fn run_vcpkg_update(ctx: &ExecutionContext) -> Result<()> {
     #[cfg(not(unix))]
     let is_root_install = false;
 
-    let mut command = if is_root_install {
-        ctx.run_type().execute(&vcpkg)
+    let sudo = if is_root_install {
+        None
     } else {
-        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-        let mut c = ctx.run_type().execute(sudo);
-        c.arg(&vcpkg);
-        c
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    };
+    let execute = |program: &Path| match sudo {
+        Some(sudo) => {
+            let mut c = ctx.run_type().execute(sudo);
+            c.arg(program);
+            c
+        }
+        None => ctx.run_type().execute(program),
     };
 
-    command.args(["upgrade", "--no-dry-run"]).status_checked()
+    // The ports come from the git checkout vcpkg is installed in
+    let root = env::var_os("VCPKG_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| vcpkg.canonicalize().ok()?.parent().map(Path::to_path_buf));
+    if let Some(root) = root.filter(|root| root.join(".git").exists()) {
+        let git = require("git")?;
+        execute(&git)
+            .args(["pull", "--ff-only"])
+            .current_dir(&root)
+            .status_checked()?;
+    }
+
+    vcpkg_upgrade(ctx, &vcpkg, None, execute(&vcpkg))?;
+
+    for project in ctx.config().vcpkg_projects() {
+        if !project.join("vcpkg.json").exists() {
+            print_warning(format!("{} has no vcpkg.json", project.display()));
+            continue;
+        }
+        println!("Upgrading {}", project.display());
+        vcpkg_upgrade(ctx, &vcpkg, Some(&project), ctx.run_type().execute(&vcpkg))?;
+    }
+
+    Ok(())
+}
+
+/// The outdated ports listed by `vcpkg upgrade`, which doesn't change anything without `--no-dry-run`.
+fn vcpkg_outdated(vcpkg: &Path, project: Option<&Path>) -> Result<Vec<String>> {
+    let mut command = Command::new(vcpkg);
+    command.arg("upgrade");
+    if let Some(project) = project {
+        command.current_dir(project);
+    }
+    // zlib:x64-linux    1.2.13 -> 1.3.1
+    Ok(command
+        .output_checked_utf8()?
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.contains(" -> "))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect())
+}
+
+fn vcpkg_upgrade(ctx: &ExecutionContext, vcpkg: &Path, project: Option<&Path>, mut command: Executor) -> Result<()> {
+    let outdated = vcpkg_outdated(vcpkg, project)?;
+    if outdated.is_empty() {
+        println!("The ports are up to date");
+        return Ok(());
+    }
+
+    if ctx.run_type().dry() {
+        println!("Outdated ports:");
+    } else {
+        if let Some(project) = project {
+            command.current_dir(project);
+        }
+        command.args(["upgrade", "--no-dry-run"]).status_checked()?;
+        println!("Upgraded ports:");
+    }
+    for port in outdated {
+        println!("  {port}");
+    }
+
+    Ok(())
+}
+
+/// The package references in the Conan cache.
+fn conan_cached_references(conan: &Path) -> BTreeSet<String> {
+    Command::new(conan)
+        .args(["list", "*"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .map(str::trim)
+                .filter(|line| line.contains('/'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn run_conan(ctx: &ExecutionContext) -> Result<()> {
+    let conan = require("conan")?;
+    let projects = ctx.config().conan_projects();
+    if projects.is_empty() {
+        return Err(SkipStep(String::from("No `conan_projects` are configured")).into());
+    }
+
+    print_separator("Conan");
+
+    let before = conan_cached_references(&conan);
+    for project in projects {
+        if !project.join("conanfile.py").exists() && !project.join("conanfile.txt").exists() {
+            print_warning(format!("{} has no conanfile", project.display()));
+            continue;
+        }
+        println!("Updating {}", project.display());
+        ctx.run_type()
+            .execute(&conan)
+            .args(["install", ".", "--update"])
+            .current_dir(&project)
+            .status_checked()?;
+    }
+
+    if !ctx.run_type().dry() {
+        let new: Vec<_> = conan_cached_references(&conan).difference(&before).cloned().collect();
+        if new.is_empty() {
+            println!("The dependencies are up to date");
+        } else {
+            println!("New in the Conan cache:");
+            for reference in new {
+                println!("  {reference}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The installed specs of a Spack environment, as `name@version/hash`.
+fn spack_installed(spack: &Path, environment: &str) -> BTreeSet<String> {
+    Command::new(spack)
+        .args(["-e", environment, "find", "--format", "{name}@{version}/{hash:7}"])
+        .output_checked_utf8()
+        .map(|output| output.stdout.lines().map(|line| line.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+pub fn run_spack(ctx: &ExecutionContext) -> Result<()> {
+    let spack = require("spack")?;
+    let environments = ctx.config().spack_environments();
+    if environments.is_empty() {
+        return Err(SkipStep(String::from("No `spack_environments` are configured")).into());
+    }
+
+    print_separator("Spack");
+
+    // The package repositories kept in their own git checkout, Spack's own checkout is left alone
+    let spack_root = Command::new(&spack)
+        .args(["location", "--spack-root"])
+        .output_checked_utf8()
+        .map(|output| PathBuf::from(output.stdout.trim()))
+        .ok();
+    let repos = Command::new(&spack)
+        .args(["repo", "list"])
+        .output_checked_utf8()?
+        .stdout;
+    let git = require("git")?;
+    for path in repos.lines().filter_map(|line| line.split_whitespace().last()) {
+        let Ok(output) = Command::new(&git)
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(path)
+            .output_checked_utf8()
+        else {
+            continue;
+        };
+        let top_level = PathBuf::from(output.stdout.trim());
+        if spack_root.as_ref() == Some(&top_level) {
+            continue;
+        }
+        println!("Pulling {}", top_level.display());
+        ctx.run_type()
+            .execute(&git)
+            .args(["pull", "--ff-only"])
+            .current_dir(&top_level)
+            .status_checked()?;
+    }
+
+    for environment in environments {
+        println!("Updating the {environment} environment");
+        let before = spack_installed(&spack, environment);
+        // `--fresh` picks the newest versions instead of reusing the installed ones
+        ctx.run_type()
+            .execute(&spack)
+            .args(["-e", environment, "concretize", "--fresh", "--force"])
+            .status_checked()?;
+        ctx.run_type()
+            .execute(&spack)
+            .args(["-e", environment, "install"])
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            for spec in spack_installed(&spack, environment).difference(&before) {
+                println!("  {spec}");
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub fn run_vscode_extensions_update(ctx: &ExecutionContext) -> Result<()> {
@@ -453,6 +1833,74 @@ pub fn run_mamba_update(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
+/// The output of `micromamba env list --json`.
+#[derive(Deserialize)]
+struct MicromambaEnvironments {
+    /// The prefixes of the environments
+    envs: Vec<PathBuf>,
+}
+
+/// The prefixes of the micromamba environments.
+fn micromamba_environments(micromamba: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new(micromamba)
+        .args(["env", "list", "--json"])
+        .output_checked_utf8()?;
+    let environments: MicromambaEnvironments =
+        serde_json::from_str(&output.stdout).context("Failed to parse the micromamba environments")?;
+    Ok(environments.envs)
+}
+
+pub fn run_micromamba_update(ctx: &ExecutionContext) -> Result<()> {
+    let micromamba = require("micromamba")?;
+    let envs = micromamba_environments(&micromamba)?;
+    if envs.is_empty() {
+        return Err(SkipStep(String::from("No micromamba environment")).into());
+    }
+
+    print_separator("micromamba");
+
+    let ignored = ctx.config().micromamba_ignore();
+    let mut failed = Vec::new();
+    for env in envs {
+        let name = env
+            .file_name()
+            .unwrap_or(env.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        if ignored
+            .iter()
+            .any(|ignored| *ignored == name || Path::new(ignored) == env)
+        {
+            println!("Ignoring {name}");
+            continue;
+        }
+
+        println!("Updating {name} ({})", env.display());
+        // micromamba can tell what it would do, so dry runs run it for real with `--dry-run`
+        let mut command = RunType::Wet.execute(&micromamba);
+        command.args(["update", "--all", "-p"]).arg(&env);
+        if ctx.run_type().dry() {
+            command.arg("--dry-run");
+        }
+        if ctx.config().yes(Step::Micromamba) {
+            command.arg("--yes");
+        }
+        if let Err(e) = command.status_checked() {
+            print_warning(format!("Failed to update {name}: {e}"));
+            failed.push(name);
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "Failed to update the micromamba environments {}",
+            failed.join(", ")
+        ))
+    }
+}
+
 pub fn run_miktex_packages_update(ctx: &ExecutionContext) -> Result<()> {
     let miktex = require("miktex")?;
     print_separator("miktex");
@@ -463,6 +1911,55 @@ pub fn run_miktex_packages_update(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+/// The virtualenvs under the `venv_roots`, the directories with a `pyvenv.cfg`.
+pub fn python_venvs(roots: &[PathBuf]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .flat_map(|root| {
+            walkdir::WalkDir::new(root)
+                .max_depth(5)
+                .into_iter()
+                .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "node_modules")
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name() == "pyvenv.cfg")
+                .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        })
+        .collect()
+}
+
+/// Upgrade the requirements of a virtualenv found under the `venv_roots`.
+pub fn run_python_venv_update(ctx: &ExecutionContext, venv: &Path) -> Result<()> {
+    let requirements = [venv.join("requirements.txt")]
+        .into_iter()
+        .chain(venv.parent().map(|project| project.join("requirements.txt")))
+        .find(|requirements| requirements.is_file())
+        .ok_or_else(|| SkipStep(format!("{} has no requirements.txt", venv.display())))?;
+    #[cfg(unix)]
+    let pip = venv.join("bin/pip");
+    #[cfg(windows)]
+    let pip = venv.join("Scripts/pip.exe");
+    let pip = pip.require()?;
+
+    print_separator(format!("Python venv ({})", venv.display()));
+
+    // `--dry-run` resolves the upgrades without installing them, so it runs for real during a dry run
+    let mut command = if ctx.run_type().dry() {
+        let mut command = RunType::Wet.execute(&pip);
+        command.args(["install", "--dry-run"]);
+        command
+    } else {
+        let mut command = ctx.run_type().execute(&pip);
+        command.arg("install");
+        command
+    };
+    command
+        .arg("-U")
+        .arg("-r")
+        .arg(&requirements)
+        .status_checked()
+        .with_context(|| format!("Failed to upgrade the requirements of {}", venv.display()))
+}
+
 pub fn run_pip3_update(ctx: &ExecutionContext) -> Result<()> {
     let py = require("python").and_then(check_is_python_2_or_shim);
     let py3 = require("python3").and_then(check_is_python_2_or_shim);
@@ -529,16 +2026,80 @@ pub fn run_pip3_update(ctx: &ExecutionContext) -> Result<()> {
         .into());
     }
 
-    print_separator("pip3");
-    if env::var("VIRTUAL_ENV").is_ok() {
-        print_warning("This step is skipped when running inside a virtual environment");
-        return Err(SkipStep("Does not run inside a virtual environment".to_string()).into());
+    print_separator("pip3");
+    if env::var("VIRTUAL_ENV").is_ok() {
+        print_warning("This step is skipped when running inside a virtual environment");
+        return Err(SkipStep("Does not run inside a virtual environment".to_string()).into());
+    }
+
+    let mut command = ctx.run_type().execute(&python3);
+    command.args(["-m", "pip", "install", "--upgrade", "--user"]);
+    if let Some(constraints) = ctx.config().pip_constraints() {
+        command.arg("-c").arg(constraints);
+    }
+    command.arg("pip").status_checked()?;
+
+    if ctx.config().pip_only_explicit() {
+        pip3_upgrade_explicit_packages(ctx, &python3)?;
+    }
+    Ok(())
+}
+
+/// Upgrade the outdated user packages that aren't only dependencies of other packages.
+fn pip3_upgrade_explicit_packages(ctx: &ExecutionContext, python3: &Path) -> Result<()> {
+    let output = Command::new(python3)
+        .args(["-m", "pip", "list", "--user", "--outdated", "--not-required"])
+        .output_checked_utf8()?;
+    // The first two lines are the `Package Version Latest Type` header and its underline
+    let outdated: Vec<(&str, &str, &str)> = output
+        .stdout
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            Some((columns.next()?, columns.next()?, columns.next()?))
+        })
+        .collect();
+    if outdated.is_empty() {
+        println!("The explicitly installed packages are up to date");
+        return Ok(());
     }
 
-    ctx.run_type()
-        .execute(&python3)
-        .args(["-m", "pip", "install", "--upgrade", "--user", "pip"])
-        .status_checked()
+    let constraints = ctx.config().pip_constraints();
+    let mut command = ctx.run_type().execute(python3);
+    command.args(["-m", "pip", "install", "--upgrade", "--user"]);
+    if let Some(constraints) = &constraints {
+        command.arg("-c").arg(constraints);
+    }
+    command.args(outdated.iter().map(|(name, _, _)| name));
+
+    match command.output() {
+        Ok(ExecutorOutput::Wet(output)) => {
+            print!("{}", String::from_utf8_lossy(&output.stdout));
+            println!("Upgraded packages:");
+            for (name, from, to) in outdated {
+                // The constraints may hold a package back from its latest version
+                if constraints.is_some() {
+                    println!("  {name} (was {from})");
+                } else {
+                    println!("  {name} ({from} -> {to})");
+                }
+            }
+            Ok(())
+        }
+        Ok(ExecutorOutput::Dry) => Ok(()),
+        Err(e) => match e.downcast_ref::<TopgradeError>() {
+            Some(TopgradeError::ProcessFailedWithOutput(_, _, stderr))
+                if stderr.contains("externally-managed-environment") =>
+            {
+                Err(eyre!(
+                    "pip refuses to install into this externally managed environment, \
+                     use pipx or a virtual environment for these packages"
+                ))
+            }
+            _ => Err(e),
+        },
+    }
 }
 
 pub fn run_pip_review_update(ctx: &ExecutionContext) -> Result<()> {
@@ -599,6 +2160,12 @@ pub fn run_pipupgrade_update(ctx: &ExecutionContext) -> Result<()> {
 }
 
 pub fn run_stack_update(ctx: &ExecutionContext) -> Result<()> {
+    let stack = require("stack")?;
+    print_separator("stack");
+
+    // Refreshing the package index doesn't change any installed package, so it also runs in dry-run mode
+    Command::new(&stack).arg("update").status_checked()?;
+
     if require("ghcup").is_ok() {
         // `ghcup` is present and probably(?) being used to install `stack`.
         // Don't upgrade `stack`, let `ghcup` handle it. Per `ghcup install stack`:
@@ -606,12 +2173,17 @@ pub fn run_stack_update(ctx: &ExecutionContext) -> Result<()> {
         return Ok(());
     }
 
-    let stack = require("stack")?;
-    print_separator("stack");
-
     ctx.run_type().execute(stack).arg("upgrade").status_checked()
 }
 
+pub fn run_cabal_update(_ctx: &ExecutionContext) -> Result<()> {
+    let cabal = require("cabal")?;
+    print_separator("cabal");
+
+    // Only refreshes the package index, so it also runs in dry-run mode
+    Command::new(cabal).arg("update").status_checked()
+}
+
 pub fn run_ghcup_update(ctx: &ExecutionContext) -> Result<()> {
     let ghcup = require("ghcup")?;
     print_separator("ghcup");
@@ -661,13 +2233,114 @@ pub fn run_tlmgr_update(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
+/// Pull the dotfiles repository of `stow_dir`, and restow each of the `stow_packages`
+/// into the home directory. The packages with conflicts are left as they are.
+#[cfg(unix)]
+pub fn run_stow(ctx: &ExecutionContext) -> Result<()> {
+    let stow = require("stow")?;
+    let dir = ctx.config().stow_dir().require()?;
+    let packages = ctx.config().stow_packages();
+    if packages.is_empty() {
+        return Err(SkipStep(String::from("No `stow_packages` are configured")).into());
+    }
+
+    print_separator("stow");
+
+    if dir.join(".git").exists() {
+        let git = require("git")?;
+        match crate::steps::git::require_clean_tree(&git, &dir) {
+            Ok(()) => ctx
+                .run_type()
+                .execute(&git)
+                .current_dir(&dir)
+                .args(["pull", "--ff-only"])
+                .status_checked()?,
+            Err(e) => print_warning(format!("Not pulling: {e}")),
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for package in packages {
+        // Stow only simulates with `-n`, it is run on dry runs to show what would change
+        let mut command = Command::new(&stow);
+        command.arg("--dir").arg(&dir).arg("--target").arg(HOME_DIR.as_path());
+        if ctx.run_type().dry() {
+            command.arg("-n");
+        }
+        let output = command
+            .args(["-v", "-R", package])
+            .output_checked_with_utf8(|_| Ok(()))?;
+
+        // Stow reports its actions and conflicts on stderr
+        if output.stderr.contains("conflicts") {
+            let details: Vec<&str> = output
+                .stderr
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("* "))
+                .collect();
+            print_warning(format!("{package}: skipped, it conflicts with {}", details.join(", ")));
+            conflicts.push(format!("{package} ({})", details.join(", ")));
+            continue;
+        }
+        if !output.status.success() {
+            return Err(eyre!("Restowing {package} failed: {}", output.stderr.trim()));
+        }
+
+        let changes: Vec<&str> = output
+            .stderr
+            .lines()
+            .filter(|line| line.starts_with("LINK:") || line.starts_with("UNLINK:"))
+            .collect();
+        if changes.is_empty() {
+            println!("{package}: up to date");
+        } else {
+            println!("{package}:");
+            for change in changes {
+                println!("  {change}");
+            }
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        crate::terminal::add_summary_note(format!(
+            "stow skipped the packages with conflicts: {}",
+            conflicts.join("; ")
+        ));
+        Err(SkipStep(format!("Conflicts in {}", conflicts.join("; "))).into())
+    }
+}
+
 pub fn run_chezmoi_update(ctx: &ExecutionContext) -> Result<()> {
     let chezmoi = require("chezmoi")?;
     HOME_DIR.join(".local/share/chezmoi").require()?;
 
     print_separator("chezmoi");
 
-    ctx.run_type().execute(chezmoi).arg("update").status_checked()
+    if ctx.run_type().dry() {
+        // Show what `chezmoi update` would apply with the current source state
+        Command::new(&chezmoi).arg("diff").status_checked()?;
+    }
+
+    if ctx.config().chezmoi_apply() {
+        ctx.run_type()
+            .execute(&chezmoi)
+            .args(["update", "--verbose"])
+            .status_checked()
+    } else {
+        ctx.run_type()
+            .execute(&chezmoi)
+            .args(["update", "--apply=false"])
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            println!("Changes pulled but not applied, run `chezmoi apply` to apply them:");
+            Command::new(&chezmoi).arg("status").status_checked()?;
+        }
+
+        Ok(())
+    }
 }
 
 pub fn run_myrepos_update(ctx: &ExecutionContext) -> Result<()> {
@@ -690,9 +2363,18 @@ pub fn run_myrepos_update(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
-pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) -> Result<()> {
-    print_separator(name);
-    let mut exec = ctx.run_type().execute(shell());
+/// Run `command` in the shell set for `name` in `command_shells`, or the default shell.
+fn shell_command(ctx: &ExecutionContext, run_type: RunType, name: &str, command: &str) -> Result<Executor> {
+    if let Some(shell) = ctx.config().command_shell(name) {
+        let Some((shell, args)) = shell.split_first() else {
+            return Err(eyre!("The shell of `{name}` in `command_shells` is empty"));
+        };
+        let mut exec = run_type.execute(require(shell)?);
+        exec.args(args).arg(command);
+        return Ok(exec);
+    }
+
+    let mut exec = run_type.execute(shell());
     #[cfg(unix)]
     let command = if let Some(command) = command.strip_prefix("-i ") {
         exec.arg("-i");
@@ -700,7 +2382,284 @@ pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) ->
     } else {
         command
     };
-    exec.arg("-c").arg(command).status_checked()
+    exec.arg("-c").arg(command);
+    Ok(exec)
+}
+
+pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) -> Result<()> {
+    print_separator(name);
+    shell_command(ctx, ctx.run_type(), name, command)?.status_checked()
+}
+
+pub fn run_plugin_update(plugin: &PluginUpdate, ctx: &ExecutionContext) -> Result<()> {
+    let detected = shell_command(ctx, RunType::Wet, &plugin.name, &plugin.detect)?
+        .output_checked()
+        .is_ok();
+    if !detected {
+        return Err(SkipStep(format!("`{}` did not succeed", plugin.detect)).into());
+    }
+
+    run_custom_command(&plugin.name, &plugin.update, ctx)
+}
+
+pub fn run_self_updating_tool(tool: &SelfUpdatingTool, ctx: &ExecutionContext) -> Result<()> {
+    let binary = require(&tool.binary)?;
+
+    print_separator(&tool.binary);
+
+    ctx.run_type()
+        .execute(&binary)
+        .args(&tool.update_args)
+        .status_checked()
+        .with_context(|| format!("Failed to update {}", tool.binary))
+}
+
+/// The tools commonly installed outside of a package manager, with the arguments updating them.
+pub const BUILTIN_SELF_UPDATING_TOOLS: &[(&str, &[&str])] = &[
+    ("aqua", &["update-aqua"]),
+    ("flyctl", &["version", "upgrade"]),
+    ("ngrok", &["update"]),
+    ("oh-my-posh", &["upgrade"]),
+    ("rclone", &["selfupdate"]),
+    ("uv", &["self", "update"]),
+    ("wasmer", &["self-update"]),
+    ("yt-dlp", &["--update"]),
+];
+
+pub fn run_builtin_self_updating_tool(binary: &str, update_args: &[&str], ctx: &ExecutionContext) -> Result<()> {
+    let path = require(binary)?;
+    if let Some(manager) = installing_package_manager(&path) {
+        return Err(SkipStep(format!("{binary} is installed with {manager}")).into());
+    }
+
+    print_separator(binary);
+
+    ctx.run_type()
+        .execute(&path)
+        .args(update_args)
+        .status_checked()
+        .with_context(|| format!("Failed to update {binary}"))
+}
+
+/// The tools commonly updated with the script they ship, with its path and arguments.
+pub const BUILTIN_INSTALLER_SCRIPT_TOOLS: &[(&str, &str, &[&str])] = &[
+    // Downloads the binary matching the checkout, which is pulled when `~/.fzf` is in `repos`
+    ("fzf", "~/.fzf/install", &["--bin"]),
+    // Checks out the latest release of nvm
+    ("nvm", "~/.nvm/install.sh", &[]),
+];
+
+/// Run the installer or update script of `tool`, in the directory of the script.
+pub fn run_installer_script<S: AsRef<OsStr>>(
+    tool: &str,
+    script: &str,
+    args: &[S],
+    ctx: &ExecutionContext,
+) -> Result<()> {
+    let script = PathBuf::from(shellexpand::tilde(script).as_ref()).require()?;
+
+    print_separator(tool);
+
+    let mut command = ctx.run_type().execute(&script);
+    if let Some(dir) = script.parent() {
+        command.current_dir(dir);
+    }
+    command
+        .args(args)
+        .status_checked()
+        .with_context(|| format!("Failed to update {tool}"))
+}
+
+pub fn run_regenerate_completions(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().regenerate_completions() {
+        return Err(SkipStep(String::from("`regenerate_completions` is not enabled")).into());
+    }
+    let completions = ctx.config().completions();
+    if completions.is_empty() {
+        return Err(SkipStep(String::from("No [[completions]] are configured")).into());
+    }
+    let Some(dir) = ctx.config().completions_dir() else {
+        return Err(SkipStep(String::from("`completions_dir` is not set")).into());
+    };
+
+    print_separator("Completions");
+
+    let mut regenerated = Vec::new();
+    let mut failed = false;
+    for completion in completions {
+        let Some(binary) = which(&completion.binary) else {
+            debug!(
+                "{} is not installed, not regenerating {}",
+                completion.binary, completion.file
+            );
+            continue;
+        };
+        let path = dir.join(&completion.file);
+
+        if ctx.run_type().dry() {
+            println!(
+                "Dry running: {} {} > {}",
+                binary.display(),
+                completion.args.join(" "),
+                path.display()
+            );
+            continue;
+        }
+
+        let generated = match Command::new(&binary).args(&completion.args).output_checked_utf8() {
+            Ok(output) => output.stdout,
+            Err(e) => {
+                print_warning(format!("Failed to generate {}: {e}", completion.file));
+                failed = true;
+                continue;
+            }
+        };
+        if fs::read_to_string(&path).is_ok_and(|current| current == generated) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, generated).with_context(|| format!("Failed to write {}", path.display()))?;
+        regenerated.push(completion.file.as_str());
+    }
+
+    if !ctx.run_type().dry() {
+        if regenerated.is_empty() {
+            println!("The completions are up to date");
+        } else {
+            println!("Regenerated: {}", regenerated.join(", "));
+        }
+    }
+
+    if failed {
+        Err(eyre!("Some completions could not be generated"))
+    } else {
+        Ok(())
+    }
+}
+
+/// The non-interactive authentication refreshes of the cloud CLIs, run when `[[cloud_auth]]` isn't set.
+const BUILTIN_CLOUD_AUTH: &[(&str, &[&str])] = &[
+    ("gcloud", &["auth", "print-access-token", "--quiet"]),
+    ("aws", &["sts", "get-caller-identity"]),
+    ("az", &["account", "get-access-token"]),
+];
+
+pub fn run_cloud_auth_refresh(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().refresh_cloud_auth() {
+        return Err(SkipStep(String::from("`refresh_cloud_auth` is not enabled")).into());
+    }
+    let clis: Vec<(&str, Vec<&str>)> = match ctx.config().cloud_auth() {
+        Some(clis) => clis
+            .iter()
+            .map(|cli| (cli.binary.as_str(), cli.command.iter().map(String::as_str).collect()))
+            .collect(),
+        None => BUILTIN_CLOUD_AUTH
+            .iter()
+            .map(|(binary, command)| (*binary, command.to_vec()))
+            .collect(),
+    };
+    let installed: Vec<(PathBuf, &str, Vec<&str>)> = clis
+        .into_iter()
+        .filter_map(|(name, command)| which(name).map(|binary| (binary, name, command)))
+        .collect();
+    if installed.is_empty() {
+        return Err(SkipStep(String::from("None of the CLIs of `cloud_auth` is installed")).into());
+    }
+
+    print_separator("Cloud authentication");
+
+    let mut failed = Vec::new();
+    for (binary, name, command) in installed {
+        // The output is captured and dropped, it can be an access token
+        match ctx.run_type().execute(&binary).args(&command).output() {
+            Ok(ExecutorOutput::Wet(_)) => println!("{name}: refreshed"),
+            Ok(ExecutorOutput::Dry) => (),
+            Err(e) => {
+                print_warning(format!("{name}: {e}"));
+                failed.push(name);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to refresh the authentication of {}", failed.join(", ")))
+    }
+}
+
+/// The caches commonly needing a rebuild after the upgrades, run when `[[cache_refresh]]` isn't set.
+const BUILTIN_CACHE_REFRESH: &[(&str, &[&str])] = &[("fc-cache", &["-f"]), ("kbuildsycoca6", &[])];
+
+pub fn run_cache_refresh(ctx: &ExecutionContext) -> Result<()> {
+    let caches: Vec<(&str, Vec<&str>)> = match ctx.config().cache_refresh() {
+        Some(caches) => caches
+            .iter()
+            .map(|cache| {
+                (
+                    cache.binary.as_str(),
+                    cache.command.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect(),
+        None => BUILTIN_CACHE_REFRESH
+            .iter()
+            .map(|(binary, command)| (*binary, command.to_vec()))
+            .collect(),
+    };
+    let installed: Vec<(PathBuf, &str, Vec<&str>)> = caches
+        .into_iter()
+        .filter_map(|(name, command)| which(name).map(|binary| (binary, name, command)))
+        .collect();
+    if installed.is_empty() {
+        return Err(SkipStep(String::from("None of the tools of `cache_refresh` is installed")).into());
+    }
+
+    print_separator("Cache refresh");
+
+    let mut failed = Vec::new();
+    for (binary, name, command) in installed {
+        match ctx.run_type().execute(&binary).args(&command).status_checked() {
+            Ok(()) => println!("{name}: refreshed"),
+            Err(e) => {
+                print_warning(format!("{name}: {e}"));
+                failed.push(name);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to refresh the caches of {}", failed.join(", ")))
+    }
+}
+
+pub fn run_cargo_subcommand_update(update: &CargoSubcommandUpdate, ctx: &ExecutionContext) -> Result<()> {
+    let cargo = require("cargo")?;
+    let binary = format!("cargo-{}", update.subcommand);
+    which(&binary)
+        .or_else(|| {
+            let cargo_home = env::var_os("CARGO_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| HOME_DIR.join(".cargo"));
+            cargo_home
+                .join("bin")
+                .join(format!("{binary}{}", env::consts::EXE_SUFFIX))
+                .if_exists()
+        })
+        .ok_or_else(|| SkipStep(format!("{binary} is not installed")))?;
+
+    print_separator(format!("cargo {}", update.subcommand));
+
+    ctx.run_type()
+        .execute(cargo)
+        .arg(&update.subcommand)
+        .args(&update.update_args)
+        .status_checked()
+        .with_context(|| format!("Failed to update cargo {}", update.subcommand))
 }
 
 pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
@@ -708,7 +2667,7 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
     let composer_home = Command::new(&composer)
         .args(["global", "config", "--absolute", "--quiet", "home"])
         .output_checked_utf8()
-        .map_err(|e| (SkipStep(format!("Error getting the composer directory: {e}"))))
+        .map_err(|e| SkipStep(format!("Error getting the composer directory: {e}")))
         .map(|s| PathBuf::from(s.stdout.trim()))?
         .require()?;
 
@@ -745,10 +2704,27 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
+    if ctx.run_type().dry() {
+        // Show what would be updated
+        return RunType::Wet
+            .execute(&composer)
+            .args(["global", "update", "--dry-run"])
+            .status_checked();
+    }
+
     let output = ctx.run_type().execute(&composer).args(["global", "update"]).output()?;
     if let ExecutorOutput::Wet(output) = output {
         let output: Utf8Output = output.try_into()?;
         print!("{}\n{}", output.stdout, output.stderr);
+
+        let upgraded = composer_upgraded_packages(&output.stderr);
+        if !upgraded.is_empty() {
+            println!("Upgraded packages:");
+            for package in upgraded {
+                println!("  {package}");
+            }
+        }
+
         if output.stdout.contains("valet") || output.stderr.contains("valet") {
             if let Some(valet) = which("valet") {
                 ctx.run_type().execute(valet).arg("install").status_checked()?;
@@ -759,32 +2735,27 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-pub fn run_dotnet_upgrade(ctx: &ExecutionContext) -> Result<()> {
-    let dotnet = require("dotnet")?;
+/// Parse the `  - Upgrading vendor/package (1.0.0 => 1.1.0)` lines composer prints to stderr.
+fn composer_upgraded_packages(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("- Upgrading "))
+        .collect()
+}
 
-    // Skip when the `dotnet tool list` subcommand fails.
-    // (This is expected when a dotnet runtime is installed but no SDK.)
-    let output = match ctx
-        .run_type()
-        .execute(&dotnet)
+/// The global .NET tools with their versions, listing them changes nothing so it runs even
+/// during a dry run.
+fn dotnet_global_tools(dotnet: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new(dotnet)
         .args(["tool", "list", "--global"])
         // dotnet will print a greeting message on its first run, from this question:
         // https://stackoverflow.com/q/70493706/14092446
         // Setting `DOTNET_NOLOGO` to `true` should disable it
         .env("DOTNET_NOLOGO", "true")
-        .output_checked_utf8()
-    {
-        Ok(output) => output,
-        Err(_) => {
-            return Err(SkipStep(String::from(
-                "Error running `dotnet tool list`. This is expected when a dotnet runtime is installed but no SDK.",
-            ))
-            .into());
-        }
-    };
+        .output_checked_utf8()?;
 
     let mut in_header = true;
-    let mut packages = output
+    let tools = output
         .stdout
         .lines()
         // Skip the header:
@@ -802,22 +2773,103 @@ pub fn run_dotnet_upgrade(ctx: &ExecutionContext) -> Result<()> {
                 in_header
             }
         })
-        .filter(|line| !line.is_empty())
-        .peekable();
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            Some((
+                columns.next()?.to_string(),
+                columns.next().unwrap_or_default().to_string(),
+            ))
+        })
+        .collect();
+
+    Ok(tools)
+}
+
+/// The latest version of a tool on NuGet, as found by `dotnet tool search`.
+fn dotnet_latest_version(dotnet: &Path, tool: &str) -> Option<String> {
+    let output = Command::new(dotnet)
+        .args(["tool", "search", tool])
+        .env("DOTNET_NOLOGO", "true")
+        .output_checked_utf8()
+        .ok()?;
+    output.stdout.lines().find_map(|line| {
+        let mut columns = line.split_whitespace();
+        if columns.next()?.eq_ignore_ascii_case(tool) {
+            columns.next().map(str::to_string)
+        } else {
+            None
+        }
+    })
+}
+
+pub fn run_dotnet_upgrade(ctx: &ExecutionContext) -> Result<()> {
+    let dotnet = require("dotnet")?;
+
+    // Skip when the `dotnet tool list` subcommand fails.
+    // (This is expected when a dotnet runtime is installed but no SDK.)
+    let tools = dotnet_global_tools(&dotnet).map_err(|_| {
+        SkipStep(String::from(
+            "Error running `dotnet tool list`. This is expected when a dotnet runtime is installed but no SDK.",
+        ))
+    })?;
 
-    if packages.peek().is_none() {
+    if tools.is_empty() {
         return Err(SkipStep(String::from("No dotnet global tools installed")).into());
     }
 
     print_separator(".NET");
 
-    for package in packages {
-        let package_name = package.split_whitespace().next().unwrap();
+    let ignored = ctx.config().dotnet_ignore();
+    let (tools, skipped): (Vec<_>, Vec<_>) = tools
+        .into_iter()
+        .partition(|(id, _)| !ignored.iter().any(|ignored| ignored.eq_ignore_ascii_case(id)));
+    for (id, _) in &skipped {
+        println!("Ignoring {id}");
+    }
+
+    if ctx.run_type().dry() {
+        for (id, version) in &tools {
+            match dotnet_latest_version(&dotnet, id) {
+                Some(latest) if latest != *version => println!("{id} {version} -> {latest}"),
+                Some(_) => (),
+                None => println!("{id}: could not find the latest version"),
+            }
+        }
+        return Ok(());
+    }
+
+    // `--all` appeared in the .NET 8 SDK and can't leave tools out
+    let sdk_major = Command::new(&dotnet)
+        .arg("--version")
+        .output_checked_utf8()
+        .ok()
+        .and_then(|output| output.stdout.split('.').next()?.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+    if skipped.is_empty() && sdk_major >= 8 {
         ctx.run_type()
             .execute(&dotnet)
-            .args(["tool", "update", package_name, "--global"])
+            .args(["tool", "update", "--global", "--all"])
+            .env("DOTNET_NOLOGO", "true")
             .status_checked()
-            .with_context(|| format!("Failed to update .NET package {package_name}"))?;
+            .context("Failed to update the .NET global tools")?;
+    } else {
+        for (id, _) in &tools {
+            ctx.run_type()
+                .execute(&dotnet)
+                .args(["tool", "update", id, "--global"])
+                .env("DOTNET_NOLOGO", "true")
+                .status_checked()
+                .with_context(|| format!("Failed to update .NET package {id}"))?;
+        }
+    }
+
+    let updated = dotnet_global_tools(&dotnet).unwrap_or_default();
+    for (id, version) in &tools {
+        if let Some((_, new_version)) = updated.iter().find(|(new_id, _)| new_id == id) {
+            if new_version != version {
+                println!("Updated {id} {version} -> {new_version}");
+            }
+        }
     }
 
     Ok(())
@@ -843,6 +2895,147 @@ pub fn run_helix_grammars(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+pub fn run_bat_cache_build(ctx: &ExecutionContext) -> Result<()> {
+    // Debian and Ubuntu ship bat as `batcat`
+    let bat = require("bat").or(require("batcat"))?;
+
+    print_separator("bat");
+
+    // delta reads its syntaxes and themes from the bat cache, so this refreshes both
+    ctx.run_type()
+        .execute(&bat)
+        .args(["cache", "--build"])
+        .status_checked()
+        .with_context(|| "Failed to build the bat cache!")
+}
+
+pub fn run_pkgx_update(ctx: &ExecutionContext) -> Result<()> {
+    let pkgx = which("pkgx");
+    let pkgm = which("pkgm");
+    if pkgx.is_none() && pkgm.is_none() {
+        return Err(SkipStep(String::from("Neither pkgx nor pkgm is installed")).into());
+    }
+
+    print_separator("pkgx");
+
+    // pkgx only touches its own prefix, so this can't get in the way of the system package manager
+    if let Some(pkgx) = &pkgx {
+        ctx.run_type().execute(pkgx).arg("--sync").status_checked()?;
+    }
+
+    match pkgm {
+        Some(pkgm) => ctx
+            .run_type()
+            .execute(pkgm)
+            .arg("update")
+            .status_checked()
+            .with_context(|| "Failed to update the pkgm packages"),
+        None => {
+            println!("pkgm is not installed, only the pkgx pantry was synced");
+            Ok(())
+        }
+    }
+}
+
+/// The installed ollama models and their ID, from the `NAME ID SIZE MODIFIED` columns of `ollama list`.
+fn ollama_models(ollama: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new(ollama).arg("list").output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            Some((columns.next()?.to_string(), columns.next()?.to_string()))
+        })
+        .collect())
+}
+
+pub fn run_ollama_pull(ctx: &ExecutionContext) -> Result<()> {
+    let ollama = require("ollama")?;
+    let before = ollama_models(&ollama)?;
+    let models: Vec<&(String, String)> = before
+        .iter()
+        .filter(|(name, _)| {
+            ctx.config()
+                .ollama_models()
+                .is_none_or(|allowed| allowed.iter().any(|allowed| allowed == name))
+        })
+        .collect();
+    if models.is_empty() {
+        return Err(SkipStep(String::from("No ollama model to pull")).into());
+    }
+
+    print_separator("ollama");
+
+    for (name, _) in &models {
+        ctx.run_type().execute(&ollama).args(["pull", name]).status_checked()?;
+    }
+
+    if !ctx.run_type().dry() {
+        let after = ollama_models(&ollama)?;
+        let changed: Vec<&str> = models
+            .iter()
+            .filter(|(name, id)| after.iter().any(|(new_name, new_id)| new_name == name && new_id != id))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if changed.is_empty() {
+            println!("All the models are up to date");
+        } else {
+            println!("Updated models: {}", changed.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_password_managers_sync(ctx: &ExecutionContext) -> Result<()> {
+    let managers: Vec<(&str, PathBuf)> = ctx
+        .config()
+        .password_managers()
+        .iter()
+        .filter_map(|manager| match manager.as_str() {
+            "rbw" | "bw" => which(manager).map(|path| (manager.as_str(), path)),
+            _ => {
+                print_warning(format!("Unknown password manager `{manager}`"));
+                None
+            }
+        })
+        .collect();
+    if managers.is_empty() {
+        return Err(SkipStep(String::from("No enabled password manager is installed")).into());
+    }
+
+    print_separator("Password managers");
+
+    let mut synced = false;
+    for (manager, path) in managers {
+        // Syncing a locked vault would ask for the master password
+        let unlocked = match manager {
+            "rbw" => Command::new(&path).arg("unlocked").output_checked().is_ok(),
+            _ => Command::new(&path)
+                .arg("status")
+                .output_checked_utf8()
+                .map(|output| output.stdout.contains(r#""status":"unlocked""#))
+                .unwrap_or(false),
+        };
+        if !unlocked {
+            print_warning(format!("The {manager} vault is locked, not syncing it"));
+            continue;
+        }
+
+        ctx.run_type().execute(&path).arg("sync").status_checked()?;
+        println!("Synced the {manager} vault");
+        synced = true;
+    }
+
+    if synced {
+        Ok(())
+    } else {
+        Err(SkipStep(String::from("All the password manager vaults are locked")).into())
+    }
+}
+
 pub fn run_raco_update(ctx: &ExecutionContext) -> Result<()> {
     let raco = require("raco")?;
 
@@ -933,7 +3126,32 @@ pub fn run_bob(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Bob");
 
-    ctx.run_type().execute(bob).args(["update", "--all"]).status_checked()
+    match ctx.config().bob_channel() {
+        Some(channel) => {
+            // `use` installs the channel when it is missing
+            ctx.run_type()
+                .execute(&bob)
+                .args(["use", channel.as_ref()])
+                .status_checked()?;
+            ctx.run_type()
+                .execute(&bob)
+                .args(["update", channel.as_ref()])
+                .status_checked()?;
+        }
+        None => ctx
+            .run_type()
+            .execute(&bob)
+            .args(["update", "--all"])
+            .status_checked()?,
+    }
+
+    if !ctx.run_type().dry() {
+        if let Ok(output) = Command::new(&bob).arg("ls").output_checked_utf8() {
+            print!("{}", output.stdout);
+        }
+    }
+
+    Ok(())
 }
 
 pub fn run_certbot(ctx: &ExecutionContext) -> Result<()> {
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
@@ -13,10 +13,10 @@ use tokio::runtime;
 use tracing::{debug, error};
 
 use crate::command::CommandExt;
-use crate::config::Step;
+use crate::config::{GitBuild, Step};
 use crate::execution_context::ExecutionContext;
 use crate::steps::emacs::Emacs;
-use crate::terminal::print_separator;
+use crate::terminal::{print_separator, shell};
 use crate::utils::{require, PathExt};
 use crate::{error::SkipStep, terminal::print_warning, HOME_DIR};
 use etcetera::base_strategy::BaseStrategy;
@@ -135,6 +135,95 @@ fn output_checked_utf8(output: Output) -> Result<()> {
     }
 }
 
+/// Skip the repositories with uncommitted changes, pulling would fail or mix them with the new ones.
+pub fn require_clean_tree(git: &Path, path: &Path) -> Result<()> {
+    let changes = Command::new(git)
+        .current_dir(path)
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output_checked_utf8()?
+        .stdout;
+    if changes.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(SkipStep(format!("{} has uncommitted changes", path.display())).into())
+    }
+}
+
+/// Pull an editor configuration repository of `editor_config_repos`, and list the new commits.
+pub fn run_editor_config_pull(ctx: &ExecutionContext, path: &str) -> Result<()> {
+    let git = require("git")?;
+    let path = PathBuf::from(shellexpand::tilde(path).as_ref()).require()?;
+    require_clean_tree(&git, &path)?;
+
+    print_separator(format!("Editor config ({})", path.display()));
+
+    let before = get_head_revision(&git, &path);
+    ctx.run_type()
+        .execute(&git)
+        .current_dir(&path)
+        .args(["pull", "--ff-only"])
+        .status_checked()?;
+    let after = get_head_revision(&git, &path);
+
+    match (before, after) {
+        (Some(before), Some(after)) if before != after => {
+            println!("{} {}:", style("Changed").yellow().bold(), path.display());
+            Command::new(&git)
+                .current_dir(&path)
+                .args(["--no-pager", "log", "--no-decorate", "--oneline"])
+                .arg(format!("{before}..{after}"))
+                .status_checked()?;
+        }
+        _ if !ctx.run_type().dry() => println!("{} {}", style("Up-to-date").green().bold(), path.display()),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Pull the repository of a tool built from git, and rebuild it when its HEAD changed.
+pub fn run_git_build(ctx: &ExecutionContext, build: &GitBuild) -> Result<()> {
+    let git = require("git")?;
+    let path = PathBuf::from(shellexpand::tilde(&build.path).as_ref()).require()?;
+    require_clean_tree(&git, &path)?;
+
+    print_separator(format!("Git build ({})", path.display()));
+
+    let before = get_head_revision(&git, &path);
+    ctx.run_type()
+        .execute(&git)
+        .current_dir(&path)
+        .args(["pull", "--ff-only"])
+        .status_checked()?;
+    let after = get_head_revision(&git, &path);
+
+    if !ctx.run_type().dry() && before == after {
+        println!("{} {}", style("Up-to-date").green().bold(), path.display());
+        return Ok(());
+    }
+
+    ctx.run_type()
+        .execute(shell())
+        .current_dir(&path)
+        .arg("-c")
+        .arg(&build.build)
+        .status_checked()
+        .with_context(|| format!("Failed to build {}", path.display()))?;
+    if let (Some(before), Some(after)) = (before, after) {
+        if !ctx.run_type().dry() {
+            println!(
+                "{} {} ({} -> {})",
+                style("Rebuilt").yellow().bold(),
+                path.display(),
+                &before[..before.len().min(8)],
+                &after[..after.len().min(8)]
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn get_head_revision<P: AsRef<Path>>(git: &Path, repo: P) -> Option<String> {
     Command::new(git)
         .stdin(Stdio::null())
@@ -150,6 +239,34 @@ fn get_head_revision<P: AsRef<Path>>(git: &Path, repo: P) -> Option<String> {
         .ok()
 }
 
+/// Map the initialized submodules of a repository to their checked out revision.
+///
+/// Submodules with merge conflicts are reported as `None`.
+async fn submodule_revisions(git: &Path, repo: &Path) -> Result<HashMap<String, Option<String>>> {
+    let output = AsyncCommand::new(git)
+        .args(["submodule", "status", "--recursive"])
+        .current_dir(repo)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(eyre!("{}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    // Lines look like `+<sha> <path> (<describe>)`, the first character being the state
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with('-'))
+        .filter_map(|line| {
+            let conflicted = line.starts_with('U');
+            let mut fields = line.get(1..)?.split_whitespace();
+            let revision = fields.next()?;
+            let path = fields.next()?;
+            Some((path.to_string(), (!conflicted).then(|| revision.to_string())))
+        })
+        .collect())
+}
+
 impl RepoStep {
     /// Try to create a `RepoStep`, fail if `git` is not found.
     pub fn try_new() -> Result<Self> {
@@ -324,6 +441,10 @@ impl RepoStep {
         if result.is_err() {
             println!("{} pulling {}", style("Failed").red().bold(), repo.as_ref().display());
         } else {
+            if ctx.config().git_update_submodules() {
+                self.update_submodules(repo.as_ref()).await;
+            }
+
             let after_revision = get_head_revision(&self.git, repo.as_ref());
 
             match (&before_revision, &after_revision) {
@@ -354,6 +475,73 @@ impl RepoStep {
         result.map(|_| ())
     }
 
+    /// Move the submodules of a repo to their remote branch and report the ones that advanced.
+    ///
+    /// Problems are only reported, they don't fail the pull of the repo itself.
+    async fn update_submodules(&self, repo: &Path) {
+        let before = match submodule_revisions(&self.git, repo).await {
+            Ok(before) if before.is_empty() => return,
+            Ok(before) => before,
+            Err(e) => {
+                print_warning(format!("Failed to list the submodules of {}: {e}", repo.display()));
+                return;
+            }
+        };
+
+        if before.values().any(Option::is_none) {
+            print_warning(format!(
+                "Skipping the submodules of {} because some have merge conflicts",
+                repo.display()
+            ));
+            return;
+        }
+
+        // `--merge` also works for submodules in a detached HEAD state
+        let output = AsyncCommand::new(&self.git)
+            .args(["submodule", "update", "--remote", "--merge", "--recursive"])
+            .current_dir(repo)
+            .stdin(Stdio::null())
+            .output()
+            .await;
+        match output {
+            Ok(output) if output.status.success() => (),
+            Ok(output) => {
+                print_warning(format!(
+                    "Skipping the submodules of {}, updating them failed (merge conflict?): {}",
+                    repo.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+                return;
+            }
+            Err(e) => {
+                print_warning(format!("Failed to update the submodules of {}: {e}", repo.display()));
+                return;
+            }
+        }
+
+        let Ok(after) = submodule_revisions(&self.git, repo).await else {
+            return;
+        };
+        let mut advanced: Vec<_> = after
+            .iter()
+            .filter_map(|(path, revision)| match (before.get(path), revision) {
+                (Some(Some(old)), Some(new)) if old != new => Some((path, old, new)),
+                _ => None,
+            })
+            .collect();
+        advanced.sort();
+        for (path, old, new) in advanced {
+            println!(
+                "{} submodule {} of {} ({}..{})",
+                style("Advanced").yellow().bold(),
+                path,
+                repo.display(),
+                &old[..old.len().min(7)],
+                &new[..new.len().min(7)]
+            );
+        }
+    }
+
     /// Pull the repositories specified in `self.repos`.
     ///
     /// # NOTE
@@ -1,11 +1,15 @@
-use std::path::PathBuf;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
+use tracing::debug;
 
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
-use crate::terminal::print_separator;
+use crate::terminal::{print_separator, print_warning};
 use crate::utils;
 use crate::utils::PathExt;
 
@@ -27,6 +31,102 @@ pub fn run_go_gup(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(gup).arg("update").status_checked()
 }
 
+/// Reinstall the binaries installed with `go install` at their latest version.
+pub fn run_go_install_update(ctx: &ExecutionContext) -> Result<()> {
+    let go = utils::require("go")?;
+    if require_go_bin("gup").is_ok() || require_go_bin("go-global-update").is_ok() {
+        return Err(SkipStep(String::from("Go binaries are updated by gup or go-global-update")).into());
+    }
+
+    let bin_dir = go_bin_dir(&go)?.require()?;
+    let ignored = ctx.config().go_ignore();
+
+    // `go version -m` prints the package a binary was built from as `\tpath\t<package>`,
+    // and the module it belongs to as `\tmod\t<module>\t<version>\t<sum>`.
+    let mut packages = Vec::new();
+    for entry in fs::read_dir(&bin_dir)? {
+        let binary = entry?.path();
+        let Ok(output) = Command::new(&go)
+            .arg("version")
+            .arg("-m")
+            .arg(&binary)
+            .output_checked_utf8()
+        else {
+            debug!("{} isn't a Go binary", binary.display());
+            continue;
+        };
+
+        let mut package = None;
+        let mut module = None;
+        for line in output.stdout.lines() {
+            match line.trim().split('\t').collect::<Vec<_>>().as_slice() {
+                ["path", path] => package = Some(path.to_string()),
+                ["mod", path, version, ..] => module = Some((path.to_string(), *version == "(devel)")),
+                _ => (),
+            }
+        }
+
+        match (package, module) {
+            (Some(package), Some((module, is_devel))) => {
+                if ignored.iter().any(|ignored| *ignored == package || *ignored == module) {
+                    debug!("Ignoring {}", package);
+                } else if is_devel {
+                    debug!("{} was built from a local checkout, not reinstalling it", package);
+                } else {
+                    packages.push(package);
+                }
+            }
+            _ => debug!("Cannot find the package of {}", binary.display()),
+        }
+    }
+
+    if packages.is_empty() {
+        return Err(SkipStep(format!(
+            "No binaries installed with `go install` in {}",
+            bin_dir.display()
+        ))
+        .into());
+    }
+
+    print_separator("go install");
+
+    let mut failed = Vec::new();
+    for package in &packages {
+        let result = ctx
+            .run_type()
+            .execute(&go)
+            .arg("install")
+            .arg(format!("{package}@latest"))
+            .status_checked();
+        if let Err(e) = result {
+            print_warning(format!("Failed to update {package}: {e}"));
+            failed.push(package.as_str());
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to update {}", failed.join(", ")))
+    }
+}
+
+/// The directory `go install` puts binaries in.
+fn go_bin_dir(go: &Path) -> Result<PathBuf> {
+    let gobin_output = Command::new(go).args(["env", "GOBIN"]).output_checked_utf8()?;
+    let gobin = gobin_output.stdout.trim();
+    if !gobin.is_empty() {
+        return Ok(PathBuf::from(gobin));
+    }
+
+    // Binaries go to the `bin` directory of the first entry of GOPATH
+    let gopath_output = Command::new(go).args(["env", "GOPATH"]).output_checked_utf8()?;
+    env::split_paths(gopath_output.stdout.trim())
+        .next()
+        .map(|gopath| gopath.join("bin"))
+        .ok_or_else(|| SkipStep(String::from("GOPATH isn't set")).into())
+}
+
 /// Get the path of a Go binary.
 fn require_go_bin(name: &str) -> Result<PathBuf> {
     utils::require(name).or_else(|_| {
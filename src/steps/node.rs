@@ -1,18 +1,20 @@
+use std::env;
 use std::fmt::Display;
 #[cfg(target_os = "linux")]
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::utils::{require_option, REQUIRE_SUDO};
 use crate::HOME_DIR;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 #[cfg(target_os = "linux")]
 use nix::unistd::Uid;
 use semver::Version;
 use tracing::debug;
 
 use crate::command::CommandExt;
+use crate::config::{NodeChannel, NodeVersionManager};
 use crate::terminal::print_separator;
 use crate::utils::{require, PathExt};
 use crate::{error::SkipStep, execution_context::ExecutionContext};
@@ -264,6 +266,228 @@ pub fn run_yarn_upgrade(ctx: &ExecutionContext) -> Result<()> {
     }
 }
 
+/// The path of `nvm.sh`, nvm being a shell function.
+fn nvm_script() -> Option<PathBuf> {
+    env::var_os("NVM_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| HOME_DIR.join(".nvm"))
+        .join("nvm.sh")
+        .if_exists()
+}
+
+/// The default Node version of `manager`, asked to the manager itself: the `node` found in the
+/// `PATH` of Topgrade is still the one of the previous default after an update.
+fn default_node_version(manager: NodeVersionManager) -> Option<String> {
+    let output = match manager {
+        NodeVersionManager::Fnm => Command::new(require("fnm").ok()?)
+            .args(["exec", "--using", "default", "node", "--version"])
+            .output_checked_utf8(),
+        NodeVersionManager::Nvm => {
+            let script = format!("source {} && nvm version default", nvm_script()?.display());
+            Command::new(require("bash").ok()?)
+                .args(["-c", &script])
+                .output_checked_utf8()
+        }
+        NodeVersionManager::Nodenv => Command::new(require("nodenv").ok()?)
+            .arg("global")
+            .output_checked_utf8(),
+        // n installs Node in place, and the `node` of Volta is a shim running its default one
+        NodeVersionManager::N | NodeVersionManager::Volta | NodeVersionManager::Autodetect => {
+            Command::new(require("node").ok()?)
+                .arg("--version")
+                .output_checked_utf8()
+        }
+    };
+
+    // nvm prints `N/A` without a default version
+    output
+        .ok()
+        .map(|output| output.stdout.trim().trim_start_matches('v').to_string())
+        .filter(|version| !version.is_empty() && version != "N/A")
+}
+
+pub fn run_node_version_manager(ctx: &ExecutionContext) -> Result<()> {
+    let manager = match ctx.config().node_version_manager() {
+        NodeVersionManager::Autodetect => [
+            NodeVersionManager::Volta,
+            NodeVersionManager::Fnm,
+            NodeVersionManager::Nvm,
+            NodeVersionManager::Nodenv,
+            NodeVersionManager::N,
+        ]
+        .into_iter()
+        .find(|manager| match manager {
+            NodeVersionManager::Nvm => nvm_script().is_some(),
+            NodeVersionManager::Fnm => require("fnm").is_ok(),
+            NodeVersionManager::Nodenv => require("nodenv").is_ok(),
+            NodeVersionManager::N => require("n").is_ok(),
+            NodeVersionManager::Volta => require("volta").is_ok(),
+            NodeVersionManager::Autodetect => false,
+        })
+        .ok_or_else(|| SkipStep(String::from("No Node version manager found")))?,
+        manager => manager,
+    };
+    let lts = ctx.config().node_channel() == NodeChannel::Lts;
+
+    print_separator("Node");
+
+    let version_before = default_node_version(manager);
+
+    match manager {
+        NodeVersionManager::Fnm => {
+            let fnm = require("fnm")?;
+            ctx.run_type()
+                .execute(&fnm)
+                .args(["install", if lts { "--lts" } else { "--latest" }])
+                .status_checked()?;
+            ctx.run_type()
+                .execute(&fnm)
+                .args(["default", if lts { "lts-latest" } else { "latest" }])
+                .status_checked()?;
+        }
+        NodeVersionManager::Nvm => {
+            let bash = require("bash")?;
+            let nvm = require_option(nvm_script(), String::from("nvm is not installed"))?;
+            let version = if lts { "lts/*" } else { "node" };
+            let mut script = format!("source {} && nvm install '{version}'", nvm.display());
+            if ctx.config().node_migrate_packages() {
+                script.push_str(" --reinstall-packages-from=current");
+            }
+            script.push_str(&format!(" && nvm alias default '{version}'"));
+            ctx.run_type().execute(bash).args(["-c", &script]).status_checked()?;
+        }
+        NodeVersionManager::Nodenv => run_nodenv(ctx, lts)?,
+        NodeVersionManager::N => {
+            let n = require("n")?;
+            ctx.run_type()
+                .execute(n)
+                .arg(if lts { "lts" } else { "latest" })
+                .status_checked()?;
+        }
+        NodeVersionManager::Volta => run_volta(ctx, lts)?,
+        NodeVersionManager::Autodetect => unreachable!("the version manager has been detected"),
+    }
+
+    if !ctx.run_type().dry() {
+        match (version_before, default_node_version(manager)) {
+            (Some(before), Some(after)) if before == after => println!("Node {after} is up to date"),
+            (Some(before), Some(after)) => println!("Node updated from {before} to {after}"),
+            (None, Some(after)) => println!("Node {after} installed"),
+            (_, None) => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// The default package managers and packages Volta installed, as `(name, version)`.
+fn volta_tools(volta: &Path) -> Vec<(String, String)> {
+    let Ok(output) = Command::new(volta)
+        .args(["list", "all", "--format", "plain"])
+        .output_checked_utf8()
+    else {
+        return Vec::new();
+    };
+
+    // package-manager yarn@4.0.2 (default)
+    // package cowsay@1.5.0 / cowsay, cowthink / node@20.10.0 npm@built-in (default)
+    output
+        .stdout
+        .lines()
+        .filter_map(|line| {
+            let (kind, rest) = line.split_once(' ')?;
+            if kind != "package" && kind != "package-manager" {
+                return None;
+            }
+            let tool = rest.split_whitespace().next()?;
+            // Scoped packages start with `@`
+            let (name, version) = tool.rsplit_once('@').filter(|(name, _)| !name.is_empty())?;
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+fn run_volta(ctx: &ExecutionContext, lts: bool) -> Result<()> {
+    let volta = require("volta")?;
+    let node = if lts { "node@lts" } else { "node@latest" };
+    let tools = volta_tools(&volta);
+
+    if ctx.run_type().dry() {
+        println!("Would install {node} with Volta");
+        for (name, version) in &tools {
+            println!("Would install {name}@latest (installed: {version})");
+        }
+        return Ok(());
+    }
+
+    ctx.run_type()
+        .execute(&volta)
+        .args(["install", node])
+        .status_checked()?;
+    for (name, _) in &tools {
+        ctx.run_type()
+            .execute(&volta)
+            .args(["install", &format!("{name}@latest")])
+            .status_checked()?;
+    }
+
+    let after = volta_tools(&volta);
+    for (name, version) in &tools {
+        if let Some((_, new)) = after.iter().find(|(new_name, new)| new_name == name && new != version) {
+            println!("{name} updated from {version} to {new}");
+        }
+    }
+    Ok(())
+}
+
+/// The newest version node-build can install, even major versions being the LTS ones.
+fn nodenv_latest_version(nodenv: &Path, lts: bool) -> Result<Version> {
+    let output = Command::new(nodenv)
+        .args(["install", "--list"])
+        .output_checked_utf8()?
+        .stdout;
+    output
+        .lines()
+        .filter_map(|line| Version::parse(line.trim()).ok())
+        .filter(|version| !lts || version.major % 2 == 0)
+        .max()
+        .ok_or_else(|| eyre!("nodenv did not list any Node version, is node-build installed?"))
+}
+
+fn run_nodenv(ctx: &ExecutionContext, lts: bool) -> Result<()> {
+    let nodenv = require("nodenv")?;
+    let version = nodenv_latest_version(&nodenv, lts)?.to_string();
+
+    if ctx.run_type().dry() {
+        println!("Would install Node {version} with nodenv and update the global packages");
+        return Ok(());
+    }
+
+    ctx.run_type()
+        .execute(&nodenv)
+        .args(["install", "--skip-existing", &version])
+        .status_checked()?;
+    ctx.run_type()
+        .execute(&nodenv)
+        .args(["global", &version])
+        .status_checked()?;
+
+    let versions = Command::new(&nodenv)
+        .args(["versions", "--bare"])
+        .output_checked_utf8()?
+        .stdout;
+    for version in versions.lines().map(str::trim).filter(|version| !version.is_empty()) {
+        println!("Updating the global packages of Node {version}");
+        ctx.run_type()
+            .execute(&nodenv)
+            .args(["exec", "npm", "update", "--global"])
+            .env("NODENV_VERSION", version)
+            .status_checked()?;
+    }
+    // Global packages may have added executables
+    ctx.run_type().execute(&nodenv).arg("rehash").status_checked()
+}
+
 pub fn deno_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let deno = require("deno")?;
     let deno_dir = HOME_DIR.join(".deno");
@@ -1,16 +1,18 @@
 use std::env::var_os;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 use color_eyre::eyre;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Context, Result};
 use walkdir::WalkDir;
 
 use crate::command::CommandExt;
-use crate::error::TopgradeError;
+use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
 use crate::sudo::Sudo;
-use crate::utils::which;
+use crate::terminal::{add_summary_note, print_separator, shell};
+use crate::utils::{require, require_option, which, REQUIRE_SUDO};
 use crate::{config, Step};
 
 fn get_execution_path() -> OsString {
@@ -46,6 +48,12 @@ impl ArchPackageManager for YayParu {
             .args(ctx.config().yay_arguments().split_whitespace())
             .env("PATH", get_execution_path());
 
+        // The `aur` step upgrades the AUR packages then
+        if ctx.config().aur_only() {
+            command.arg("--repo");
+        } else if !ctx.config().aur_ignore().is_empty() {
+            command.arg("--ignore").arg(ctx.config().aur_ignore().join(","));
+        }
         if ctx.config().yes(Step::System) {
             command.arg("--noconfirm");
         }
@@ -336,14 +344,131 @@ pub fn get_arch_package_manager(ctx: &ExecutionContext) -> Option<Box<dyn ArchPa
     }
 }
 
+/// The installed version of `archlinux-keyring`.
+fn keyring_version() -> Option<String> {
+    Command::new("pacman")
+        .args(["-Q", "archlinux-keyring"])
+        .output_checked_utf8()
+        .ok()
+        .and_then(|output| output.stdout.split_whitespace().nth(1).map(str::to_string))
+}
+
+/// Update `archlinux-keyring` on its own, a stale keyring fails the signature checks of the
+/// packages signed by newer keys.
+fn update_keyring(ctx: &ExecutionContext) -> Result<()> {
+    let Some(before) = keyring_version() else {
+        println!("archlinux-keyring is not installed, not updating it first");
+        return Ok(());
+    };
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.args(["pacman", "-Sy", "--needed", "archlinux-keyring"]);
+    if ctx.config().yes(Step::System) {
+        command.arg("--noconfirm");
+    }
+    command.status_checked().context("Failed to update archlinux-keyring")?;
+
+    if ctx.config().arch_refresh_keys() {
+        ctx.run_type()
+            .execute(sudo)
+            .args(["pacman-key", "--refresh-keys"])
+            .status_checked()
+            .context("Failed to refresh the pacman keys")?;
+    }
+
+    if !ctx.run_type().dry() {
+        match keyring_version() {
+            Some(after) if after != before => println!("archlinux-keyring updated from {before} to {after}"),
+            _ => println!("archlinux-keyring {before} is up to date"),
+        }
+    }
+
+    Ok(())
+}
+
 pub fn upgrade_arch_linux(ctx: &ExecutionContext) -> Result<()> {
     let package_manager =
         get_arch_package_manager(ctx).ok_or_else(|| eyre::Report::from(TopgradeError::FailedGettingPackageManager))?;
+    if ctx.config().arch_update_keyring_first() {
+        update_keyring(ctx)?;
+    }
     package_manager.upgrade(ctx)
 }
 
-pub fn show_pacnew() {
-    let mut iter = WalkDir::new("/etc")
+/// Upgrade the AUR packages apart from the repository ones, with `aur_only`.
+pub fn run_aur_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().aur_only() {
+        return Err(SkipStep(String::from(
+            "The AUR packages are upgraded with the system, set `aur_only` to upgrade them separately",
+        ))
+        .into());
+    }
+    let helper = match ctx.config().arch_package_manager() {
+        config::ArchPackageManager::Paru => which("paru"),
+        config::ArchPackageManager::Yay => which("yay"),
+        _ => which("paru").or_else(|| which("yay")),
+    }
+    .ok_or_else(|| SkipStep(String::from("No AUR helper (paru or yay) found")))?;
+
+    print_separator("AUR");
+
+    let ignored = ctx.config().aur_ignore();
+    let upgrades: Vec<String> = Command::new(&helper)
+        .arg("-Qua")
+        .env("PATH", get_execution_path())
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter(|line| {
+                    line.split_whitespace()
+                        .next()
+                        .is_some_and(|package| !ignored.iter().any(|ignored| ignored == package))
+                })
+                .map(str::to_string)
+                .collect()
+        })
+        // `-Qua` fails when there is nothing to upgrade
+        .unwrap_or_default();
+
+    if upgrades.is_empty() {
+        println!("The AUR packages are up to date");
+        return Ok(());
+    }
+
+    let mut command = ctx.run_type().execute(&helper);
+    command
+        .arg("-Sua")
+        .args(ctx.config().yay_arguments().split_whitespace())
+        .env("PATH", get_execution_path());
+    if !ignored.is_empty() {
+        command.arg("--ignore").arg(ignored.join(","));
+    }
+    if ctx.config().yes(Step::System) {
+        command.arg("--noconfirm");
+    }
+    command.status_checked()?;
+
+    println!(
+        "{}",
+        if ctx.run_type().dry() {
+            "AUR packages that would be rebuilt:"
+        } else {
+            "Rebuilt AUR packages:"
+        }
+    );
+    for upgrade in upgrades {
+        println!("  {upgrade}");
+    }
+
+    Ok(())
+}
+
+/// The `.pacnew` and `.pacsave` files pacman left in `/etc`.
+fn pacnew_files() -> Vec<PathBuf> {
+    WalkDir::new("/etc")
         .into_iter()
         .filter_map(Result::ok)
         .filter(|f| {
@@ -352,13 +477,71 @@ pub fn show_pacnew() {
                 .filter(|ext| ext == &"pacnew" || ext == &"pacsave")
                 .is_some()
         })
-        .peekable();
+        .map(|entry| entry.into_path())
+        .collect()
+}
 
-    if iter.peek().is_some() {
+pub fn show_pacnew() {
+    let files = pacnew_files();
+    if !files.is_empty() {
         println!("\nPacman backup configuration files found:");
 
-        for entry in iter {
-            println!("{}", entry.path().display());
+        for file in files {
+            println!("{}", file.display());
+        }
+    }
+}
+
+const PACMAN_LOCK: &str = "/var/lib/pacman/db.lck";
+
+/// Report a pacman database lock left behind and merge or report the `.pacnew` and `.pacsave` files,
+/// with `pacman_check`.
+pub fn run_pacman_check(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().pacman_check() {
+        return Err(SkipStep(String::from("`pacman_check` is not enabled")).into());
+    }
+    require("pacman")?;
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    print_separator("pacman check");
+
+    // paru, yay, pamac or packagekitd may hold the lock too, so it is only reported, never removed
+    if Path::new(PACMAN_LOCK).exists() {
+        println!("The pacman database is locked by {PACMAN_LOCK}");
+        add_summary_note(format!(
+            "The pacman database is locked, remove {PACMAN_LOCK} if no package manager is running"
+        ));
+    }
+
+    let mut files = pacnew_files();
+    let merge_tool = ctx
+        .config()
+        .pacman_merge_tool()
+        .filter(|_| ctx.config().yes(Step::System));
+    if let Some(tool) = merge_tool.filter(|_| !files.is_empty()) {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(shell())
+            .args(["-c", tool])
+            .status_checked()
+            .with_context(|| format!("Failed to merge the pacman configuration files with `{tool}`"))?;
+        if !ctx.run_type().dry() {
+            files = pacnew_files();
         }
     }
+
+    if files.is_empty() {
+        println!("No .pacnew or .pacsave files to merge");
+    } else {
+        println!("Configuration files to merge:");
+        for file in &files {
+            println!("  {}", file.display());
+        }
+        add_summary_note(format!(
+            "{} .pacnew or .pacsave file(s) in /etc need merging, for example with `pacdiff`",
+            files.len()
+        ));
+    }
+
+    Ok(())
 }
@@ -1,12 +1,22 @@
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
+use crate::executor::{Executor, RunType};
 use crate::terminal::print_separator;
-use crate::utils::{require_option, REQUIRE_SUDO};
+use crate::utils::{require_option, which, PathExt, NO_METADATA_REFRESH, REQUIRE_SUDO};
 use crate::Step;
 use color_eyre::eyre::Result;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 pub fn upgrade_freebsd(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(NO_METADATA_REFRESH.to_string()).into());
+    }
+    if !ctx.config().freebsd_update() {
+        return Err(SkipStep(String::from("`freebsd_update` is disabled")).into());
+    }
+
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     print_separator("FreeBSD Update");
     ctx.run_type()
@@ -15,17 +25,64 @@ pub fn upgrade_freebsd(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+/// The upgrades listed by `pkg upgrade -n`, as `name: old -> new`.
+fn pkg_pending_upgrades(pkg: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(pkg).args(["upgrade", "-n"]).output_checked_utf8() else {
+        return Vec::new();
+    };
+
+    output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.contains(" -> "))
+        .map(String::from)
+        .collect()
+}
+
 pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    // /usr/sbin is usually not in the PATH of regular users
+    let pkg = which("pkg")
+        .or_else(|| PathBuf::from("/usr/sbin/pkg").if_exists())
+        .ok_or_else(|| SkipStep(String::from("pkg is not installed")))?;
+    let sudo = if nix::unistd::Uid::effective().is_root() {
+        None
+    } else {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    };
+    let pkg_command = |run_type: RunType| -> Executor {
+        match sudo {
+            Some(sudo) => {
+                let mut command = run_type.execute(sudo);
+                command.arg(&pkg);
+                command
+            }
+            None => run_type.execute(&pkg),
+        }
+    };
     print_separator("FreeBSD Packages");
 
-    let mut command = ctx.run_type().execute(sudo);
+    pkg_command(ctx.run_type()).arg("update").status_checked()?;
+
+    if ctx.run_type().dry() {
+        return pkg_command(RunType::Wet).args(["upgrade", "-n"]).status_checked();
+    }
 
-    command.args(["/usr/sbin/pkg", "upgrade"]);
+    let pending = pkg_pending_upgrades(&pkg);
+    let mut command = pkg_command(ctx.run_type());
+    command.arg("upgrade");
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
-    command.status_checked()
+    command.status_checked()?;
+
+    if !pending.is_empty() {
+        println!("Upgraded packages:");
+        for upgrade in pending {
+            println!("  {upgrade}");
+        }
+    }
+    Ok(())
 }
 
 pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
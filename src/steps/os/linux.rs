@@ -1,17 +1,28 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Context, Result};
 use ini::Ini;
 use tracing::{debug, warn};
 
+use crate::breaking_changes::data_dir;
 use crate::command::CommandExt;
+use crate::config::Config;
 use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
+use crate::executor::RunType;
 use crate::steps::generic::is_wsl;
 use crate::steps::os::archlinux;
-use crate::terminal::{print_separator, prompt_yesno};
-use crate::utils::{require, require_option, which, PathExt, REQUIRE_SUDO};
+pub use crate::steps::os::archlinux::{run_aur_update, run_pacman_check};
+use crate::sudo::Sudo;
+use crate::terminal::{add_summary_note, print_separator, print_warning, prompt_yesno, shell};
+use crate::utils::{require, require_option, which, PathExt, NO_METADATA_REFRESH, REQUIRE_SUDO};
 use crate::{Step, HOME_DIR};
 
 static OS_RELEASE_PATH: &str = "/etc/os-release";
@@ -33,6 +44,7 @@ pub enum Distribution {
     OpenMandriva,
     OpenSuseTumbleweed,
     PCLinuxOS,
+    Slackware,
     Suse,
     SuseMicro,
     Vanilla,
@@ -83,6 +95,7 @@ impl Distribution {
             Some("neon") => Distribution::KDENeon,
             Some("openmandriva") => Distribution::OpenMandriva,
             Some("pclinuxos") => Distribution::PCLinuxOS,
+            Some("slackware") => Distribution::Slackware,
             _ => {
                 if let Some(name) = name {
                     if name.contains("Vanilla") {
@@ -119,6 +132,11 @@ impl Distribution {
             return Ok(Distribution::Bedrock);
         }
 
+        // Atomic variants that `parse_os_release` doesn't know about, such as the Universal Blue images
+        if PathBuf::from("/run/ostree-booted").exists() && which("rpm-ostree").is_some() {
+            return Ok(Distribution::FedoraImmutable);
+        }
+
         if PathBuf::from(OS_RELEASE_PATH).exists() {
             let os_release = Ini::load_from_file(OS_RELEASE_PATH)?;
 
@@ -133,12 +151,14 @@ impl Distribution {
     }
 
     pub fn upgrade(self, ctx: &ExecutionContext) -> Result<()> {
+        if ctx.config().metadata_only() {
+            return self.refresh_metadata(ctx);
+        }
+
         print_separator("System update");
 
         match self {
-            Distribution::Alpine => upgrade_alpine_linux(ctx),
-            Distribution::Chimera => upgrade_chimera_linux(ctx),
-            Distribution::Wolfi => upgrade_wolfi_linux(ctx),
+            Distribution::Alpine | Distribution::Chimera | Distribution::Wolfi => upgrade_apk(ctx),
             Distribution::Arch => archlinux::upgrade_arch_linux(ctx),
             Distribution::CentOS | Distribution::Fedora => upgrade_redhat(ctx),
             Distribution::FedoraImmutable => upgrade_fedora_immutable(ctx),
@@ -157,10 +177,60 @@ impl Distribution {
             Distribution::Bedrock => update_bedrock(ctx),
             Distribution::OpenMandriva => upgrade_openmandriva(ctx),
             Distribution::PCLinuxOS => upgrade_pclinuxos(ctx),
+            Distribution::Slackware => upgrade_slackware(ctx),
             Distribution::Nobara => upgrade_nobara(ctx),
         }
     }
 
+    /// Refresh the package indexes and list the outdated packages, for `--metadata-only`.
+    fn refresh_metadata(self, ctx: &ExecutionContext) -> Result<()> {
+        if !matches!(
+            self,
+            Distribution::Alpine
+                | Distribution::Arch
+                | Distribution::CentOS
+                | Distribution::Debian
+                | Distribution::Fedora
+                | Distribution::OpenSuseTumbleweed
+                | Distribution::Suse
+        ) {
+            return Err(SkipStep(format!("No metadata refresh is known for {self:?}")).into());
+        }
+
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        let run_type = ctx.run_type();
+
+        print_separator("System metadata");
+
+        match self {
+            Distribution::Alpine => {
+                run_type.execute(sudo).args(["apk", "update"]).status_checked()?;
+                run_type.execute("apk").args(["version", "-l", "<"]).status_checked()
+            }
+            Distribution::Arch => {
+                run_type.execute(sudo).args(["pacman", "-Sy"]).status_checked()?;
+                // pacman exits with 1 when nothing is outdated
+                run_type.execute("pacman").arg("-Qu").status_checked_with_codes(&[1])
+            }
+            Distribution::CentOS | Distribution::Fedora => {
+                // check-update exits with 100 when updates are available
+                run_type
+                    .execute(sudo)
+                    .arg(which("dnf").unwrap_or_else(|| PathBuf::from("yum")))
+                    .args(["check-update", "--refresh"])
+                    .status_checked_with_codes(&[100])
+            }
+            Distribution::Debian => {
+                run_type.execute(sudo).args(["apt-get", "update"]).status_checked()?;
+                run_type.execute("apt").args(["list", "--upgradable"]).status_checked()
+            }
+            _ => {
+                run_type.execute(sudo).args(["zypper", "refresh"]).status_checked()?;
+                run_type.execute("zypper").arg("list-updates").status_checked()
+            }
+        }
+    }
+
     pub fn show_summary(self) {
         if let Distribution::Arch = self {
             archlinux::show_pacnew();
@@ -196,61 +266,237 @@ fn update_bedrock(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-fn upgrade_alpine_linux(ctx: &ExecutionContext) -> Result<()> {
-    let apk = require("apk")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+/// The upgrades `apk upgrade --simulate` would install, as `name (old -> new)`.
+fn apk_pending_upgrades(apk: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(apk).args(["upgrade", "--simulate"]).output_checked_utf8() else {
+        return Vec::new();
+    };
 
-    ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
+    // (1/2) Upgrading musl (1.2.4-r1 -> 1.2.4-r2)
+    output
+        .stdout
+        .lines()
+        .filter_map(|line| line.split_once(" Upgrading "))
+        .map(|(_, upgrade)| upgrade.trim().to_string())
+        .collect()
 }
 
-fn upgrade_chimera_linux(ctx: &ExecutionContext) -> Result<()> {
+/// Alpine, Chimera and Wolfi all use apk, containers usually run it as root without sudo.
+fn upgrade_apk(ctx: &ExecutionContext) -> Result<()> {
     let apk = require("apk")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let sudo = if nix::unistd::Uid::effective().is_root() {
+        None
+    } else {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    };
+    let apk_command = || match sudo {
+        Some(sudo) => {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(&apk);
+            command
+        }
+        None => ctx.run_type().execute(&apk),
+    };
 
-    ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
-}
+    apk_command().arg("update").status_checked()?;
 
-fn upgrade_wolfi_linux(ctx: &ExecutionContext) -> Result<()> {
-    let apk = require("apk")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    if ctx.run_type().dry() {
+        return RunType::Wet
+            .execute(&apk)
+            .args(["upgrade", "--simulate"])
+            .status_checked();
+    }
+
+    let pending = apk_pending_upgrades(&apk);
+    let mut command = apk_command();
+    command.arg("upgrade");
+    if ctx.config().yes(Step::System) {
+        command.arg("--no-interactive");
+    }
+    command.status_checked()?;
 
-    ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
+    if pending.is_empty() {
+        println!("The apk packages are up to date");
+    } else {
+        println!("Upgraded packages:");
+        for upgrade in pending {
+            println!("  {upgrade}");
+        }
+    }
+    Ok(())
 }
 
 fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(ostree) = which("rpm-ostree") {
-        if ctx.config().rpm_ostree() {
-            let mut command = ctx.run_type().execute(ostree);
-            command.arg("upgrade");
-            return command.status_checked();
-        }
+    if which("rpm-ostree").is_some() && ctx.config().rpm_ostree() {
+        return upgrade_fedora_immutable(ctx);
     };
 
+    let dnf = require("dnf").or_else(|_| require("yum"))?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let mut command = ctx.run_type().execute(sudo);
-    command
-        .arg(which("dnf").unwrap_or_else(|| Path::new("yum").to_path_buf()))
-        .arg(if ctx.config().redhat_distro_sync() {
+
+    // check-update doesn't need root and exits with 100 when updates are available
+    let pending = dnf_pending_updates(&dnf);
+    if ctx.run_type().dry() {
+        if pending.is_empty() {
+            println!("The packages are up to date");
+        } else {
+            println!("Available updates: {}", pending.join(", "));
+        }
+    } else {
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(&dnf).arg(if ctx.config().redhat_distro_sync() {
             "distro-sync"
         } else {
             "upgrade"
         });
 
-    if let Some(args) = ctx.config().dnf_arguments() {
-        command.args(args.split_whitespace());
-    }
+        if let Some(args) = ctx.config().dnf_arguments() {
+            command.args(args.split_whitespace());
+        }
 
-    if ctx.config().yes(Step::System) {
-        command.arg("-y");
+        if let Some(limit) = ctx.config().bandwidth_limit() {
+            debug!("Limiting the dnf downloads to {limit} KB/s");
+            command.arg(format!("--setopt=throttle={limit}k"));
+        }
+
+        if ctx.config().yes(Step::System) {
+            command.arg("-y");
+        }
+
+        command.status_checked()?;
+        if !pending.is_empty() {
+            println!("Upgraded packages: {}", pending.join(", "));
+        }
+
+        if ctx.config().report_pending_security() {
+            report_pending_security("dnf", &dnf_pending_security(&dnf));
+        }
     }
 
-    command.status_checked()?;
+    for (module, enabled, default) in dnf_outdated_module_streams(&dnf) {
+        add_summary_note(format!(
+            "The {module}:{enabled} module stream is enabled but {default} is the default, switch with `sudo dnf module switch-to {module}:{default}`"
+        ));
+    }
     Ok(())
 }
 
+/// The packages with a security advisory which are still not upgraded.
+fn dnf_pending_security(dnf: &Path) -> Vec<String> {
+    // FEDORA-2024-0123456789  Important/Sec.  openssl-1:3.1.4-2.fc39.x86_64
+    Command::new(dnf)
+        .args(["updateinfo", "list", "--updates", "security", "--quiet"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [_, severity, package] if severity.ends_with("Sec.") => Some(package.to_string()),
+                    _ => None,
+                })
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The upgradable packages coming from a security suite, such as `jammy-security`.
+fn apt_pending_security() -> Vec<String> {
+    // Listing...
+    // openssl/jammy-security 3.0.2-0ubuntu1.12 amd64 [upgradable from: 3.0.2-0ubuntu1.10]
+    Command::new("apt")
+        .args(["list", "--upgradable"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| line.split_whitespace().next()?.split_once('/'))
+                .filter(|(_, suites)| suites.split(',').any(|suite| suite.ends_with("-security")))
+                .map(|(package, _)| package.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Flag the security updates `manager` didn't install in the summary.
+fn report_pending_security(manager: &str, pending: &[String]) {
+    if pending.is_empty() {
+        return;
+    }
+    print_warning(format!("Security updates are still pending: {}", pending.join(", ")));
+    add_summary_note(format!(
+        "SECURITY: {manager} didn't install {} security update(s): {}",
+        pending.len(),
+        pending.join(", ")
+    ));
+}
+
+/// The packages `check-update` lists, as `name.arch`.
+fn dnf_pending_updates(dnf: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(dnf)
+        .args(["check-update", "--quiet"])
+        .output_checked_with_utf8(|output| match output.status.code() {
+            Some(0) | Some(100) => Ok(()),
+            _ => Err(()),
+        })
+    else {
+        return Vec::new();
+    };
+
+    // <name>.<arch>    <version>    <repository>, until the obsoleted packages
+    output
+        .stdout
+        .lines()
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter(|line| line.split_whitespace().count() == 3)
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+/// The enabled module streams that aren't the default stream anymore, as `(module, enabled, default)`.
+fn dnf_outdated_module_streams(dnf: &Path) -> Vec<(String, String, String)> {
+    // dnf5 dropped modularity, the command fails there
+    let Ok(output) = Command::new(dnf)
+        .args(["module", "list", "--quiet"])
+        .output_checked_utf8()
+    else {
+        return Vec::new();
+    };
+    outdated_module_streams(&output.stdout)
+}
+
+/// The enabled module streams that aren't the default stream in the output of `dnf module list`.
+fn outdated_module_streams(list: &str) -> Vec<(String, String, String)> {
+    // nodejs    18 [e]    common [d]    Javascript runtime
+    let mut streams: BTreeMap<String, (Option<String>, Option<String>)> = BTreeMap::new();
+    for line in list.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(module), Some(stream)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let flags: String = fields.take_while(|field| field.starts_with('[')).collect();
+        let entry = streams.entry(module.to_string()).or_default();
+        if flags.contains("[e]") {
+            entry.0 = Some(stream.to_string());
+        }
+        if flags.contains("[d]") {
+            entry.1 = Some(stream.to_string());
+        }
+    }
+
+    streams
+        .into_iter()
+        .filter_map(|(module, streams)| match streams {
+            (Some(enabled), Some(default)) if enabled != default => Some((module, enabled, default)),
+            _ => None,
+        })
+        .collect()
+}
+
 fn upgrade_nobara(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let pkg_manager = require("dnf")?;
@@ -287,9 +533,32 @@ fn upgrade_nobara(ctx: &ExecutionContext) -> Result<()> {
 
 fn upgrade_fedora_immutable(ctx: &ExecutionContext) -> Result<()> {
     let ostree = require("rpm-ostree")?;
-    let mut command = ctx.run_type().execute(ostree);
-    command.arg("upgrade");
-    command.status_checked()?;
+
+    if ctx.run_type().dry() {
+        // `--check` only looks for an update, it exits with 77 when there is none
+        return RunType::Wet
+            .execute(&ostree)
+            .args(["upgrade", "--check"])
+            .status_checked_with_codes(&[77]);
+    }
+
+    // 77 means there was nothing to upgrade
+    let unchanged = Cell::new(false);
+    ctx.run_type()
+        .execute(&ostree)
+        .args(["upgrade", "--unchanged-exit-77"])
+        .status_checked_with(|status| match status.code() {
+            Some(0) => Ok(()),
+            Some(77) => {
+                unchanged.set(true);
+                Ok(())
+            }
+            _ => Err(()),
+        })?;
+
+    if !unchanged.get() {
+        add_summary_note("Reboot required: rpm-ostree staged a new deployment for the next boot");
+    }
     Ok(())
 }
 
@@ -358,7 +627,7 @@ fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let mut command = ctx.run_type().execute(sudo);
 
-    command.arg(&which("dnf").unwrap()).arg("upgrade");
+    command.arg(which("dnf").unwrap()).arg("upgrade");
 
     if let Some(args) = ctx.config().dnf_arguments() {
         command.args(args.split_whitespace());
@@ -377,7 +646,7 @@ fn upgrade_pclinuxos(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let mut command_update = ctx.run_type().execute(sudo);
 
-    command_update.arg(&which("apt-get").unwrap()).arg("update");
+    command_update.arg(which("apt-get").unwrap()).arg("update");
 
     if let Some(args) = ctx.config().dnf_arguments() {
         command_update.args(args.split_whitespace());
@@ -390,7 +659,7 @@ fn upgrade_pclinuxos(ctx: &ExecutionContext) -> Result<()> {
     command_update.status_checked()?;
 
     let mut cmd = ctx.run_type().execute(sudo);
-    cmd.arg(&which("apt-get").unwrap());
+    cmd.arg(which("apt-get").unwrap());
     cmd.arg("dist-upgrade");
     if ctx.config().yes(Step::System) {
         cmd.arg("-y");
@@ -420,28 +689,146 @@ fn upgrade_vanilla(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The packages `xbps-install -un` would update, from the already synced repository index.
+fn xbps_pending_updates(xbps_install: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(xbps_install).arg("-un").output_checked_utf8() else {
+        return Vec::new();
+    };
+    xbps_updates(&output.stdout)
+}
+
+/// The packages to update in the output of `xbps-install -n`, as `<name>-<version>`.
+fn xbps_updates(transaction: &str) -> Vec<String> {
+    // <pkgver> <action> <arch> <repository> <installed size> <download size>
+    transaction
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pkgver = fields.next()?;
+            (fields.next()? == "update").then(|| pkgver.to_string())
+        })
+        .collect()
+}
+
 fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
+    let xbps_install = require("xbps-install")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    if ctx.run_type().dry() {
+        // Syncing the repository index needs root, `-n` doesn't install anything
+        return RunType::Wet
+            .execute(sudo)
+            .arg(&xbps_install)
+            .arg("-Sun")
+            .status_checked();
+    }
+
+    // xbps has to be updated on its own before the other packages
     let mut command = ctx.run_type().execute(sudo);
-    command.args(["xbps-install", "-Su", "xbps"]);
+    command.arg(&xbps_install).args(["-Su", "xbps"]);
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
     command.status_checked()?;
 
+    let pending = xbps_pending_updates(&xbps_install);
     let mut command = ctx.run_type().execute(sudo);
-    command.args(["xbps-install", "-u"]);
+    command.arg(&xbps_install).arg("-u");
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
     command.status_checked()?;
 
+    if !pending.is_empty() {
+        println!("Upgraded packages:");
+        for pkgver in pending {
+            println!("  {pkgver}");
+        }
+    }
+    Ok(())
+}
+
+/// The titles of the unread Gentoo news items.
+fn gentoo_unread_news() -> Vec<String> {
+    let Ok(output) = Command::new("eselect").args(["news", "list"]).output_checked_utf8() else {
+        return Vec::new();
+    };
+    unread_news(&output.stdout)
+}
+
+/// The titles of the unread news items in the output of `eselect news list`.
+fn unread_news(list: &str) -> Vec<String> {
+    //   [12]  N     2024-01-01  Some title
+    list.lines()
+        .filter_map(|line| line.split_once(']'))
+        .filter_map(|(_, item)| item.trim_start().strip_prefix("N "))
+        .filter_map(|item| item.trim_start().split_once(char::is_whitespace))
+        .map(|(_date, title)| title.trim().to_string())
+        .collect()
+}
+
+/// The packages slackpkg lists for `upgrade-all`, answering no so nothing is installed.
+fn slackpkg_upgradable(slackpkg: &Path, sudo: &Sudo) -> Result<Vec<String>> {
+    let output = RunType::Wet
+        .execute(sudo)
+        .arg(slackpkg)
+        .args(["-dialog=off", "-batch=on", "-default_answer=n", "upgrade-all"])
+        .output_checked_utf8()?;
+    debug!("slackpkg upgrade-all: {:?}", output.stdout);
+
+    Ok(output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.ends_with(".txz") || line.ends_with(".tgz"))
+        .map(String::from)
+        .collect())
+}
+
+fn upgrade_slackware(ctx: &ExecutionContext) -> Result<()> {
+    let slackpkg = require("slackpkg")?;
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let yes = ctx.config().yes(Step::System);
+
+    // The package lists are only metadata, they are also refreshed on dry runs
+    let mut command = RunType::Wet.execute(sudo);
+    command.arg(&slackpkg);
+    if yes {
+        command.args(["-dialog=off", "-batch=on", "-default_answer=y"]);
+    }
+    command.arg("update").status_checked()?;
+
+    let upgradable = slackpkg_upgradable(&slackpkg, sudo)?;
+    if upgradable.is_empty() {
+        println!("The packages are up to date");
+        return Ok(());
+    }
+    if ctx.run_type().dry() {
+        println!("Upgradable packages:");
+        for package in upgradable {
+            println!("  {package}");
+        }
+        return Ok(());
+    }
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&slackpkg);
+    if yes {
+        command.args(["-dialog=off", "-batch=on", "-default_answer=y"]);
+    }
+    command.arg("upgrade-all").status_checked()?;
+
+    println!("Upgraded packages:");
+    for package in upgradable {
+        println!("  {package}");
+    }
     Ok(())
 }
 
 fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
     let run_type = ctx.run_type();
 
+    let emerge = require("emerge")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     if let Some(layman) = which("layman") {
         run_type
@@ -452,33 +839,238 @@ fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
     }
 
     println!("Syncing portage");
-    run_type
-        .execute(sudo)
-        .args(["emerge", "--sync"])
-        .args(
-            ctx.config()
-                .emerge_sync_flags()
-                .map(|s| s.split_whitespace().collect())
-                .unwrap_or_else(|| vec!["-q"]),
-        )
-        .status_checked()?;
+    if let Some(eix_sync) = which("eix-sync") {
+        // Syncs the repositories and updates the eix cache
+        run_type.execute(sudo).arg(eix_sync).status_checked()?;
+    } else {
+        run_type
+            .execute(sudo)
+            .arg(&emerge)
+            .arg("--sync")
+            .args(
+                ctx.config()
+                    .emerge_sync_flags()
+                    .map(|s| s.split_whitespace().collect())
+                    .unwrap_or_else(|| vec!["-q"]),
+            )
+            .status_checked()?;
 
-    if let Some(eix_update) = which("eix-update") {
-        run_type.execute(sudo).arg(eix_update).status_checked()?;
+        if let Some(eix_update) = which("eix-update") {
+            run_type.execute(sudo).arg(eix_update).status_checked()?;
+        }
     }
 
-    run_type
+    let update_flags: Vec<&str> = ctx
+        .config()
+        .emerge_update_flags()
+        .map(|s| s.split_whitespace().collect())
+        .unwrap_or_else(|| vec!["-uDNa", "--with-bdeps=y", "world"]);
+    if run_type.dry() {
+        // `--pretend` shows what would be merged and doesn't need root
+        RunType::Wet
+            .execute(&emerge)
+            .args(&update_flags)
+            .arg("--pretend")
+            .status_checked()?;
+    } else {
+        run_type
+            .execute(sudo)
+            .arg(&emerge)
+            .args(&update_flags)
+            .status_checked()?;
+    }
+
+    let news = gentoo_unread_news();
+    if !news.is_empty() {
+        add_summary_note(format!(
+            "{} unread Gentoo news item(s), read them with `eselect news read`: {}",
+            news.len(),
+            news.join("; ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Count the upgrades apt defers because of phasing, by simulating the upgrade without root.
+fn apt_phased_updates() -> usize {
+    let Ok(output) = Command::new("apt-get")
+        .args(["--simulate", "dist-upgrade"])
+        .env("LC_ALL", "C")
+        .output_checked_utf8()
+    else {
+        return 0;
+    };
+    phased_updates(&output.stdout)
+}
+
+/// Count the upgrades deferred because of phasing in the output of `apt-get --simulate`.
+fn phased_updates(simulation: &str) -> usize {
+    // The deferred packages are listed, indented, under this heading
+    simulation
+        .lines()
+        .skip_while(|line| !line.starts_with("The following upgrades have been deferred due to phasing"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .map(|line| line.split_whitespace().count())
+        .sum()
+}
+
+/// The packages `dpkg --audit` reports as half-installed, half-configured or otherwise broken.
+fn dpkg_audit() -> Vec<String> {
+    let Ok(output) = Command::new("dpkg")
+        .arg("--audit")
+        .env("LC_ALL", "C")
+        .output_checked_utf8()
+    else {
+        return Vec::new();
+    };
+
+    // Each explanation is followed by the packages, indented, with their description
+    output
+        .stdout
+        .lines()
+        .filter(|line| line.starts_with(' '))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+fn check_dpkg_audit(ctx: &ExecutionContext, sudo: &Sudo) -> Result<()> {
+    let broken = dpkg_audit();
+    if broken.is_empty() {
+        return Ok(());
+    }
+
+    print_warning(format!("dpkg left packages unconfigured: {}", broken.join(", ")));
+    if ctx.config().dpkg_configure_pending() && ctx.config().yes(Step::System) {
+        ctx.run_type()
+            .execute(sudo)
+            .args(["dpkg", "--configure", "-a"])
+            .status_checked()?;
+        if ctx.run_type().dry() || dpkg_audit().is_empty() {
+            return Ok(());
+        }
+    }
+
+    add_summary_note(format!(
+        "dpkg left {} package(s) unconfigured ({}), run `sudo dpkg --configure -a` to finish their installation",
+        broken.len(),
+        broken.join(", ")
+    ));
+    Ok(())
+}
+
+/// Whether unattended-upgrade is running, it holds the dpkg lock while it does.
+fn unattended_upgrade_running() -> bool {
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    // The process name is truncated to 15 characters
+    processes
+        .flatten()
+        .filter_map(|process| std::fs::read_to_string(process.path().join("comm")).ok())
+        .any(|comm| comm.trim() == "unattended-upgr")
+}
+
+/// Wait for unattended-upgrade to finish, or skip the step, depending on `apt_wait_for_unattended`.
+fn wait_for_unattended_upgrade(ctx: &ExecutionContext) -> Result<()> {
+    if !unattended_upgrade_running() {
+        return Ok(());
+    }
+    if !ctx.config().apt_wait_for_unattended() {
+        return Err(SkipStep(String::from(
+            "unattended-upgrade is running, set `apt_wait_for_unattended = true` to wait for it",
+        ))
+        .into());
+    }
+    if ctx.run_type().dry() {
+        println!("unattended-upgrade is running, a real run would wait for it");
+        return Ok(());
+    }
+
+    let timeout = ctx.config().apt_unattended_timeout();
+    println!("Waiting up to {}s for unattended-upgrade to finish", timeout.as_secs());
+    let started = Instant::now();
+    while unattended_upgrade_running() {
+        if started.elapsed() > timeout {
+            return Err(SkipStep(format!(
+                "unattended-upgrade is still running after {}s",
+                timeout.as_secs()
+            ))
+            .into());
+        }
+        thread::sleep(Duration::from_secs(5));
+    }
+    Ok(())
+}
+
+/// Where apt keeps the package lists of `apt_sources_override`, apart from the system's ones.
+const APT_OVERRIDE_LISTS: &str = "/var/lib/apt/topgrade-lists";
+
+/// The apt options replacing the system's sources with `apt_sources_override` for one command.
+///
+/// The package lists go to their own directory too, so the system's ones still match its sources.
+fn apt_sources_override_args(ctx: &ExecutionContext, sudo: &Sudo) -> Result<Vec<String>> {
+    let Some(sources) = ctx.config().apt_sources_override() else {
+        return Ok(Vec::new());
+    };
+    let sources = sources.require()?;
+    debug!("apt uses the sources of {} only", sources.display());
+
+    ctx.run_type()
         .execute(sudo)
-        .arg("emerge")
-        .args(
-            ctx.config()
-                .emerge_update_flags()
-                .map(|s| s.split_whitespace().collect())
-                .unwrap_or_else(|| vec!["-uDNa", "--with-bdeps=y", "world"]),
-        )
+        .args(["mkdir", "-p", &format!("{APT_OVERRIDE_LISTS}/partial")])
         .status_checked()?;
 
-    Ok(())
+    Ok(vec![
+        String::from("-o"),
+        format!("Dir::Etc::sourcelist={}", sources.display()),
+        String::from("-o"),
+        String::from("Dir::Etc::sourceparts=-"),
+        String::from("-o"),
+        format!("Dir::State::Lists={APT_OVERRIDE_LISTS}"),
+    ])
+}
+
+/// The apt options limiting the downloads to `bandwidth_limit`.
+fn apt_bandwidth_limit_args(ctx: &ExecutionContext) -> Vec<String> {
+    let Some(limit) = ctx.config().bandwidth_limit() else {
+        return Vec::new();
+    };
+    debug!("Limiting the apt downloads to {limit} KB/s");
+    vec![
+        String::from("-o"),
+        format!("Acquire::http::Dl-Limit={limit}"),
+        String::from("-o"),
+        format!("Acquire::https::Dl-Limit={limit}"),
+    ]
+}
+
+/// The program and its first arguments running `binary` with its downloads limited to
+/// `bandwidth_limit` by `trickle`, for the package managers without their own limit.
+///
+/// `binary` runs unlimited when no limit is set or trickle isn't installed.
+fn trickle_wrapped(ctx: &ExecutionContext, binary: &Path) -> (PathBuf, Vec<OsString>) {
+    let Some(limit) = ctx.config().bandwidth_limit() else {
+        return (binary.to_path_buf(), Vec::new());
+    };
+    let Some(trickle) = which("trickle") else {
+        debug!(
+            "trickle is not installed, not limiting the downloads of {}",
+            binary.display()
+        );
+        return (binary.to_path_buf(), Vec::new());
+    };
+    debug!(
+        "Limiting the downloads of {} to {limit} KB/s with trickle",
+        binary.display()
+    );
+    // `-s` runs without the trickled daemon
+    (
+        trickle,
+        vec!["-s".into(), "-d".into(), limit.to_string().into(), binary.into()],
+    )
 }
 
 fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
@@ -513,16 +1105,28 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     }
 
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    wait_for_unattended_upgrade(ctx)?;
+    let sources_override = apt_sources_override_args(ctx, sudo)?;
+    let bandwidth_limit = apt_bandwidth_limit_args(ctx);
     if !is_nala {
         ctx.run_type()
             .execute(sudo)
             .arg(&apt)
+            .args(&sources_override)
+            .args(&bandwidth_limit)
             .arg("update")
             .status_checked_with_codes(&[0, 100])?;
     }
 
+    let include_phased = ctx.config().apt_include_phased();
+    let phased = if include_phased || is_nala {
+        0
+    } else {
+        apt_phased_updates()
+    };
+
     let mut command = ctx.run_type().execute(sudo);
-    command.arg(&apt);
+    command.arg(&apt).args(&sources_override).args(&bandwidth_limit);
     if is_nala {
         command.arg("upgrade");
     } else {
@@ -531,11 +1135,26 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
+    if include_phased {
+        command.args(["-o", "APT::Get::Always-Include-Phased-Updates=true"]);
+    }
     if let Some(args) = ctx.config().apt_arguments() {
         command.args(args.split_whitespace());
     }
     command.status_checked()?;
 
+    if phased > 0 {
+        add_summary_note(format!(
+            "apt held back {phased} package(s) still being phased in, set `apt_include_phased = true` to install them"
+        ));
+    }
+
+    check_dpkg_audit(ctx, sudo)?;
+
+    if ctx.config().report_pending_security() && !ctx.run_type().dry() {
+        report_pending_security("apt", &apt_pending_security());
+    }
+
     if ctx.config().cleanup() {
         ctx.run_type().execute(sudo).arg(&apt).arg("clean").status_checked()?;
 
@@ -565,15 +1184,126 @@ pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The field of a `.deb` file, such as its `Package` or `Version`.
+fn deb_field(deb: &Path, field: &str) -> Result<String> {
+    Ok(Command::new("dpkg-deb")
+        .arg("--field")
+        .arg(deb)
+        .arg(field)
+        .output_checked_utf8()?
+        .stdout
+        .trim()
+        .to_string())
+}
+
+/// Fetch the latest `.deb` of a sideloaded package with its command from `deb_sources`, and install it if
+/// it is newer than the installed version.
+///
+/// The command prints the path of the `.deb` it fetched on the last line of its output.
+pub fn run_deb_source(ctx: &ExecutionContext, package: &str, command: &str) -> Result<()> {
+    let apt = require("apt-get")?;
+    let dpkg = require("dpkg")?;
+
+    print_separator(format!("deb source ({package})"));
+    if ctx.run_type().dry() {
+        println!("Would fetch the latest {package} .deb with `{command}`");
+        return Ok(());
+    }
+
+    let output = Command::new(shell())
+        .arg("-c")
+        .arg(command)
+        .output_checked_utf8()
+        .wrap_err_with(|| format!("Failed to fetch the latest {package} .deb"))?;
+    let deb = output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| eyre!("`{command}` printed no path of a .deb"))?;
+    let deb = fs::canonicalize(&deb).wrap_err_with(|| format!("`{command}` printed {}", deb.display()))?;
+
+    let deb_package = deb_field(&deb, "Package")?;
+    if deb_package != package {
+        return Err(eyre!("{} is a .deb of {deb_package}, not {package}", deb.display()));
+    }
+    let new = deb_field(&deb, "Version")?;
+    // `dpkg-query` fails when the package isn't installed yet
+    let installed = Command::new("dpkg-query")
+        .args(["--show", "--showformat=${Version}", package])
+        .output_checked_utf8()
+        .map(|output| output.stdout.trim().to_string())
+        .ok()
+        .filter(|version| !version.is_empty());
+    if let Some(installed) = &installed {
+        let newer = Command::new(&dpkg)
+            .args(["--compare-versions", &new, "gt", installed])
+            .output_checked()
+            .is_ok();
+        if !newer {
+            println!("{package} {installed} is up to date");
+            return Ok(());
+        }
+    }
+
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let mut install = ctx.run_type().execute(sudo);
+    // A path tells apt to install the file rather than the package from the repositories
+    install.arg(&apt).arg("install").arg(&deb);
+    if ctx.config().yes(Step::DebSources) {
+        install.arg("-y");
+    }
+    install.status_checked()?;
+
+    add_summary_note(format!(
+        "Updated {package} ({} -> {new})",
+        installed.as_deref().unwrap_or("not installed")
+    ));
+
+    Ok(())
+}
+
+/// The packages `eopkg list-upgrades` reports, it doesn't need root.
+fn eopkg_upgradable(eopkg: &Path) -> Vec<String> {
+    let Ok(output) = Command::new(eopkg).arg("list-upgrades").output_checked_utf8() else {
+        return Vec::new();
+    };
+
+    // <name>    - <summary>
+    output
+        .stdout
+        .lines()
+        .filter(|line| line.contains(" - "))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
 fn upgrade_solus(ctx: &ExecutionContext) -> Result<()> {
+    let eopkg = require("eopkg")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    let upgradable = eopkg_upgradable(&eopkg);
+    if ctx.run_type().dry() {
+        if upgradable.is_empty() {
+            println!("The packages are up to date");
+        } else {
+            println!("Upgradable packages: {}", upgradable.join(", "));
+        }
+        return Ok(());
+    }
+
     let mut cmd = ctx.run_type().execute(sudo);
-    cmd.arg("eopkg");
+    cmd.arg(&eopkg);
     if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+        cmd.arg("--yes-all");
     }
     cmd.arg("upgrade").status_checked()?;
 
+    if !upgradable.is_empty() {
+        println!("Upgraded packages: {}", upgradable.join(", "));
+    }
     Ok(())
 }
 
@@ -715,17 +1445,61 @@ fn upgrade_exherbo(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The number of the current NixOS system generation, from its `system-<n>-link` profile.
+fn nixos_generation() -> Option<u32> {
+    let link = std::fs::read_link("/nix/var/nix/profiles/system").ok()?;
+    link.file_name()?
+        .to_str()?
+        .strip_prefix("system-")?
+        .strip_suffix("-link")?
+        .parse()
+        .ok()
+}
+
 fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let generation_before = nixos_generation();
+
     let mut command = ctx.run_type().execute(sudo);
     command.args(["/run/current-system/sw/bin/nixos-rebuild", "switch", "--upgrade"]);
 
     if let Some(args) = ctx.config().nix_arguments() {
         command.args(args.split_whitespace());
     }
-    command.status_checked()?;
+    if let Err(e) = command.status_checked() {
+        // A generation is only worth rolling back from if the failed switch created it
+        let generation_after = nixos_generation();
+        if let (Some(before), Some(after)) = (generation_before, generation_after) {
+            if after > before {
+                print_warning(format!(
+                    "nixos-rebuild switch failed on generation {after}, roll back to generation {before} with `sudo nixos-rebuild switch --rollback`"
+                ));
+                if ctx.config().nix_rollback_on_failure() && ctx.config().yes(Step::System) {
+                    ctx.run_type()
+                        .execute(sudo)
+                        .args(["/run/current-system/sw/bin/nixos-rebuild", "switch", "--rollback"])
+                        .status_checked()?;
+                    add_summary_note(format!(
+                        "NixOS rolled back to generation {before} after the switch to generation {after} failed"
+                    ));
+                }
+            }
+        }
+        return Err(e);
+    }
 
-    if ctx.config().cleanup() {
+    if let (Some(before), Some(after)) = (generation_before, nixos_generation()) {
+        if before != after {
+            add_summary_note(format!("NixOS generation {before} -> {after}"));
+        }
+    }
+
+    if ctx.config().cleanup()
+        && ctx.confirm_destructive(
+            Step::System,
+            "all the NixOS generations but the current one, and the Nix store paths they use",
+        )?
+    {
         ctx.run_type()
             .execute(sudo)
             .args(["/run/current-system/sw/bin/nix-collect-garbage", "-d"])
@@ -735,87 +1509,435 @@ fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
-    // KDE neon is ubuntu based but uses it's own manager, pkcon
-    // running apt update with KDE neon is an error
-    // in theory rpm based distributions use pkcon as well, though that
-    // seems rare
-    // if that comes up we need to create a Distribution::PackageKit or some such
+fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
+    // KDE neon is ubuntu based but uses it's own manager, pkcon
+    // running apt update with KDE neon is an error
+    // in theory rpm based distributions use pkcon as well, though that
+    // seems rare
+    // if that comes up we need to create a Distribution::PackageKit or some such
+
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let pkcon = which("pkcon").unwrap();
+    // pkcon ignores update with update and refresh provided together
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&pkcon)
+        .arg("refresh")
+        .status_checked()?;
+    let mut exe = ctx.run_type().execute(sudo);
+    let cmd = exe.arg(&pkcon).arg("update");
+    if ctx.config().yes(Step::System) {
+        cmd.arg("-y");
+    }
+    if ctx.config().cleanup() {
+        cmd.arg("--autoremove");
+    }
+    // from pkcon man, exit code 5 is 'Nothing useful was done.'
+    cmd.status_checked_with_codes(&[5])?;
+
+    Ok(())
+}
+
+/// `needrestart` should be skipped if:
+///
+/// 1. This is a redhat-based distribution
+/// 2. This is a debian-based distribution and it is using `nala` as the `apt`
+///    alternative
+fn should_skip_needrestart() -> Result<()> {
+    let distribution = Distribution::detect()?;
+    let msg = "needrestart will be ran by the package manager";
+
+    if distribution.redhat_based() {
+        return Err(SkipStep(String::from(msg)).into());
+    }
+
+    if matches!(distribution, Distribution::Debian) {
+        let apt = which("apt-fast")
+            .or_else(|| {
+                if which("mist").is_some() {
+                    Some(PathBuf::from("mist"))
+                } else {
+                    None
+                }
+            })
+            .or_else(|| {
+                if Path::new("/usr/bin/nala").exists() {
+                    Some(Path::new("/usr/bin/nala").to_path_buf())
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_else(|| PathBuf::from("apt-get"));
+
+        let is_nala = apt.ends_with("nala");
+
+        if is_nala {
+            return Err(SkipStep(String::from(msg)).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Upgrade Ubuntu to its next release, only when enabled and explicitly confirmed.
+pub fn run_ubuntu_release_upgrade(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().ubuntu_release_upgrade() {
+        return Err(SkipStep(String::from("`ubuntu_release_upgrade` is not enabled")).into());
+    }
+    let do_release_upgrade = require("do-release-upgrade")?;
+    let confirmed = ctx.config().confirm_release_upgrade();
+    // A release upgrade can cut the connection or need someone at the machine
+    if (ctx.under_ssh() || ctx.config().remote_guest()) && !confirmed {
+        return Err(SkipStep(String::from(
+            "Release upgrades are not run remotely without `--confirm-release-upgrade`",
+        ))
+        .into());
+    }
+
+    // `-c` exits with 1 when there is no new release, it only checks
+    let available = Command::new(&do_release_upgrade)
+        .arg("-c")
+        .output_checked_with_utf8(|output| {
+            if matches!(output.status.code(), Some(0 | 1)) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })?;
+    if !available.status.success() {
+        return Err(SkipStep(String::from("No new Ubuntu release available")).into());
+    }
+
+    print_separator("Ubuntu release upgrade");
+    print!("{}{}", available.stdout, available.stderr);
+    if ctx.run_type().dry() {
+        return ctx.run_type().execute(&do_release_upgrade).status_checked();
+    }
+
+    print_warning(
+        "Upgrading to a new release is a major operation which replaces most of the system, \
+         make sure you have a backup",
+    );
+    // `--yes` doesn't confirm this one
+    if !confirmed && !prompt_yesno("Upgrade to the new Ubuntu release now?")? {
+        return Err(SkipStep(String::from("Release upgrade declined")).into());
+    }
+
+    let mut command = if nix::unistd::Uid::effective().is_root() {
+        ctx.run_type().execute(&do_release_upgrade)
+    } else {
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(&do_release_upgrade);
+        command
+    };
+    command.status_checked()?;
+    add_summary_note("Reboot to finish the upgrade to the new Ubuntu release");
+
+    Ok(())
+}
+
+pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let needrestart = require("needrestart")?;
+
+    if !ctx.config().needrestart() {
+        return Err(SkipStep(String::from("needrestart is disabled in the configuration")).into());
+    }
+
+    should_skip_needrestart()?;
+
+    print_separator("Check for needed restarts");
+
+    // Restart the outdated services automatically only when the user agreed to it
+    let mode = if ctx.config().yes(Step::Restarts) { "a" } else { "l" };
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&needrestart)
+        .args(["-r", mode])
+        .status_checked()?;
+
+    if needrestart_kernel_outdated(sudo, &needrestart) {
+        print_warning("The running kernel is outdated, a reboot is required");
+        add_summary_note("Reboot required: needrestart reports that the running kernel is outdated");
+    }
+
+    Ok(())
+}
+
+/// The services restarting would end the sessions, they are only listed.
+const SESSION_SERVICES: &[&str] = &[
+    "dbus.service",
+    "dbus-broker.service",
+    "systemd-logind.service",
+    "display-manager.service",
+    "gdm.service",
+    "sddm.service",
+    "lightdm.service",
+];
+
+/// The services `dnf needs-restarting` lists, and whether it requires a reboot.
+fn dnf_needs_restarting(sudo: &Sudo, dnf: &Path) -> Result<(BTreeSet<String>, bool)> {
+    let services = Command::new(sudo)
+        .arg(dnf)
+        .args(["needs-restarting", "--services"])
+        .output_checked_utf8()?
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|service| !service.is_empty())
+        .map(|service| {
+            if service.contains('.') {
+                service.to_string()
+            } else {
+                format!("{service}.service")
+            }
+        })
+        .collect();
+    // `-r` exits with 1 when a reboot is required
+    let reboot = Command::new(sudo)
+        .arg(dnf)
+        .args(["needs-restarting", "-r"])
+        .output_checked_with(|output| match output.status.code() {
+            Some(0 | 1) => Ok(()),
+            _ => Err(()),
+        })
+        .is_ok_and(|output| output.status.code() == Some(1));
+    Ok((services, reboot))
+}
+
+/// The services of the processes still mapping a deleted library, replaced by an upgrade.
+fn services_using_deleted_libraries(sudo: &Sudo) -> Result<BTreeSet<String>> {
+    // The maps of the other users' processes are only readable by root
+    let output = Command::new(sudo)
+        .args(["sh", "-c", r"grep -lsE '\.so[^ ]* \(deleted\)$' /proc/[0-9]*/maps"])
+        // 1 when nothing maps a deleted library, 2 when a process exited while it was read
+        .output_checked_with_utf8(|output| match output.status.code() {
+            Some(0..=2) => Ok(()),
+            _ => Err(()),
+        })?;
+
+    // 0::/system.slice/sshd.service
+    Ok(output
+        .stdout
+        .lines()
+        .filter_map(|maps| maps.strip_suffix("/maps"))
+        .filter_map(|process| fs::read_to_string(format!("{process}/cgroup")).ok())
+        .filter_map(|cgroup| {
+            cgroup
+                .lines()
+                .filter_map(|line| line.rsplit('/').next())
+                .find(|unit| unit.ends_with(".service") && !unit.starts_with("user@"))
+                .map(String::from)
+        })
+        .collect())
+}
+
+/// List the services running outdated code after the upgrades without needrestart, and restart
+/// them when `auto_restart_services` is set.
+pub fn run_restart_audit(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().report_restart_needed() {
+        return Err(SkipStep(String::from("`report_restart_needed` is not enabled")).into());
+    }
+    if ctx.config().needrestart() && which("needrestart").is_some() {
+        return Err(SkipStep(String::from("needrestart already checks the needed restarts")).into());
+    }
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    print_separator("Restart audit");
+
+    let (services, reboot) = match which("dnf") {
+        Some(dnf) => dnf_needs_restarting(sudo, &dnf)?,
+        None => (services_using_deleted_libraries(sudo)?, false),
+    };
+    if reboot {
+        print_warning("The upgraded core libraries or kernel require a reboot");
+        add_summary_note("Reboot required: dnf needs-restarting reports updated core packages");
+    }
+    if services.is_empty() {
+        println!("No service runs outdated code");
+        return Ok(());
+    }
+
+    let services: Vec<&str> = services.iter().map(String::as_str).collect();
+    println!("Services running outdated code: {}", services.join(", "));
+    let (session, restartable): (Vec<&str>, Vec<&str>) = services
+        .iter()
+        .copied()
+        .partition(|service| SESSION_SERVICES.contains(service) || service.starts_with("getty@"));
 
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let pkcon = which("pkcon").unwrap();
-    // pkcon ignores update with update and refresh provided together
-    ctx.run_type()
-        .execute(sudo)
-        .arg(&pkcon)
-        .arg("refresh")
-        .status_checked()?;
-    let mut exe = ctx.run_type().execute(sudo);
-    let cmd = exe.arg(&pkcon).arg("update");
-    if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+    if ctx.config().auto_restart_services() && ctx.config().yes(Step::Restarts) {
+        if !restartable.is_empty() {
+            ctx.run_type()
+                .execute(sudo)
+                .args(["systemctl", "restart"])
+                .args(&restartable)
+                .status_checked()?;
+        }
+    } else if !restartable.is_empty() {
+        add_summary_note(format!(
+            "Restart needed: {} run outdated code, restart them with `sudo systemctl restart {}`",
+            restartable.join(", "),
+            restartable.join(" ")
+        ));
     }
-    if ctx.config().cleanup() {
-        cmd.arg("--autoremove");
+    // Restarting them would end the session Topgrade runs in
+    if !session.is_empty() {
+        add_summary_note(format!(
+            "Restart needed: {} run outdated code, log out or reboot to restart them",
+            session.join(", ")
+        ));
     }
-    // from pkcon man, exit code 5 is 'Nothing useful was done.'
-    cmd.status_checked_with_codes(&[5])?;
 
     Ok(())
 }
 
-/// `needrestart` should be skipped if:
+/// Whether needrestart's batch mode reports a pending kernel upgrade.
 ///
-/// 1. This is a redhat-based distribution
-/// 2. This is a debian-based distribution and it is using `nala` as the `apt`
-///    alternative
-fn should_skip_needrestart() -> Result<()> {
-    let distribution = Distribution::detect()?;
-    let msg = "needrestart will be ran by the package manager";
+/// `NEEDRESTART-KSTA` is 1 when the running kernel is current, 2 for a pending ABI compatible
+/// upgrade and 3 for a pending version upgrade.
+fn needrestart_kernel_outdated(sudo: &Sudo, needrestart: &Path) -> bool {
+    let Ok(output) = Command::new(sudo).arg(needrestart).arg("-b").output_checked_utf8() else {
+        return false;
+    };
 
-    if distribution.redhat_based() {
-        return Err(SkipStep(String::from(msg)).into());
-    }
+    output
+        .stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("NEEDRESTART-KSTA:"))
+        .any(|status| status.trim().parse::<u8>().is_ok_and(|status| status > 1))
+}
 
-    if matches!(distribution, Distribution::Debian) {
-        let apt = which("apt-fast")
-            .or_else(|| {
-                if which("mist").is_some() {
-                    Some(PathBuf::from("mist"))
-                } else {
-                    None
-                }
-            })
-            .or_else(|| {
-                if Path::new("/usr/bin/nala").exists() {
-                    Some(Path::new("/usr/bin/nala").to_path_buf())
-                } else {
-                    None
+/// The numbers in a kernel release, compared to find the newest one.
+fn kernel_version_key(release: &str) -> Vec<u64> {
+    release
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+/// The newest kernel with modules installed, if any.
+fn newest_installed_kernel() -> Option<String> {
+    let modules = ["/usr/lib/modules", "/lib/modules"]
+        .into_iter()
+        .map(Path::new)
+        .find(|dir| dir.is_dir())?;
+    fs::read_dir(modules)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        // The modules of removed kernels can be left behind without their `modules.dep`
+        .filter(|entry| entry.path().join("modules.dep").exists())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .max_by_key(|release| kernel_version_key(release))
+}
+
+/// Update systemd-boot or regenerate the GRUB configuration, with `update_bootloader`. The old
+/// kernels aren't pruned, their boot entries go away with them when the package manager removes them.
+pub fn run_bootloader_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().update_bootloader() {
+        return Err(SkipStep(String::from("`update_bootloader` is not enabled")).into());
+    }
+    if is_wsl()? {
+        return Err(SkipStep(String::from("WSL has no bootloader to update")).into());
+    }
+
+    // systemd-boot only boots UEFI systems. `is-installed` fails when it is not the installed
+    // bootloader, and when the ESP can't be read, which usually needs root.
+    let systemd_boot = which("bootctl")
+        .filter(|_| Path::new("/sys/firmware/efi").exists())
+        .filter(|bootctl| {
+            let mut command = match ctx.sudo() {
+                Some(sudo) => {
+                    let mut command = RunType::Wet.execute(sudo);
+                    command.arg(bootctl);
+                    command
                 }
-            })
-            .unwrap_or_else(|| PathBuf::from("apt-get"));
+                None => RunType::Wet.execute(bootctl),
+            };
+            command.arg("is-installed").output_checked().is_ok()
+        });
+    let grub_mkconfig = which("grub-mkconfig").or_else(|| which("grub2-mkconfig"));
+    let grub_config = ["/boot/grub/grub.cfg", "/boot/grub2/grub.cfg"]
+        .into_iter()
+        .find(|config| Path::new(config).exists());
+    if systemd_boot.is_none() && (grub_mkconfig.is_none() || grub_config.is_none()) {
+        return Err(SkipStep(String::from("No systemd-boot or GRUB bootloader found")).into());
+    }
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
 
-        let is_nala = apt.ends_with("nala");
+    print_separator("Bootloader");
 
-        if is_nala {
-            return Err(SkipStep(String::from(msg)).into());
+    if let Some(bootctl) = systemd_boot {
+        // `--graceful` doesn't fail when the installed version is already the newest
+        ctx.run_type()
+            .execute(sudo)
+            .arg(bootctl)
+            .args(["update", "--graceful"])
+            .status_checked()
+            .context("Failed to update systemd-boot")?;
+        println!("systemd-boot is updated");
+    } else if let Some(update_grub) = which("update-grub") {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(update_grub)
+            .status_checked()
+            .context("Failed to regenerate the GRUB configuration")?;
+        println!("The GRUB configuration is regenerated");
+    } else if let (Some(grub_mkconfig), Some(grub_config)) = (grub_mkconfig, grub_config) {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(grub_mkconfig)
+            .args(["-o", grub_config])
+            .status_checked()
+            .context("Failed to regenerate the GRUB configuration")?;
+        println!("The GRUB configuration {grub_config} is regenerated");
+    }
+
+    let running = Command::new("uname")
+        .arg("-r")
+        .output_checked_utf8()
+        .ok()
+        .map(|output| output.stdout.trim().to_string());
+    if let (Some(running), Some(newest)) = (running, newest_installed_kernel()) {
+        if running != newest {
+            print_warning(format!(
+                "The running kernel {running} is not the newest installed one {newest}"
+            ));
+            add_summary_note(format!(
+                "Reboot recommended: the running kernel {running} is not the newest installed one {newest}"
+            ));
         }
     }
 
     Ok(())
 }
 
-pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
+pub fn run_etckeeper_commit(ctx: &ExecutionContext, message: &str) -> Result<()> {
+    if !ctx.config().etckeeper() {
+        return Err(SkipStep(String::from("etckeeper is not enabled in the configuration")).into());
+    }
+    let etckeeper = require("etckeeper")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let needrestart = require("needrestart")?;
-
-    should_skip_needrestart()?;
 
-    print_separator("Check for needed restarts");
-
-    ctx.run_type().execute(sudo).arg(needrestart).status_checked()?;
+    print_separator("etckeeper");
+
+    // Committing a clean /etc fails, `unclean` exits successfully only when there are changes
+    if !ctx.run_type().dry()
+        && Command::new(sudo)
+            .arg(&etckeeper)
+            .arg("unclean")
+            .output_checked()
+            .is_err()
+    {
+        println!("Nothing to commit in /etc");
+        return Ok(());
+    }
 
-    Ok(())
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&etckeeper)
+        .args(["commit", message])
+        .status_checked()
 }
 
 pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
@@ -848,23 +1970,43 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     let flatpak = require("flatpak")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let cleanup = ctx.config().cleanup();
+    // `remove_unused` also uninstalls the unused runtimes without `cleanup`
+    let cleanup = ctx.config().cleanup() || ctx.config().flatpak_remove_unused();
     let yes = ctx.config().yes(Step::Flatpak);
     let run_type = ctx.run_type();
+
+    if ctx.config().metadata_only() {
+        print_separator("Flatpak metadata");
+        run_type
+            .execute(&flatpak)
+            .args(["update", "--appstream", "--user"])
+            .status_checked()?;
+        return run_type
+            .execute(&flatpak)
+            .args(["remote-ls", "--updates"])
+            .status_checked();
+    }
+
+    if let Some(remotes) = ctx.config().flatpak_remotes() {
+        print_separator("Flatpak Remotes");
+        add_flatpak_remotes(ctx, &flatpak, sudo, remotes)?;
+    }
+
     print_separator("Flatpak User Packages");
 
+    let (limited_flatpak, limited_args) = trickle_wrapped(ctx, &flatpak);
     let mut update_args = vec!["update", "--user"];
     if yes {
         update_args.push("-y");
     }
-    run_type.execute(&flatpak).args(&update_args).status_checked()?;
+    run_type
+        .execute(&limited_flatpak)
+        .args(&limited_args)
+        .args(&update_args)
+        .status_checked()?;
 
     if cleanup {
-        let mut cleanup_args = vec!["uninstall", "--user", "--unused"];
-        if yes {
-            cleanup_args.push("-y");
-        }
-        run_type.execute(&flatpak).args(&cleanup_args).status_checked()?;
+        remove_unused_flatpaks(ctx, &flatpak, "--user", &HOME_DIR.join(".local/share/flatpak"), None)?;
     }
 
     print_separator("Flatpak System Packages");
@@ -875,38 +2017,350 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
         }
         run_type
             .execute(sudo)
-            .arg(&flatpak)
+            .arg(&limited_flatpak)
+            .args(&limited_args)
             .args(&update_args)
             .status_checked()?;
         if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
-            }
-            run_type
-                .execute(sudo)
-                .arg(flatpak)
-                .args(&cleanup_args)
-                .status_checked()?;
+            remove_unused_flatpaks(ctx, &flatpak, "--system", Path::new("/var/lib/flatpak"), Some(sudo))?;
         }
     } else {
         let mut update_args = vec!["update", "--system"];
         if yes {
             update_args.push("-y");
         }
-        run_type.execute(&flatpak).args(&update_args).status_checked()?;
+        run_type
+            .execute(&limited_flatpak)
+            .args(&limited_args)
+            .args(&update_args)
+            .status_checked()?;
         if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
+            remove_unused_flatpaks(ctx, &flatpak, "--system", Path::new("/var/lib/flatpak"), None)?;
+        }
+    }
+
+    if ctx.config().flatpak_report_permission_changes() {
+        print_separator("Flatpak Permissions");
+        report_flatpak_permission_changes(&flatpak, run_type.dry());
+    }
+
+    Ok(())
+}
+
+/// Compare the permissions of the installed apps to the ones saved by the previous run, and report
+/// the changes. The permissions aren't saved during a dry run.
+fn report_flatpak_permission_changes(flatpak: &Path, dry_run: bool) {
+    let dir = data_dir().join("topgrade_flatpak_permissions");
+    let apps = Command::new(flatpak)
+        .args(["list", "--app", "--columns=application,installation"])
+        .output_checked_utf8()
+        .map(|output| output.stdout)
+        .unwrap_or_default();
+
+    let mut changed = Vec::new();
+    for (app, installation) in apps.lines().filter_map(|line| line.split_once(char::is_whitespace)) {
+        let installation = installation.trim();
+        let scope = match installation {
+            "user" => String::from("--user"),
+            "system" => String::from("--system"),
+            other => format!("--installation={other}"),
+        };
+        let Ok(output) = Command::new(flatpak)
+            .args(["info", "--show-permissions", &scope, app])
+            .output_checked_utf8()
+        else {
+            continue;
+        };
+        let current = output.stdout;
+        let snapshot = dir.join(format!("{installation}-{app}"));
+
+        if let Ok(previous) = fs::read_to_string(&snapshot) {
+            let (removed, added) = permission_changes(&previous, &current);
+            if !removed.is_empty() || !added.is_empty() {
+                println!("{app} ({installation}):");
+                for line in removed {
+                    println!("  - {line}");
+                }
+                for line in added {
+                    println!("  + {line}");
+                }
+                changed.push(app);
+            }
+        }
+        if !dry_run {
+            fs::create_dir_all(&dir)
+                .and_then(|_| fs::write(&snapshot, &current))
+                .ok();
+        }
+    }
+
+    if changed.is_empty() {
+        println!("No Flatpak app permissions changed");
+    } else {
+        add_summary_note(format!(
+            "The permissions of these Flatpak apps changed: {}",
+            changed.join(", ")
+        ));
+    }
+}
+
+/// The lines of the `previous` permissions missing from the `current` ones, and the lines added to them.
+fn permission_changes<'a>(previous: &'a str, current: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let removed = previous
+        .lines()
+        .filter(|line| !current.lines().any(|l| l == *line))
+        .collect();
+    let added = current
+        .lines()
+        .filter(|line| !previous.lines().any(|l| l == *line))
+        .collect();
+    (removed, added)
+}
+
+/// Count this run, telling whether `flatpak repair` is due, or the number of runs until it is.
+fn flatpak_repair_due(interval: u32, dry_run: bool) -> Result<(), u32> {
+    let path = data_dir().join("flatpak_repair_runs");
+    let runs = fs::read_to_string(&path)
+        .ok()
+        .and_then(|runs| runs.trim().parse::<u32>().ok())
+        .unwrap_or(0)
+        + 1;
+    let due = runs >= interval;
+    if !dry_run {
+        let runs = if due { 0 } else { runs };
+        fs::create_dir_all(data_dir())
+            .and_then(|_| fs::write(&path, runs.to_string()))
+            .ok();
+    }
+
+    if due {
+        Ok(())
+    } else {
+        Err(interval - runs)
+    }
+}
+
+/// Run `flatpak repair` on the user and system installations, every `repair_interval` runs.
+pub fn run_flatpak_repair(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().flatpak_repair() {
+        return Err(SkipStep(String::from("`repair` is not enabled in the [flatpak] section")).into());
+    }
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(String::from(NO_METADATA_REFRESH)).into());
+    }
+    let flatpak = require("flatpak")?;
+    let interval = ctx.config().flatpak_repair_interval();
+    if let Err(remaining) = flatpak_repair_due(interval, ctx.run_type().dry()) {
+        return Err(SkipStep(format!(
+            "flatpak repair runs every {interval} runs, next in {remaining}"
+        ))
+        .into());
+    }
+
+    print_separator("Flatpak Repair");
+    let system_sudo = ctx.config().flatpak_use_sudo() || std::env::var("SSH_CLIENT").is_ok();
+    let sudo = if system_sudo {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    } else {
+        None
+    };
+
+    for (scope, sudo) in [("--user", None), ("--system", sudo)] {
+        // `--dry-run` only reports the problems, so it runs for real during a dry run
+        let mut command = match sudo {
+            Some(sudo) => {
+                let mut command = RunType::Wet.execute(sudo);
+                command.arg(&flatpak);
+                command
+            }
+            None => RunType::Wet.execute(&flatpak),
+        };
+        command.args(["repair", scope]);
+        if ctx.run_type().dry() {
+            command.arg("--dry-run");
+        }
+        let output = command.output_checked_utf8()?;
+        print!("{}", output.stdout);
+
+        // Deleting ref app/org.example.App/x86_64/stable due to missing objects
+        let fixed = output
+            .stdout
+            .lines()
+            .filter(|line| line.starts_with("Deleting ref"))
+            .count();
+        if fixed > 0 {
+            add_summary_note(format!("flatpak repair {scope}: {fixed} broken refs reinstalled"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the configured remotes missing from the system installation, never removing any.
+fn add_flatpak_remotes(
+    ctx: &ExecutionContext,
+    flatpak: &Path,
+    sudo: &Sudo,
+    remotes: &BTreeMap<String, String>,
+) -> Result<()> {
+    let existing: Vec<String> = Command::new(flatpak)
+        .args(["remotes", "--system", "--columns=name"])
+        .output_checked_utf8()
+        .map(|output| output.stdout.lines().map(|line| line.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    let mut added = Vec::new();
+    for (name, url) in remotes {
+        if existing.contains(name) {
+            continue;
+        }
+        let mut command = if ctx.config().flatpak_use_sudo() || std::env::var("SSH_CLIENT").is_ok() {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(flatpak);
+            command
+        } else {
+            ctx.run_type().execute(flatpak)
+        };
+        command
+            .args(["remote-add", "--system", "--if-not-exists", name, url])
+            .status_checked()
+            .with_context(|| format!("Failed to add the Flatpak remote {name}"))?;
+        added.push(name.as_str());
+    }
+
+    if added.is_empty() {
+        println!("All the configured remotes exist");
+    } else if !ctx.run_type().dry() {
+        println!("Added remotes: {}", added.join(", "));
+    }
+
+    Ok(())
+}
+
+/// The disk usage of `dir` in KiB, counting the hard links of the OSTree repository once.
+fn disk_usage_kib(dir: &Path) -> Option<u64> {
+    Command::new("du")
+        .arg("-sk")
+        .arg(dir)
+        .output_checked_utf8()
+        .ok()?
+        .stdout
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The runtimes of an installation that no application uses, for dry runs.
+///
+/// `flatpak uninstall --unused` has no dry run, so this approximates it: the extensions of the
+/// runtimes in use, such as the GL drivers, aren't counted as unused.
+fn unused_flatpak_runtimes(flatpak: &Path, installation: &str) -> Vec<String> {
+    let list = |kind: &str, column: &str| -> Vec<String> {
+        Command::new(flatpak)
+            .args(["list", installation, kind])
+            .arg(format!("--columns={column}"))
+            .output_checked_utf8()
+            .map(|output| output.stdout.lines().map(|line| line.trim().to_string()).collect())
+            .unwrap_or_default()
+    };
+    let used: Vec<String> = list("--app", "runtime")
+        .iter()
+        .filter_map(|runtime| runtime.split('/').next().map(str::to_string))
+        .collect();
+
+    list("--runtime", "application")
+        .into_iter()
+        .filter(|runtime| !runtime.ends_with(".Locale") && !runtime.ends_with(".Debug"))
+        .filter(|runtime| !used.iter().any(|used| runtime.starts_with(used.as_str())))
+        .collect()
+}
+
+/// Uninstall the unused runtimes of an installation, with `cleanup` or `remove_unused`.
+fn remove_unused_flatpaks(
+    ctx: &ExecutionContext,
+    flatpak: &Path,
+    installation: &str,
+    dir: &Path,
+    sudo: Option<&Sudo>,
+) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    let what = format!("the unused Flatpak runtimes ({})", installation.trim_start_matches('-'));
+
+    if ctx.run_type().dry() {
+        let unused = unused_flatpak_runtimes(flatpak, installation);
+        if unused.is_empty() {
+            println!("No unused Flatpak runtimes ({})", installation.trim_start_matches('-'));
+        } else {
+            println!("Would remove {what}, likely:");
+            for runtime in unused {
+                println!("  {runtime}");
             }
-            run_type.execute(flatpak).args(&cleanup_args).status_checked()?;
+        }
+        return Ok(());
+    }
+
+    if !ctx.confirm_destructive(Step::Flatpak, &what)? {
+        return Ok(());
+    }
+
+    let before = disk_usage_kib(dir);
+    let mut command = match sudo {
+        Some(sudo) => {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(flatpak);
+            command
+        }
+        None => ctx.run_type().execute(flatpak),
+    };
+    command.args(["uninstall", installation, "--unused"]);
+    if ctx.config().yes(Step::Flatpak) {
+        command.arg("-y");
+    }
+    command.status_checked()?;
+
+    if let (Some(before), Some(after)) = (before, disk_usage_kib(dir)) {
+        let reclaimed = before.saturating_sub(after);
+        if reclaimed > 0 {
+            println!("Reclaimed {:.1} MiB", reclaimed as f64 / 1024.0);
         }
     }
 
     Ok(())
 }
 
+/// The connected snap interfaces, as plug and slot, or `None` with a warning if they can't be
+/// listed. The refresh doesn't depend on them, so this doesn't fail the step.
+fn snap_connections(snap: &Path) -> Option<BTreeSet<(String, String)>> {
+    match Command::new(snap).args(["connections", "--all"]).output_checked_utf8() {
+        Ok(output) => Some(connected_interfaces(&output.stdout)),
+        Err(e) => {
+            print_warning(format!("Failed to list the snap connections, not checking them: {e}"));
+            None
+        }
+    }
+}
+
+/// The connected interfaces in the output of `snap connections`, as plug and slot.
+fn connected_interfaces(connections: &str) -> BTreeSet<(String, String)> {
+    // Interface  Plug          Slot    Notes
+    // home       firefox:home  :home   -
+    // camera     firefox:camera  -     -
+    connections
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            match (columns.get(1), columns.get(2)) {
+                (Some(plug), Some(slot)) if *plug != "-" && *slot != "-" => Some((plug.to_string(), slot.to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let snap = require("snap")?;
@@ -916,13 +2370,110 @@ pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
     }
     print_separator("snap");
 
-    ctx.run_type().execute(sudo).arg(snap).arg("refresh").status_checked()
+    if let Some(limit) = ctx.config().bandwidth_limit() {
+        // snapd downloads the refreshes itself, only its own setting limits them
+        debug!("snap refresh has no download limit, not limiting it to {limit} KB/s");
+        println!("`bandwidth_limit` doesn't apply to snap, use `sudo snap set system refresh.rate-limit={limit}KB`");
+    }
+
+    let connected_before = if ctx.config().snap_report_disconnected() && !ctx.run_type().dry() {
+        snap_connections(&snap)
+    } else {
+        None
+    };
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&snap)
+        .arg("refresh")
+        .status_checked()?;
+
+    let connected_after = connected_before.as_ref().and_then(|_| snap_connections(&snap));
+    if let (Some(connected_before), Some(connected_after)) = (connected_before, connected_after) {
+        let mut disconnected: Vec<&(String, String)> = connected_before.difference(&connected_after).collect();
+        if !disconnected.is_empty() && ctx.config().snap_reconnect() && ctx.config().yes(Step::Snap) {
+            let mut reconnected = Vec::new();
+            disconnected.retain(|(plug, slot)| {
+                match ctx
+                    .run_type()
+                    .execute(sudo)
+                    .arg(&snap)
+                    .args(["connect", plug, slot])
+                    .status_checked()
+                {
+                    Ok(()) => {
+                        reconnected.push(format!("{plug} to {slot}"));
+                        false
+                    }
+                    Err(e) => {
+                        print_warning(format!("Failed to reconnect {plug} to {slot}: {e}"));
+                        true
+                    }
+                }
+            });
+            if !reconnected.is_empty() {
+                add_summary_note(format!(
+                    "snap: reconnected the interfaces the refresh disconnected: {}",
+                    reconnected.join(", ")
+                ));
+            }
+        }
+        if !disconnected.is_empty() {
+            let commands: Vec<String> = disconnected
+                .iter()
+                .map(|(plug, slot)| format!("`sudo snap connect {plug} {slot}`"))
+                .collect();
+            add_summary_note(format!(
+                "snap: the refresh disconnected some interfaces, reconnect them with: {}",
+                commands.join(", ")
+            ));
+        }
+    }
+
+    if ctx.config().cleanup() && ctx.config().snap_remove_old_revisions() {
+        // Name  Version  Rev  Tracking  Publisher  Notes
+        // core  16-2.61  16928  latest/stable  canonical✓  core,disabled
+        let old_revisions: Vec<(String, String)> = Command::new(&snap)
+            .args(["list", "--all"])
+            .output_checked_utf8()?
+            .stdout
+            .lines()
+            .skip(1)
+            .filter(|line| line.contains("disabled"))
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split_whitespace().collect();
+                Some((columns.first()?.to_string(), columns.get(2)?.to_string()))
+            })
+            .collect();
+
+        let what = old_revisions
+            .iter()
+            .map(|(name, revision)| format!("{name} (revision {revision})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !old_revisions.is_empty()
+            && ctx.confirm_destructive(Step::Snap, &format!("the old snap revisions {what}"))?
+        {
+            for (name, revision) in &old_revisions {
+                ctx.run_type()
+                    .execute(sudo)
+                    .arg(&snap)
+                    .args(["remove", name, &format!("--revision={revision}")])
+                    .status_checked()?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 pub fn run_pihole_update(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let pihole = require("pihole")?;
     Path::new("/opt/pihole/update.sh").require()?;
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(NO_METADATA_REFRESH.to_string()).into());
+    }
 
     print_separator("pihole");
 
@@ -1086,6 +2637,45 @@ pub fn run_auto_cpufreq(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+/// Build the `systemd-run` command line used to apply the configured `resource_limits`.
+///
+/// Returns `None` if no limits are configured, or if `systemd-run` isn't available or can't reach
+/// the user service manager, in which case commands run unconstrained.
+pub fn resource_limits_wrapper(config: &Config) -> Option<Vec<OsString>> {
+    let properties = config.resource_limit_properties();
+    if properties.is_empty() {
+        return None;
+    }
+
+    let Some(systemd_run) = which("systemd-run") else {
+        print_warning("`resource_limits` are configured but `systemd-run` is not available, running without limits");
+        return None;
+    };
+
+    let mut wrapper: Vec<OsString> = vec![systemd_run.clone().into(), "--scope".into(), "--quiet".into()];
+    // Non-root users need their own service manager to create scopes, which plain SSH sessions
+    // often don't have
+    if !nix::unistd::Uid::effective().is_root() {
+        if Command::new(&systemd_run)
+            .args(["--user", "--scope", "--quiet", "true"])
+            .output_checked()
+            .is_err()
+        {
+            print_warning(
+                "`systemd-run --user` can't reach the user service manager, running without `resource_limits`",
+            );
+            return None;
+        }
+        wrapper.push("--user".into());
+    }
+    for property in properties {
+        wrapper.push("-p".into());
+        wrapper.push(property.into());
+    }
+
+    Some(wrapper)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1235,6 +2825,11 @@ mod tests {
         test_template(include_str!("os_release/vanilla"), Distribution::Vanilla);
     }
 
+    #[test]
+    fn test_slackware() {
+        test_template(include_str!("os_release/slackware"), Distribution::Slackware);
+    }
+
     #[test]
     fn test_solus() {
         test_template(include_str!("os_release/solus"), Distribution::Solus);
@@ -1244,4 +2839,104 @@ mod tests {
     fn test_nobara() {
         test_template(include_str!("os_release/nobara"), Distribution::Nobara);
     }
+
+    #[test]
+    fn test_kernel_version_key() {
+        assert!(kernel_version_key("6.10.2-arch1-1") > kernel_version_key("6.9.12-arch1-1"));
+        assert!(kernel_version_key("6.8.0-45-generic") > kernel_version_key("6.8.0-9-generic"));
+    }
+
+    #[test]
+    fn test_phased_updates() {
+        let simulation = "\
+Reading package lists...
+Calculating upgrade...
+The following upgrades have been deferred due to phasing:
+  gnome-shell gnome-shell-common
+  libmutter-12-0
+The following packages will be upgraded:
+  curl libcurl4
+2 upgraded, 0 newly installed, 0 to remove and 3 not upgraded.
+";
+        assert_eq!(phased_updates(simulation), 3);
+        assert_eq!(
+            phased_updates("0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n"),
+            0
+        );
+    }
+
+    #[test]
+    fn test_outdated_module_streams() {
+        let list = "\
+CentOS Stream 9 - AppStream
+Name      Stream    Profiles                                Summary
+nodejs    18 [e]    common [d], development, minimal, s2i   Javascript runtime
+nodejs    20 [d]    common [d], development, minimal, s2i   Javascript runtime
+php       8.1 [d][e] common [d], devel, minimal             PHP scripting language
+ruby      3.1       common [d]                              An interpreter of object-oriented scripting language
+ruby      3.3 [d]   common [d]                              An interpreter of object-oriented scripting language
+
+Hint: [d]efault, [e]nabled, [x]disabled, [i]nstalled
+";
+        assert_eq!(
+            outdated_module_streams(list),
+            [(String::from("nodejs"), String::from("18"), String::from("20"))]
+        );
+    }
+
+    #[test]
+    fn test_unread_news() {
+        let list = "\
+News items:
+  [1]      2021-01-30  Python 3.9 is the default
+  [2]   N  2024-01-01  Profile 23.0 migration
+  [3]   N  2024-05-09  Some news item
+";
+        assert_eq!(unread_news(list), ["Profile 23.0 migration", "Some news item"]);
+        assert!(unread_news("No news is good news.\n").is_empty());
+    }
+
+    #[test]
+    fn test_xbps_updates() {
+        let transaction = "\
+curl-8.9.1_1 update x86_64 https://repo-default.voidlinux.org/current 512000 256000
+libcurl-8.9.1_1 update x86_64 https://repo-default.voidlinux.org/current 1024000 512000
+libnghttp3-1.4.0_1 install x86_64 https://repo-default.voidlinux.org/current 204800 102400
+";
+        assert_eq!(xbps_updates(transaction), ["curl-8.9.1_1", "libcurl-8.9.1_1"]);
+    }
+
+    #[test]
+    fn test_permission_changes() {
+        let previous = "[Context]\nshared=network;ipc;\nfilesystems=xdg-download;\n";
+        let current = "[Context]\nshared=network;ipc;\nfilesystems=home;\ndevices=dri;\n";
+        assert_eq!(
+            permission_changes(previous, current),
+            (
+                vec!["filesystems=xdg-download;"],
+                vec!["filesystems=home;", "devices=dri;"]
+            )
+        );
+        assert_eq!(permission_changes(previous, previous), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_connected_interfaces() {
+        let connections = "\
+Interface        Plug                      Slot              Notes
+camera           firefox:camera            -                 -
+content[gnome]   firefox:gnome-42-2204     gnome-42-2204:gnome-42-2204  -
+home             firefox:home              :home             -
+";
+        assert_eq!(
+            connected_interfaces(connections),
+            BTreeSet::from([
+                (
+                    String::from("firefox:gnome-42-2204"),
+                    String::from("gnome-42-2204:gnome-42-2204")
+                ),
+                (String::from("firefox:home"), String::from(":home")),
+            ])
+        );
+    }
 }
@@ -1,11 +1,14 @@
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
+use crate::executor::RunType;
 use crate::terminal::{print_separator, prompt_yesno};
-use crate::utils::{require_option, REQUIRE_SUDO};
+use crate::utils::{require_option, which, PathExt, REQUIRE_SUDO};
 use crate::{utils::require, Step};
 use color_eyre::eyre::Result;
 use std::collections::HashSet;
 use std::fs;
+use std::path::Path;
 use std::process::Command;
 use tracing::debug;
 
@@ -18,30 +21,172 @@ pub fn run_macports(ctx: &ExecutionContext) -> Result<()> {
         .execute(sudo)
         .args(["port", "selfupdate"])
         .status_checked()?;
-    ctx.run_type()
-        .execute(sudo)
-        .args(["port", "-u", "upgrade", "outdated"])
-        .status_checked()?;
-    if ctx.config().cleanup() {
+
+    let outdated = macports_outdated()?;
+    if outdated.is_empty() {
+        println!("No outdated ports");
+    } else {
+        println!("Outdated ports: {}", outdated.join(", "));
+        ctx.run_type()
+            .execute(sudo)
+            .args(["port", "-u", "upgrade", "outdated"])
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            let still_outdated = macports_outdated()?;
+            let upgraded: Vec<&str> = outdated
+                .iter()
+                .filter(|port| !still_outdated.contains(port))
+                .map(String::as_str)
+                .collect();
+            println!("Upgraded ports: {}", upgraded.join(", "));
+        }
+    }
+
+    if ctx.config().macports_reclaim() {
         ctx.run_type()
             .execute(sudo)
             .args(["port", "-N", "reclaim"])
             .status_checked()?;
     }
 
+    super::unix::suggest_rehash(ctx);
+
+    Ok(())
+}
+
+pub fn run_nix_darwin(ctx: &ExecutionContext) -> Result<()> {
+    let darwin_rebuild = require("darwin-rebuild")?;
+    let flake = ctx
+        .config()
+        .nix_darwin_flake()
+        .ok_or_else(|| SkipStep(String::from("`nix_darwin_flake` isn't set")))?
+        .require()?;
+
+    print_separator("nix-darwin");
+
+    if ctx.run_type().dry() {
+        // `build` builds the configuration without activating it, and links it to `./result`,
+        // which is left in a temporary directory
+        let link_dir = tempfile::tempdir()?;
+        return RunType::Wet
+            .execute(darwin_rebuild)
+            .current_dir(link_dir.path())
+            .arg("build")
+            .arg("--flake")
+            .arg(fs::canonicalize(&flake)?)
+            .status_checked();
+    }
+
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let current_system = Path::new("/run/current-system");
+    let before = fs::read_link(current_system).ok();
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(darwin_rebuild)
+        .arg("switch")
+        .arg("--flake")
+        .arg(flake)
+        .status_checked()?;
+
+    let after = fs::read_link(current_system).ok();
+    match (before, after) {
+        (Some(before), Some(after)) if before == after => println!("The system configuration didn't change"),
+        (Some(before), Some(after)) => {
+            if let Some(nix) = which("nix") {
+                Command::new(nix)
+                    .args(["store", "diff-closures"])
+                    .arg(before)
+                    .arg(after)
+                    .status_checked()?;
+            }
+        }
+        _ => (),
+    }
+
     Ok(())
 }
 
+/// List the outdated ports.
+fn macports_outdated() -> Result<Vec<String>> {
+    // The following installed ports are outdated:
+    // bash                           5.2.26_0 < 5.2.32_0
+    let output = Command::new("port").arg("outdated").output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .filter(|line| line.contains(" < "))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect())
+}
+
+/// Whether `mas` is signed in to the App Store.
+///
+/// `mas account` isn't supported anymore on recent macOS versions, only its explicit
+/// "Not signed in" answer counts as signed out.
+fn mas_signed_in(mas: &Path) -> bool {
+    match Command::new(mas).arg("account").output_checked_with_utf8(|_| Ok(())) {
+        Ok(output) => !format!("{}{}", output.stdout, output.stderr).contains("Not signed in"),
+        Err(_) => true,
+    }
+}
+
+fn mas_outdated(mas: &Path) -> Result<Vec<String>> {
+    // 497799835 Xcode (15.0 -> 15.1)
+    let output = Command::new(mas).arg("outdated").output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(_, app)| app.trim().to_string())
+        .filter(|app| !app.is_empty())
+        .collect())
+}
+
 pub fn run_mas(ctx: &ExecutionContext) -> Result<()> {
     let mas = require("mas")?;
+    if !mas_signed_in(&mas) {
+        return Err(SkipStep(String::from("mas is not signed in to the App Store")).into());
+    }
+
     print_separator("macOS App Store");
 
-    ctx.run_type().execute(mas).arg("upgrade").status_checked()
+    let outdated = mas_outdated(&mas)?;
+    if outdated.is_empty() {
+        println!("The App Store apps are up to date");
+        return Ok(());
+    }
+    if ctx.run_type().dry() {
+        println!("Outdated App Store apps:");
+        for app in &outdated {
+            println!("  {app}");
+        }
+        return Ok(());
+    }
+
+    ctx.run_type().execute(&mas).arg("upgrade").status_checked()?;
+
+    let remaining = mas_outdated(&mas)?;
+    let updated: Vec<&String> = outdated.iter().filter(|app| !remaining.contains(app)).collect();
+    if !updated.is_empty() {
+        println!("Updated App Store apps:");
+        for app in updated {
+            println!("  {app}");
+        }
+    }
+
+    Ok(())
 }
 
 pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     print_separator("macOS system update");
 
+    if ctx.config().metadata_only() {
+        return ctx.run_type().execute("softwareupdate").arg("--list").status_checked();
+    }
+
     let should_ask = !(ctx.config().yes(Step::System) || ctx.config().dry_run());
     if should_ask {
         println!("Finding available software");
@@ -1,10 +1,16 @@
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
-use crate::terminal::print_separator;
-use crate::utils::{require_option, REQUIRE_SUDO};
+use crate::executor::RunType;
+use crate::terminal::{add_summary_note, print_separator};
+use crate::utils::{require_option, NO_METADATA_REFRESH, REQUIRE_SUDO};
 use color_eyre::eyre::Result;
 
 pub fn upgrade_openbsd(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(NO_METADATA_REFRESH.to_string()).into());
+    }
+
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     print_separator("OpenBSD Update");
     ctx.run_type()
@@ -24,6 +30,14 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
             .status_checked()?;
     }
 
+    if ctx.run_type().dry() {
+        // `-n` goes through the update without changing anything
+        return RunType::Wet
+            .execute(sudo)
+            .args(["/usr/sbin/pkg_add", "-un"])
+            .status_checked();
+    }
+
     ctx.run_type()
         .execute(sudo)
         .args(["/usr/sbin/pkg_add", "-u"])
@@ -31,3 +45,58 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
 
     Ok(())
 }
+
+pub fn run_fw_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().openbsd_fw_update() {
+        return Err(SkipStep(String::from("`openbsd_fw_update` is disabled")).into());
+    }
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    print_separator("OpenBSD Firmware");
+
+    if ctx.run_type().dry() {
+        return RunType::Wet
+            .execute(sudo)
+            .args(["/usr/sbin/fw_update", "-n"])
+            .status_checked();
+    }
+
+    ctx.run_type().execute(sudo).arg("/usr/sbin/fw_update").status_checked()
+}
+
+pub fn run_syspatch(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().openbsd_syspatch() {
+        return Err(SkipStep(String::from("`openbsd_syspatch` is not enabled")).into());
+    }
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    print_separator("OpenBSD Patches");
+
+    // `-c` lists the available patches without installing them
+    let available: Vec<String> = RunType::Wet
+        .execute(sudo)
+        .args(["/usr/sbin/syspatch", "-c"])
+        .output_checked_utf8()?
+        .stdout
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if available.is_empty() {
+        println!("No patches available");
+        return Ok(());
+    }
+    if ctx.run_type().dry() {
+        println!("Available patches: {}", available.join(", "));
+        return Ok(());
+    }
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg("/usr/sbin/syspatch")
+        .status_checked()?;
+
+    println!("Installed patches: {}", available.join(", "));
+    add_summary_note(format!(
+        "Reboot may be required: syspatch installed {}",
+        available.join(", ")
+    ));
+    Ok(())
+}
@@ -1,18 +1,22 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
 use std::path::Component;
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::Instant;
 use std::{env::var, path::Path};
 
 use crate::command::CommandExt;
+use crate::config::TerraformFlavor;
 use crate::{Step, HOME_DIR};
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use home;
 use ini::Ini;
+use semver::Version;
 use tracing::debug;
 
 #[cfg(target_os = "linux")]
@@ -21,10 +25,10 @@ use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use crate::executor::Executor;
-#[cfg(any(target_os = "linux", target_os = "macos"))]
 use crate::executor::RunType;
+use crate::terminal::add_summary_note;
 use crate::terminal::print_separator;
-use crate::utils::{require, require_option, PathExt, REQUIRE_SUDO};
+use crate::utils::{installing_package_manager, require, require_option, which, PathExt, REQUIRE_SUDO};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 const INTEL_BREW: &str = "/usr/local/bin/brew";
@@ -32,6 +36,10 @@ const INTEL_BREW: &str = "/usr/local/bin/brew";
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 const ARM_BREW: &str = "/opt/homebrew/bin/brew";
 
+/// The default prefix of Homebrew on Linux, which isn't always added to PATH.
+#[cfg(target_os = "linux")]
+const LINUX_BREW: &str = "/home/linuxbrew/.linuxbrew/bin/brew";
+
 #[derive(Copy, Clone, Debug)]
 #[allow(dead_code)]
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -51,6 +59,18 @@ impl BrewVariant {
         }
     }
 
+    /// The brew binary, Homebrew on Linux is also looked for in its default prefixes.
+    fn binary_path(self) -> Option<PathBuf> {
+        let binary = which(self.binary_name());
+        #[cfg(target_os = "linux")]
+        let binary = binary.or_else(|| {
+            [PathBuf::from(LINUX_BREW), HOME_DIR.join(".linuxbrew/bin/brew")]
+                .into_iter()
+                .find(|brew| brew.exists())
+        });
+        binary
+    }
+
     #[cfg(target_os = "macos")]
     fn is_path(&self) -> bool {
         matches!(self, BrewVariant::Path)
@@ -65,6 +85,7 @@ impl BrewVariant {
         match self {
             BrewVariant::MacArm if both_exists => "Brew (ARM)",
             BrewVariant::MacIntel if both_exists => "Brew (Intel)",
+            _ if cfg!(target_os = "linux") => "Brew (Linux)",
             _ => "Brew",
         }
     }
@@ -81,7 +102,7 @@ impl BrewVariant {
                 command.arg("-arm64e").arg(self.binary_name());
                 command
             }
-            _ => run_type.execute(self.binary_name()),
+            _ => run_type.execute(self.binary_path().unwrap_or_else(|| PathBuf::from(self.binary_name()))),
         }
     }
 
@@ -175,25 +196,66 @@ pub fn run_oh_my_fish(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(fish).args(["-c", "omf update"]).status_checked()
 }
 
+/// The installed pkgsrc packages, as `name-version`.
+fn pkgin_installed(pkgin: &Path) -> BTreeSet<String> {
+    Command::new(pkgin)
+        .arg("list")
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn run_pkgin(ctx: &ExecutionContext) -> Result<()> {
     let pkgin = require("pkgin")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    // Unprivileged pkgsrc installs, and root, don't need sudo
+    let uid = nix::unistd::Uid::effective();
+    let sudo = if uid.is_root() || fs::metadata(&pkgin)?.uid() == uid.as_raw() {
+        None
+    } else {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    };
+    let pkgin_command = |run_type: RunType| match sudo {
+        Some(sudo) => {
+            let mut command = run_type.execute(sudo);
+            command.arg(&pkgin);
+            command
+        }
+        None => run_type.execute(&pkgin),
+    };
 
     print_separator("Pkgin");
 
-    let mut command = ctx.run_type().execute(sudo);
-    command.arg(&pkgin).arg("update");
+    // The options come before the command
+    let mut command = pkgin_command(ctx.run_type());
     if ctx.config().yes(Step::Pkgin) {
         command.arg("-y");
     }
-    command.status_checked()?;
+    command.arg("update").status_checked()?;
 
-    let mut command = ctx.run_type().execute(sudo);
-    command.arg(&pkgin).arg("upgrade");
+    if ctx.run_type().dry() {
+        // `-n` answers no and prints the actions that would be taken
+        return pkgin_command(RunType::Wet).args(["-n", "upgrade"]).status_checked();
+    }
+
+    let before = pkgin_installed(&pkgin);
+    let mut command = pkgin_command(ctx.run_type());
     if ctx.config().yes(Step::Pkgin) {
         command.arg("-y");
     }
-    command.status_checked()
+    command.arg("upgrade").status_checked()?;
+
+    let upgraded: Vec<_> = pkgin_installed(&pkgin).difference(&before).cloned().collect();
+    if !upgraded.is_empty() {
+        println!("Upgraded packages: {}", upgraded.join(", "));
+    }
+    Ok(())
 }
 
 pub fn run_fish_plug(ctx: &ExecutionContext) -> Result<()> {
@@ -269,10 +331,31 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+/// Set the download concurrency chosen with `parallel_downloads` and the mirrors chosen with
+/// `bottle_mirror` and `api_domain` for this command only.
+fn brew_download_env(ctx: &ExecutionContext, command: &mut Executor) {
+    if let Some(downloads) = ctx.config().brew_parallel_downloads() {
+        debug!("HOMEBREW_DOWNLOAD_CONCURRENCY={downloads}");
+        command.env("HOMEBREW_DOWNLOAD_CONCURRENCY", downloads.to_string());
+    }
+    if let Some(mirror) = ctx.config().brew_bottle_mirror() {
+        debug!("Brew downloads the bottles from {mirror}");
+        command.env("HOMEBREW_BOTTLE_DOMAIN", mirror);
+    }
+    if let Some(domain) = ctx.config().brew_api_domain() {
+        debug!("Brew uses the API at {domain}");
+        command.env("HOMEBREW_API_DOMAIN", domain);
+    }
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
     #[allow(unused_variables)]
-    let binary_name = require(variant.binary_name())?;
+    let binary_name = require_option(
+        variant.binary_path(),
+        format!("{} is not installed", variant.binary_name()),
+    )?;
 
     #[cfg(target_os = "macos")]
     {
@@ -281,13 +364,45 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
         }
     }
 
+    #[cfg(target_os = "linux")]
+    {
+        if nix::unistd::Uid::effective().is_root() {
+            return Err(SkipStep("Homebrew refuses to run as root on Linux".to_string()).into());
+        }
+    }
+
     print_separator(variant.step_title());
     let run_type = ctx.run_type();
 
-    variant.execute(run_type).arg("update").status_checked()?;
+    let mut command = variant.execute(run_type);
+    brew_download_env(ctx, &mut command);
+    command.arg("update").status_checked()?;
+
+    if ctx.config().metadata_only() {
+        return variant
+            .execute(run_type)
+            .args(["outdated", "--formula"])
+            .status_checked();
+    }
+
+    if run_type.dry() {
+        // Show what would be upgraded
+        variant
+            .execute(RunType::Wet)
+            .args(["outdated", "--formula"])
+            .status_checked()?;
+    }
+
+    let restart_services = ctx.config().brew_restart_services();
+    let outdated = if restart_services {
+        brew_outdated_formulae(variant)?
+    } else {
+        BTreeSet::new()
+    };
 
     let mut command = variant.execute(run_type);
     command.args(["upgrade", "--formula"]);
+    brew_download_env(ctx, &mut command);
 
     if ctx.config().brew_fetch_head() {
         command.arg("--fetch-HEAD");
@@ -295,12 +410,401 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
 
     command.status_checked()?;
 
-    if ctx.config().cleanup() {
+    if restart_services {
+        let upgraded = if run_type.dry() {
+            outdated
+        } else {
+            // The formulae that failed to upgrade are still outdated
+            let still_outdated = brew_outdated_formulae(variant)?;
+            outdated.difference(&still_outdated).cloned().collect()
+        };
+        brew_restart_services(ctx, variant, &upgraded)?;
+    }
+
+    if ctx.config().cleanup()
+        && ctx.confirm_destructive(
+            Step::BrewFormula,
+            "the outdated versions of the installed formulae and the old downloads in the brew cache",
+        )?
+    {
         variant.execute(run_type).arg("cleanup").status_checked()?;
     }
 
     if ctx.config().brew_autoremove() {
-        variant.execute(run_type).arg("autoremove").status_checked()?;
+        brew_autoremove(ctx, variant)?;
+    }
+
+    if ctx.config().brew_doctor() {
+        brew_doctor(variant)?;
+    }
+
+    if ctx.config().brew_check_missing() {
+        brew_missing(variant)?;
+    }
+
+    if ctx.config().brew_bundle_cleanup() {
+        brew_bundle_cleanup(ctx, variant)?;
+    }
+
+    if ctx.config().brew_report_migrations() {
+        brew_report_migrations(variant);
+    }
+
+    suggest_rehash(ctx);
+
+    Ok(())
+}
+
+/// Prints `formula cask` for the installed formulae which moved to homebrew/cask, or are deprecated
+/// or disabled in favor of a cask. The replacement methods only exist in recent Homebrew versions.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const BREW_CASK_MIGRATIONS: &str = r##"migrations = CoreTap.instance.tap_migrations
+Formula.installed.each do |f|
+  target = migrations[f.name].to_s
+  cask = target.start_with?("homebrew/cask") ? (target.split("/")[2] || f.name) : nil
+  cask ||= f.deprecation_replacement_cask if f.respond_to?(:deprecation_replacement_cask)
+  cask ||= f.disable_replacement_cask if f.respond_to?(:disable_replacement_cask)
+  puts "#{f.name} #{cask}" if cask
+end"##;
+
+/// Add the installed formulae which are now casks to the summary. It is only informational, a
+/// failure is only printed.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_report_migrations(variant: BrewVariant) {
+    let output = match variant
+        .execute(RunType::Wet)
+        .args(["ruby", "-e", BREW_CASK_MIGRATIONS])
+        .output_checked_utf8()
+    {
+        Ok(output) => output.stdout,
+        Err(e) => {
+            crate::terminal::print_warning(format!("Failed to look for the formulae moved to a cask: {e}"));
+            return;
+        }
+    };
+
+    let migrations: Vec<String> = output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(formula, cask)| format!("{formula} (`brew uninstall {formula} && brew install --cask {cask}`)"))
+        .collect();
+    if !migrations.is_empty() {
+        add_summary_note(format!(
+            "{}: formulae which are now casks, switch to the cask with: {}",
+            variant.step_title(),
+            migrations.join(", ")
+        ));
+    }
+}
+
+/// The size of a Homebrew disk usage like `3.4MB`, in bytes.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_size_bytes(size: &str) -> Option<f64> {
+    let (number, unit) = size.split_at(size.find(|c: char| c.is_ascii_alphabetic())?);
+    let multiplier = match unit {
+        "B" => 1.0,
+        "KB" => 1024.0,
+        "MB" => 1024.0 * 1024.0,
+        "GB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    number.parse::<f64>().ok().map(|number| number * multiplier)
+}
+
+/// Uninstall the formulae which were only installed as dependencies and aren't needed anymore,
+/// and report them with the space reclaimed.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_autoremove(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    // ==> Would autoremove 2 unneeded formulae:
+    // libfoo
+    // libbar
+    let preview = variant
+        .execute(RunType::Wet)
+        .args(["autoremove", "--dry-run"])
+        .output_checked_utf8()?
+        .stdout;
+    let unneeded: Vec<&str> = preview
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("==>"))
+        .collect();
+    if unneeded.is_empty() {
+        println!("There are no unneeded formulae to autoremove");
+        return Ok(());
+    }
+
+    let what = format!("the unneeded formulae {}", unneeded.join(", "));
+    if ctx.run_type().dry() {
+        println!("Would autoremove {}", unneeded.join(", "));
+        return Ok(());
+    }
+    if !ctx.confirm_destructive(Step::BrewFormula, &what)? {
+        return Ok(());
+    }
+
+    // Uninstalling /opt/homebrew/Cellar/libfoo/1.0... (12 files, 3.4MB)
+    let output = variant
+        .execute(ctx.run_type())
+        .arg("autoremove")
+        .output_checked_utf8()?
+        .stdout;
+    print!("{output}");
+    let reclaimed: f64 = output
+        .lines()
+        .filter(|line| line.starts_with("Uninstalling"))
+        .filter_map(|line| line.trim_end().strip_suffix(')')?.rsplit_once(", "))
+        .filter_map(|(_, size)| brew_size_bytes(size))
+        .sum();
+    add_summary_note(format!(
+        "{}: autoremoved {}, reclaiming {:.1}MB",
+        variant.step_title(),
+        unneeded.join(", "),
+        reclaimed / (1024.0 * 1024.0)
+    ));
+
+    Ok(())
+}
+
+/// Install the global Brewfile, then uninstall what is not in it anymore.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_bundle_cleanup(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    if var("HOMEBREW_BUNDLE_FILE_GLOBAL").is_err() && !HOME_DIR.join(".Brewfile").exists() {
+        println!("There is no global Brewfile, not cleaning up with brew bundle");
+        return Ok(());
+    }
+    let run_type = ctx.run_type();
+
+    let mut command = variant.execute(run_type);
+    brew_download_env(ctx, &mut command);
+    command
+        .args(["bundle", "install", "--global", "--no-upgrade"])
+        .status_checked()?;
+
+    // Without `--force` it only lists what would be uninstalled, and exits with 1 when there is any
+    let preview = variant
+        .execute(RunType::Wet)
+        .args(["bundle", "cleanup", "--global"])
+        .output_checked_with_utf8(|output| {
+            if matches!(output.status.code(), Some(0 | 1)) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })?;
+    // The section headers and the hint at the end have spaces, the names don't
+    let removed: Vec<&str> = preview
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.contains(char::is_whitespace))
+        .collect();
+    if removed.is_empty() {
+        println!("Everything installed is in the Brewfile");
+        return Ok(());
+    }
+
+    let what = format!("{} which are not in the Brewfile", removed.join(", "));
+    if run_type.dry() {
+        println!("Would uninstall {what}");
+        return Ok(());
+    }
+    if !ctx.confirm_destructive(Step::BrewFormula, &what)? {
+        return Ok(());
+    }
+
+    variant
+        .execute(run_type)
+        .args(["bundle", "cleanup", "--global", "--force"])
+        .status_checked()?;
+    add_summary_note(format!("Uninstalled {what}"));
+
+    Ok(())
+}
+
+/// Remind once in the summary to rehash the shells already open, with `suggest_rehash`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn suggest_rehash(ctx: &ExecutionContext) {
+    static SUGGESTED: std::sync::Once = std::sync::Once::new();
+    if !ctx.config().suggest_rehash() || ctx.run_type().dry() {
+        return;
+    }
+
+    SUGGESTED.call_once(|| {
+        let shell = var("SHELL").unwrap_or_default();
+        let hint = match Path::new(&shell).file_name().and_then(OsStr::to_str) {
+            Some("zsh") => "run `rehash` in the zsh shells already open",
+            Some("bash") => "run `hash -r` in the bash shells already open",
+            Some("fish") => "run `fish_update_completions` to complete the new commands",
+            _ => "open a new shell",
+        };
+        add_summary_note(format!("New commands may be installed, {hint}"));
+    });
+}
+
+/// The outdated formulae, by their name without the tap.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_outdated_formulae(variant: BrewVariant) -> Result<BTreeSet<String>> {
+    let output = variant
+        .execute(RunType::Wet)
+        .args(["outdated", "--formula", "--quiet"])
+        .output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .filter_map(|formula| formula.trim().rsplit('/').next())
+        .filter(|formula| !formula.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// The outdated casks, including the ones `greedy_args` select.
+#[cfg(target_os = "macos")]
+fn brew_outdated_casks(variant: BrewVariant, greedy_args: &[&str]) -> Result<BTreeSet<String>> {
+    let output = variant
+        .execute(RunType::Wet)
+        .args(["outdated", "--cask", "--quiet"])
+        .args(greedy_args)
+        .output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|cask| !cask.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Reinstall the outdated casks of `reinstall_casks`, they don't update properly with an upgrade.
+#[cfg(target_os = "macos")]
+fn brew_reinstall_casks(ctx: &ExecutionContext, variant: BrewVariant, casks: &[&str]) -> Result<()> {
+    if ctx.run_type().dry() {
+        println!("Would reinstall the casks {}", casks.join(", "));
+    }
+
+    let mut failed = Vec::new();
+    for cask in casks {
+        let mut command = variant.execute(ctx.run_type());
+        command.args(["reinstall", "--cask"]);
+        if ctx.config().brew_cask_no_quarantine() {
+            command.arg("--no-quarantine");
+        }
+        brew_download_env(ctx, &mut command);
+        if let Err(e) = command.arg(cask).status_checked() {
+            crate::terminal::print_warning(format!("Failed to reinstall {cask}: {e}"));
+            failed.push(*cask);
+        }
+    }
+
+    if !ctx.run_type().dry() {
+        let reinstalled: Vec<&str> = casks.iter().filter(|cask| !failed.contains(*cask)).copied().collect();
+        if !reinstalled.is_empty() {
+            println!("Reinstalled casks: {}", reinstalled.join(", "));
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to reinstall the casks {}", failed.join(", ")))
+    }
+}
+
+/// Restart the started `brew services` of the `upgraded` formulae, so that they run the new binaries.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_restart_services(ctx: &ExecutionContext, variant: BrewVariant, upgraded: &BTreeSet<String>) -> Result<()> {
+    if upgraded.is_empty() {
+        return Ok(());
+    }
+
+    // Name          Status  User File
+    // postgresql@16 started me   ~/Library/LaunchAgents/homebrew.mxcl.postgresql@16.plist
+    let services = variant
+        .execute(RunType::Wet)
+        .args(["services", "list"])
+        .output_checked_utf8()?
+        .stdout;
+    let restarted: Vec<&str> = services
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            (fields.next()? == "started" && upgraded.contains(name)).then_some(name)
+        })
+        .collect();
+
+    for service in &restarted {
+        variant
+            .execute(ctx.run_type())
+            .args(["services", "restart", service])
+            .status_checked()?;
+    }
+    if !restarted.is_empty() && !ctx.run_type().dry() {
+        println!("Restarted the brew services: {}", restarted.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Run `brew doctor` and add the deprecated or disabled formulae it finds to the summary.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_doctor(variant: BrewVariant) -> Result<()> {
+    // `brew doctor` fails when it finds any problem, we only care about what it prints
+    let output = variant
+        .execute(RunType::Wet)
+        .arg("doctor")
+        .output_checked_with_utf8(|_| Ok(()))?;
+    let report = format!("{}{}", output.stdout, output.stderr);
+    print!("{report}");
+
+    // Warning: Some installed formulae are deprecated or disabled.
+    // You should find replacements for the following formulae:
+    //   formula1
+    //   formula2
+    let deprecated: Vec<&str> = report
+        .lines()
+        .skip_while(|line| !line.contains("deprecated or disabled"))
+        .skip(1)
+        .skip_while(|line| !line.starts_with(char::is_whitespace))
+        .take_while(|line| line.starts_with(char::is_whitespace) && !line.trim().is_empty())
+        .map(str::trim)
+        .collect();
+
+    if !deprecated.is_empty() {
+        add_summary_note(format!(
+            "{}: deprecated or disabled formulae: {}",
+            variant.step_title(),
+            deprecated.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run `brew missing` and add the formulae missing some dependencies to the summary.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_missing(variant: BrewVariant) -> Result<()> {
+    // `brew missing` fails when a dependency is missing, which is what it reports
+    let output = variant
+        .execute(RunType::Wet)
+        .arg("missing")
+        .output_checked_with_utf8(|_| Ok(()))?;
+    print!("{}", output.stdout);
+
+    // formula: dependency1 dependency2
+    let broken: Vec<&str> = output
+        .stdout
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(formula, _)| formula.trim())
+        .collect();
+
+    if !broken.is_empty() {
+        add_summary_note(format!(
+            "{}: formulae with missing dependencies, fix them with `brew reinstall`: {}",
+            variant.step_title(),
+            broken.join(", ")
+        ));
     }
 
     Ok(())
@@ -315,12 +819,28 @@ pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()>
     print_separator(format!("{} - Cask", variant.step_title()));
     let run_type = ctx.run_type();
 
+    // The formula step already ran `brew update`
+    if ctx.config().metadata_only() {
+        return variant.execute(run_type).args(["outdated", "--cask"]).status_checked();
+    }
+
     let cask_upgrade_exists = variant
         .execute(RunType::Wet)
         .args(["--repository", "buo/cask-upgrade"])
         .output_checked_utf8()
         .map(|p| Path::new(p.stdout.trim()).exists())?;
 
+    let mut greedy_args = vec![];
+    if ctx.config().brew_cask_greedy() {
+        greedy_args.push("--greedy");
+    }
+    if ctx.config().brew_greedy_latest() {
+        greedy_args.push("--greedy-latest");
+    }
+    if ctx.config().brew_greedy_auto_updates() {
+        greedy_args.push("--greedy-auto-updates");
+    }
+
     let mut brew_args = vec![];
 
     if cask_upgrade_exists {
@@ -330,39 +850,179 @@ pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()>
         }
     } else {
         brew_args.extend(["upgrade", "--cask"]);
-        if ctx.config().brew_cask_greedy() {
-            brew_args.push("--greedy");
-        }
-        if ctx.config().brew_greedy_latest() {
-            brew_args.push("--greedy-latest");
+        brew_args.extend(&greedy_args);
+    }
+    if ctx.config().brew_cask_no_quarantine() {
+        brew_args.push("--no-quarantine");
+    }
+
+    let outdated = brew_outdated_casks(variant, &greedy_args)?;
+    // `--greedy` includes both the `:latest` casks and the ones updating themselves
+    let all_outdated = brew_outdated_casks(variant, &["--greedy"])?;
+    let reinstall: Vec<&str> = ctx
+        .config()
+        .brew_reinstall_casks()
+        .iter()
+        .map(String::as_str)
+        .filter(|cask| all_outdated.contains(*cask))
+        .collect();
+    let skipped: Vec<&str> = all_outdated
+        .difference(&outdated)
+        .map(String::as_str)
+        .filter(|cask| !reinstall.contains(cask))
+        .collect();
+
+    if reinstall.is_empty() {
+        let mut command = variant.execute(run_type);
+        command.args(&brew_args);
+        brew_download_env(ctx, &mut command);
+        command.status_checked()?;
+    } else {
+        // Naming the other casks leaves the reinstalled ones out of the upgrade
+        let others: Vec<&str> = outdated
+            .iter()
+            .map(String::as_str)
+            .filter(|cask| !reinstall.contains(cask))
+            .collect();
+        if !others.is_empty() {
+            let mut command = variant.execute(run_type);
+            command.args(["upgrade", "--cask"]).args(&greedy_args);
+            if ctx.config().brew_cask_no_quarantine() {
+                command.arg("--no-quarantine");
+            }
+            brew_download_env(ctx, &mut command);
+            command.args(&others).status_checked()?;
         }
+        brew_reinstall_casks(ctx, variant, &reinstall)?;
     }
 
-    variant.execute(run_type).args(&brew_args).status_checked()?;
+    if !run_type.dry() {
+        let still_outdated = brew_outdated_casks(variant, &greedy_args)?;
+        let upgraded: Vec<&str> = outdated.difference(&still_outdated).map(String::as_str).collect();
+        if !upgraded.is_empty() {
+            println!("Upgraded casks: {}", upgraded.join(", "));
+        }
+    }
+    if !skipped.is_empty() {
+        println!("Skipped by the greedy settings: {}", skipped.join(", "));
+    }
 
-    if ctx.config().cleanup() {
+    if ctx.config().cleanup()
+        && ctx.confirm_destructive(
+            Step::BrewCask,
+            "the outdated versions of the installed casks and the old downloads in the brew cache",
+        )?
+    {
         variant.execute(run_type).arg("cleanup").status_checked()?;
     }
 
+    suggest_rehash(ctx);
+
     Ok(())
 }
 
+/// The packages of the user profile with their versions.
+fn guix_installed(guix: &Path) -> BTreeMap<String, String> {
+    Command::new(guix)
+        .args(["package", "--list-installed"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| {
+                    // name, version, output and store path, separated by tabs
+                    let mut columns = line.split('\t');
+                    Some((columns.next()?.to_string(), columns.next()?.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn run_guix(ctx: &ExecutionContext) -> Result<()> {
     let guix = require("guix")?;
 
     let run_type = ctx.run_type();
 
-    let output = Command::new(&guix).arg("pull").output_checked_utf8();
-    debug!("guix pull output: {:?}", output);
-    let should_upgrade = output.is_ok();
-    debug!("Can Upgrade Guix: {:?}", should_upgrade);
-
     print_separator("Guix");
 
-    if should_upgrade {
-        return run_type.execute(&guix).args(["package", "-u"]).status_checked();
+    if ctx.config().guix_pull() {
+        run_type
+            .execute(&guix)
+            .arg("pull")
+            .status_checked()
+            .context("Failed to pull the Guix channels")?;
+    }
+
+    if run_type.dry() {
+        // Building nothing, `--dry-run` shows what the upgrade would do
+        return Command::new(&guix).args(["upgrade", "--dry-run"]).status_checked();
     }
-    Err(SkipStep(String::from("Guix Pull Failed, Skipping")).into())
+
+    let before = guix_installed(&guix);
+    run_type.execute(&guix).arg("upgrade").status_checked()?;
+
+    for (name, version) in guix_installed(&guix) {
+        match before.get(&name) {
+            Some(old) if *old != version => println!("{name} {old} -> {version}"),
+            None => println!("{name} {version} (new)"),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_mandb(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().rebuild_mandb() {
+        return Err(SkipStep(String::from("`rebuild_mandb` is not enabled")).into());
+    }
+    // macOS keeps `makewhatis` out of PATH
+    let indexer = which("mandb")
+        .or_else(|| which("makewhatis"))
+        .or_else(|| PathBuf::from("/usr/libexec/makewhatis").if_exists())
+        .ok_or_else(|| SkipStep(String::from("Neither mandb nor makewhatis is installed")))?;
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    print_separator("Man page index");
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&indexer);
+    if indexer.ends_with("mandb") {
+        command.arg("--quiet");
+    }
+    command.status_checked()?;
+
+    println!("The man page index is rebuilt");
+    Ok(())
+}
+
+pub fn run_locatedb(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().rebuild_locatedb() {
+        return Err(SkipStep(String::from("`rebuild_locatedb` is not enabled")).into());
+    }
+    // `updatedb` is shared by mlocate and plocate, which may only install it as `updatedb.plocate`
+    // or in an sbin directory missing from the PATH of regular users. macOS and the BSDs keep their
+    // own out of PATH.
+    let updater = which("updatedb")
+        .or_else(|| which("updatedb.plocate"))
+        .or_else(|| {
+            ["/usr/sbin/updatedb", "/usr/libexec/locate.updatedb"]
+                .into_iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+        })
+        .ok_or_else(|| SkipStep(String::from("No locate implementation is installed")))?;
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    print_separator("locate database");
+
+    let started = Instant::now();
+    ctx.run_type().execute(sudo).arg(&updater).status_checked()?;
+
+    println!("The locate database is refreshed in {}s", started.elapsed().as_secs());
+    Ok(())
 }
 
 pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
@@ -389,26 +1049,109 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
+    if ctx.config().nix_check_substituters() {
+        check_nix_substituters(&nix);
+    }
+
     let run_type = ctx.run_type();
     run_type.execute(nix_channel).arg("--update").status_checked()?;
 
-    if Path::new(&manifest_json_path).exists() {
+    if !ctx.config().nix_env_upgrade() {
+        return Ok(());
+    }
+
+    let new_profile = Path::new(&manifest_json_path).exists();
+    let installed = || -> BTreeSet<String> {
+        let output = if new_profile {
+            Command::new(&nix)
+                .args(nix_args())
+                .args(["profile", "list"])
+                .output_checked_utf8()
+        } else {
+            Command::new(&nix_env).arg("--query").output_checked_utf8()
+        };
+        output
+            .map(|output| {
+                output
+                    .stdout
+                    .lines()
+                    .map(str::trim)
+                    // `nix profile list` shows the store paths, `nix-env` the names with versions
+                    .filter(|line| !new_profile || line.contains("/nix/store/"))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let before = installed();
+
+    if new_profile {
         run_type
-            .execute(nix)
+            .execute(&nix)
             .args(nix_args())
             .arg("profile")
             .arg("upgrade")
             .arg(".*")
             .arg("--verbose")
-            .status_checked()
+            .status_checked()?;
     } else {
-        let mut command = run_type.execute(nix_env);
+        let mut command = if run_type.dry() {
+            // `--dry-run` shows the upgrades without doing them
+            let mut command = RunType::Wet.execute(&nix_env);
+            command.arg("--dry-run");
+            command
+        } else {
+            run_type.execute(&nix_env)
+        };
         command.arg("--upgrade");
         if let Some(args) = ctx.config().nix_env_arguments() {
             command.args(args.split_whitespace());
         };
-        command.status_checked()
+        command.status_checked()?;
+    }
+
+    if !run_type.dry() {
+        let advanced: Vec<_> = installed().difference(&before).cloned().collect();
+        if !advanced.is_empty() {
+            println!("Upgraded packages:");
+            for package in advanced {
+                println!("  {package}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rebuild the environment of a project of `direnv_dirs`, so it is cached for the next `cd`.
+pub fn run_direnv_warmup(ctx: &ExecutionContext, dir: &Path) -> Result<()> {
+    let direnv = require("direnv")?;
+    let dir = dir.require()?;
+    dir.join(".envrc").require()?;
+
+    print_separator(format!("direnv ({})", dir.display()));
+
+    // The flake development shell is what `use flake` loads, building it first shows its output
+    if dir.join("flake.nix").exists() {
+        if let Some(nix) = which("nix") {
+            ctx.run_type()
+                .execute(nix)
+                .current_dir(dir)
+                .args(["develop", "--command", "true"])
+                .status_checked()?;
+        }
     }
+
+    ctx.run_type()
+        .execute(&direnv)
+        .arg("exec")
+        .arg(dir)
+        .arg("true")
+        .status_checked()?;
+    if !ctx.run_type().dry() {
+        println!("The environment of {} is up to date", dir.display());
+    }
+
+    Ok(())
 }
 
 pub fn run_nix_self_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -524,6 +1267,60 @@ fn nix_profile_dir(nix: &Path) -> Result<Option<PathBuf>> {
     )
 }
 
+/// The substituters in the Nix configuration, with `nix config show` or the older `nix show-config`.
+fn nix_substituters(nix: &Path) -> Vec<String> {
+    if let Ok(output) = Command::new(nix)
+        .args(nix_args())
+        .args(["config", "show", "substituters"])
+        .output_checked_utf8()
+    {
+        return output.stdout.split_whitespace().map(str::to_string).collect();
+    }
+
+    Command::new(nix)
+        .args(nix_args())
+        .arg("show-config")
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .find_map(|line| line.strip_prefix("substituters = "))
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Report the substituters that don't serve their `nix-cache-info`, an unreachable one slows down
+/// every build.
+fn check_nix_substituters(nix: &Path) {
+    let unreachable: Vec<String> = nix_substituters(nix)
+        .into_iter()
+        // The other stores, such as `ssh://` ones, can't be checked with curl
+        .filter(|substituter| substituter.starts_with("http://") || substituter.starts_with("https://"))
+        .filter(|substituter| {
+            Command::new("curl")
+                .args(["--silent", "--fail", "--head", "--max-time", "10"])
+                .arg(format!("{}/nix-cache-info", substituter.trim_end_matches('/')))
+                .output_checked()
+                .is_err()
+        })
+        .collect();
+
+    for substituter in &unreachable {
+        println!("The Nix substituter {substituter} is unreachable");
+    }
+    if !unreachable.is_empty() {
+        add_summary_note(format!(
+            "Unreachable Nix substituter(s), which slow down the builds: {}",
+            unreachable.join(", ")
+        ));
+    }
+}
+
 fn nix_args() -> [&'static str; 2] {
     ["--extra-experimental-features", "nix-command"]
 }
@@ -548,7 +1345,14 @@ pub fn run_asdf(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type()
         .execute(&asdf)
         .args(["plugin", "update", "--all"])
-        .status_checked()
+        .status_checked()?;
+
+    if ctx.config().reshim_after_update() {
+        ctx.run_type().execute(&asdf).arg("reshim").status_checked()?;
+        println!("The asdf shims are regenerated");
+    }
+
+    Ok(())
 }
 
 pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
@@ -561,16 +1365,41 @@ pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type()
         .execute(&mise)
         .args(["plugins", "update"])
-        .status_checked()
+        .status_checked()?;
+
+    if ctx.config().reshim_after_update() {
+        ctx.run_type().execute(&mise).arg("reshim").status_checked()?;
+        println!("The mise shims are regenerated");
+    }
+
+    Ok(())
 }
 
 pub fn run_home_manager(ctx: &ExecutionContext) -> Result<()> {
     let home_manager = require("home-manager")?;
 
+    let flake = ctx
+        .config()
+        .home_manager_flake()
+        .map(|flake| flake.require())
+        .transpose()?;
+
     print_separator("home-manager");
 
-    let mut cmd = ctx.run_type().execute(home_manager);
-    cmd.arg("switch");
+    // `--dry-run` builds the configuration and shows what activating it would do
+    let mut cmd = if ctx.run_type().dry() {
+        let mut cmd = RunType::Wet.execute(home_manager);
+        cmd.args(["switch", "--dry-run"]);
+        cmd
+    } else {
+        let mut cmd = ctx.run_type().execute(home_manager);
+        cmd.arg("switch");
+        cmd
+    };
+
+    if let Some(flake) = flake {
+        cmd.arg("--flake").arg(flake);
+    }
 
     if let Some(extra_args) = ctx.config().home_manager() {
         cmd.args(extra_args);
@@ -605,11 +1434,139 @@ pub fn run_pyenv(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep("Pyenv is installed, but $PYENV_ROOT is not set correctly".to_string()).into());
     }
 
-    if !pyenv_dir.join(".git").exists() {
+    let update_pyenv = pyenv_dir.join(".git").exists();
+    let install_latest = ctx.config().pyenv_install_latest();
+    if !update_pyenv && !install_latest {
         return Err(SkipStep("pyenv is not a git repository".to_string()).into());
     }
 
-    ctx.run_type().execute(pyenv).arg("update").status_checked()
+    if update_pyenv {
+        ctx.run_type().execute(&pyenv).arg("update").status_checked()?;
+    }
+    if install_latest {
+        pyenv_install_latest(ctx, &pyenv)?;
+    }
+
+    Ok(())
+}
+
+/// Install the latest patch release of each Python minor version installed with pyenv.
+fn pyenv_install_latest(ctx: &ExecutionContext, pyenv: &Path) -> Result<()> {
+    let versions = |args: &[&str]| -> Result<Vec<Version>> {
+        Ok(Command::new(pyenv)
+            .args(args)
+            .output_checked_utf8()?
+            .stdout
+            .lines()
+            .filter_map(|line| Version::parse(line.trim()).ok())
+            .filter(|version| version.pre.is_empty())
+            .collect())
+    };
+
+    // The newest installed version of each minor version, virtualenvs and other flavors are left alone
+    let mut installed: BTreeMap<(u64, u64), Version> = BTreeMap::new();
+    for version in versions(&["versions", "--bare"])? {
+        let newest = installed
+            .entry((version.major, version.minor))
+            .or_insert(version.clone());
+        if version > *newest {
+            *newest = version;
+        }
+    }
+    let available = versions(&["install", "--list"])?;
+
+    let mut upgrades = Vec::new();
+    for ((major, minor), current) in installed {
+        if let Some(latest) = available
+            .iter()
+            .filter(|version| version.major == major && version.minor == minor)
+            .max()
+            .filter(|latest| **latest > current)
+        {
+            upgrades.push((current, latest.clone()));
+        }
+    }
+
+    if upgrades.is_empty() {
+        println!("The installed Python versions are the latest patch releases");
+        return Ok(());
+    }
+
+    if ctx.run_type().dry() {
+        for (current, latest) in &upgrades {
+            println!("Would install Python {latest} (installed: {current})");
+        }
+        return Ok(());
+    }
+
+    for (current, latest) in &upgrades {
+        ctx.run_type()
+            .execute(pyenv)
+            .args(["install", "--skip-existing", &latest.to_string()])
+            .status_checked()?;
+        println!("Python {current} -> {latest}");
+    }
+    ctx.run_type().execute(pyenv).arg("rehash").status_checked()
+}
+
+pub fn run_tfenv(ctx: &ExecutionContext) -> Result<()> {
+    let manager = match ctx.config().terraform_flavor() {
+        TerraformFlavor::Terraform => "tfenv",
+        TerraformFlavor::OpenTofu => "tofuenv",
+    };
+    let managers: Vec<PathBuf> = [manager, "tgenv"].into_iter().filter_map(which).collect();
+    if managers.is_empty() {
+        return Err(SkipStep(format!("Neither {manager} nor tgenv is installed")).into());
+    }
+
+    print_separator("tfenv");
+
+    for manager in managers {
+        // Listing the remote versions doesn't change anything, so it also runs in dry-run mode
+        let latest = Command::new(&manager)
+            .arg("list-remote")
+            .output_checked_utf8()?
+            .stdout
+            .lines()
+            .filter_map(|line| Version::parse(line.trim()).ok())
+            .filter(|version| version.pre.is_empty())
+            .max()
+            .ok_or_else(|| eyre!("{} didn't list any release", manager.display()))?
+            .to_string();
+        println!("Latest version available with {}: {latest}", manager.display());
+
+        ctx.run_type()
+            .execute(&manager)
+            .args(["install", &latest])
+            .status_checked()?;
+        ctx.run_type()
+            .execute(&manager)
+            .args(["use", &latest])
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            println!("Now using {latest}");
+        }
+    }
+
+    Ok(())
+}
+
+/// List the SDKMAN! candidates whose installed version isn't the latest one.
+fn sdkman_outdated_candidates(bash: &Path, sdkman_init_path: &str) -> Result<Vec<String>> {
+    // Without any argument, `sdk upgrade` lists the outdated candidates as
+    // `<candidate> (local: <versions>; default: <version>)` and asks whether to upgrade them.
+    let cmd_outdated = format!("source {sdkman_init_path} && echo n | sdk upgrade");
+    let output = Command::new(bash)
+        .args(["-c", cmd_outdated.as_str()])
+        .output_checked_utf8()?;
+
+    Ok(console::strip_ansi_codes(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("(local:"))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect())
 }
 
 pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
@@ -646,17 +1603,37 @@ pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
             .status_checked()?;
     }
 
+    // `sdk update` only refreshes the list of candidates, so it also runs in dry-run mode
+    // to be able to tell which candidates are outdated.
     let cmd_update = format!("source {} && sdk update", &sdkman_init_path);
-    ctx.run_type()
-        .execute(&bash)
-        .args(["-c", cmd_update.as_str()])
-        .status_checked()?;
+    Command::new(&bash).args(["-c", cmd_update.as_str()]).status_checked()?;
 
-    let cmd_upgrade = format!("source {} && sdk upgrade", &sdkman_init_path);
-    ctx.run_type()
-        .execute(&bash)
-        .args(["-c", cmd_upgrade.as_str()])
-        .status_checked()?;
+    let outdated = sdkman_outdated_candidates(&bash, &sdkman_init_path)?;
+    if outdated.is_empty() {
+        println!("All candidates are up-to-date");
+    } else {
+        println!("Outdated candidates: {}", outdated.join(", "));
+
+        let cmd_upgrade = format!("source {} && sdk upgrade", &sdkman_init_path);
+        ctx.run_type()
+            .execute(&bash)
+            .args(["-c", cmd_upgrade.as_str()])
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            let still_outdated = sdkman_outdated_candidates(&bash, &sdkman_init_path)?;
+            let upgraded: Vec<&str> = outdated
+                .iter()
+                .filter(|candidate| !still_outdated.contains(candidate))
+                .map(String::as_str)
+                .collect();
+            if upgraded.is_empty() {
+                println!("No candidates upgraded");
+            } else {
+                println!("Upgraded candidates: {}", upgraded.join(", "));
+            }
+        }
+    }
 
     if ctx.config().cleanup() {
         let cmd_flush_archives = format!("source {} && sdk flush archives", &sdkman_init_path);
@@ -675,12 +1652,53 @@ pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+fn bun_version(bun: &Path) -> Option<String> {
+    Command::new(bun)
+        .arg("--version")
+        .output_checked_utf8()
+        .ok()
+        .map(|output| output.stdout.trim().to_string())
+}
+
+/// The global Bun packages, as `name@version`.
+fn bun_global_packages(bun: &Path) -> BTreeSet<String> {
+    Command::new(bun)
+        .args(["pm", "ls", "-g"])
+        .output_checked_utf8()
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| line.split_whitespace().last())
+                .filter(|package| package.contains('@'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
     let bun = require("bun")?;
+    if !ctx.config().bun_self_update() {
+        return Err(SkipStep(String::from("`bun_self_update` is disabled")).into());
+    }
+    if let Some(manager) = installing_package_manager(&bun) {
+        return Err(SkipStep(format!("Bun is installed with {manager}")).into());
+    }
 
     print_separator("Bun");
 
-    ctx.run_type().execute(bun).arg("upgrade").status_checked()
+    let before = bun_version(&bun);
+    ctx.run_type().execute(&bun).arg("upgrade").status_checked()?;
+
+    if !ctx.run_type().dry() {
+        match (before, bun_version(&bun)) {
+            (Some(before), Some(after)) if before != after => println!("Bun updated from {before} to {after}"),
+            (_, Some(after)) => println!("Bun {after} is up to date"),
+            _ => (),
+        }
+    }
+    Ok(())
 }
 
 pub fn run_bun_packages(ctx: &ExecutionContext) -> Result<()> {
@@ -698,7 +1716,15 @@ pub fn run_bun_packages(ctx: &ExecutionContext) -> Result<()> {
         return Ok(());
     }
 
-    ctx.run_type().execute(bun).args(["-g", "update"]).status_checked()
+    let before = bun_global_packages(&bun);
+    ctx.run_type().execute(&bun).args(["-g", "update"]).status_checked()?;
+
+    if !ctx.run_type().dry() {
+        for package in bun_global_packages(&bun).difference(&before) {
+            println!("  {package}");
+        }
+    }
+    Ok(())
 }
 
 /// Update dotfiles with `rcm(7)`.
@@ -1,14 +1,16 @@
+use std::cell::Cell;
+use std::env;
 use std::path::Path;
 use std::{ffi::OsStr, process::Command};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use etcetera::base_strategy::BaseStrategy;
 use tracing::debug;
 
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
-use crate::terminal::{print_separator, print_warning};
-use crate::utils::{require, which};
+use crate::terminal::{add_summary_note, print_separator, print_warning};
+use crate::utils::{require, which, PathExt, NO_METADATA_REFRESH};
 use crate::{error::SkipStep, steps::git::RepoStep};
 use crate::{powershell, Step};
 
@@ -36,15 +38,190 @@ pub fn run_chocolatey(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
+/// The identifiers of the packages in a table printed by winget.
+fn winget_table_ids(output: &str) -> Vec<String> {
+    // Name           Id                Version Available Source
+    // ---------------------------------------------------------
+    // Microsoft Edge Microsoft.Edge    120.0   121.0     winget
+    // 2 upgrades available.
+    let mut lines = output
+        .lines()
+        // The progress spinner is printed before the table with carriage returns
+        .map(|line| line.rsplit('\r').next().unwrap_or(line));
+    let Some(header) = lines.find(|line| line.starts_with("Name") && line.contains(" Id ")) else {
+        return Vec::new();
+    };
+    let header: Vec<char> = header.chars().collect();
+    let column = |name: &str| {
+        header
+            .windows(name.len() + 2)
+            .position(|window| window.iter().collect::<String>() == format!(" {name} "))
+            .map(|position| position + 1)
+    };
+    let Some(id_start) = column("Id") else {
+        return Vec::new();
+    };
+    let id_end = column("Version").unwrap_or(header.len());
+
+    lines
+        .skip_while(|line| line.starts_with('-'))
+        .take_while(|line| !line.trim().is_empty() && !line.starts_with(char::is_numeric))
+        .filter_map(|line| {
+            let line: Vec<char> = line.chars().collect();
+            let id: String = line.get(id_start..id_end.min(line.len()))?.iter().collect();
+            let id = id.trim();
+            (!id.is_empty()).then(|| id.to_string())
+        })
+        .collect()
+}
+
 pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
     let winget = require("winget")?;
+    let yes = ctx.config().yes(Step::Winget);
 
     print_separator("winget");
 
+    let mut agreements = vec!["--accept-source-agreements"];
+    if yes {
+        agreements.extend(["--accept-package-agreements", "--disable-interactivity"]);
+    }
+
     ctx.run_type()
-        .execute(winget)
-        .args(["upgrade", "--all"])
-        .status_checked()
+        .execute(&winget)
+        .args(["source", "update"])
+        .status_checked()?;
+
+    let list_upgrades = |include_pinned: bool| -> Result<Vec<String>> {
+        let mut command = Command::new(&winget);
+        command.arg("upgrade").arg("--accept-source-agreements");
+        if include_pinned {
+            command.arg("--include-pinned");
+        }
+        // winget exits with an error code when there is nothing to upgrade
+        let output = command.output_checked_with_utf8(|_| Ok(()))?;
+        Ok(winget_table_ids(&output.stdout))
+    };
+    let upgradable = list_upgrades(false)?;
+    let pinned: Vec<String> = list_upgrades(true)?
+        .into_iter()
+        .filter(|id| !upgradable.contains(id))
+        .collect();
+    let ignore = ctx.config().winget_ignore();
+    let (ignored, upgrades): (Vec<String>, Vec<String>) = upgradable
+        .into_iter()
+        .partition(|id| ignore.iter().any(|ignored| ignored.eq_ignore_ascii_case(id)));
+
+    let reboot_required = Cell::new(false);
+    let mut upgraded = Vec::new();
+    let mut failed = Vec::new();
+    for id in &upgrades {
+        let result = ctx
+            .run_type()
+            .execute(&winget)
+            .args(["upgrade", "--id", id, "--exact"])
+            .args(&agreements)
+            .status_checked_with(|status| match status.code().map(|code| code as u32) {
+                Some(0) => Ok(()),
+                // APPINSTALLER_CLI_ERROR_INSTALL_REBOOT_REQUIRED_TO_FINISH and _FOR_INSTALL
+                Some(0x8A15_0109 | 0x8A15_010A) => {
+                    reboot_required.set(true);
+                    Ok(())
+                }
+                _ => Err(()),
+            });
+        match result {
+            Ok(()) => upgraded.push(id.as_str()),
+            Err(e) => {
+                debug!("winget failed to upgrade {id}: {e:?}");
+                failed.push(id.as_str());
+            }
+        }
+    }
+
+    if !ctx.run_type().dry() && !upgraded.is_empty() {
+        println!("Upgraded: {}", upgraded.join(", "));
+    }
+    if !pinned.is_empty() {
+        println!("Skipped, pinned: {}", pinned.join(", "));
+    }
+    if !ignored.is_empty() {
+        println!("Skipped, in winget_ignore: {}", ignored.join(", "));
+    }
+    if reboot_required.get() {
+        add_summary_note("Reboot required: some winget upgrades are not finished");
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("winget failed to upgrade {}", failed.join(", ")))
+    }
+}
+
+pub fn run_visual_studio_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().visual_studio() {
+        return Err(SkipStep(String::from(
+            "Visual Studio updates are not enabled in the configuration",
+        ))
+        .into());
+    }
+
+    let installer_dir = env::var_os("ProgramFiles(x86)")
+        .map(|program_files| Path::new(&program_files).join("Microsoft Visual Studio\\Installer"))
+        .ok_or_else(|| SkipStep(String::from("ProgramFiles(x86) is not set")))?;
+    let vswhere = installer_dir.join("vswhere.exe").require()?;
+    let setup = installer_dir.join("setup.exe").require()?;
+
+    let instances = Command::new(&vswhere)
+        .args(["-all", "-prerelease", "-property", "installationPath"])
+        .output_checked_utf8()?
+        .stdout;
+    let instances: Vec<&str> = instances
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if instances.is_empty() {
+        return Err(SkipStep(String::from("No Visual Studio instance is installed")).into());
+    }
+
+    print_separator("Visual Studio");
+
+    // The installer waits for Visual Studio to be closed, which would hang the run
+    let running = Command::new("tasklist")
+        .args(["/FI", "IMAGENAME eq devenv.exe"])
+        .output_checked_utf8()
+        .map(|output| output.stdout.contains("devenv.exe"))
+        .unwrap_or(false);
+    if running {
+        return Err(eyre!("Visual Studio is running, close it to let it be updated"));
+    }
+
+    let reboot_required = Cell::new(false);
+    for instance in instances {
+        ctx.run_type()
+            .execute(&setup)
+            .args(["update", "--installPath", instance, "--quiet", "--norestart"])
+            .status_checked_with(|status| match status.code() {
+                Some(0) => Ok(()),
+                // ERROR_SUCCESS_REBOOT_REQUIRED
+                Some(3010) => {
+                    reboot_required.set(true);
+                    Ok(())
+                }
+                _ => Err(()),
+            })?;
+        if !ctx.run_type().dry() {
+            println!("Updated {instance}");
+        }
+    }
+
+    if reboot_required.get() {
+        print_warning("A reboot is required to finish the Visual Studio update");
+        add_summary_note("Reboot required: the Visual Studio update is not finished");
+    }
+
+    Ok(())
 }
 
 pub fn run_scoop(ctx: &ExecutionContext) -> Result<()> {
@@ -199,6 +376,10 @@ pub fn run_wsl_topgrade(ctx: &ExecutionContext) -> Result<()> {
 }
 
 pub fn windows_update(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(NO_METADATA_REFRESH.to_string()).into());
+    }
+
     let powershell = powershell::Powershell::windows_powershell();
 
     print_separator("Windows Update");
@@ -214,6 +395,28 @@ pub fn windows_update(ctx: &ExecutionContext) -> Result<()> {
     }
 }
 
+/// Install the driver updates offered by Windows Update, only when enabled and never remotely.
+pub fn windows_driver_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().windows_driver_updates() {
+        return Err(SkipStep(String::from("`driver_updates` is not enabled in the [windows] section")).into());
+    }
+    // A driver update can cut the connection or need someone at the machine
+    if ctx.under_ssh() || ctx.config().remote_guest() {
+        return Err(SkipStep(String::from("Driver updates are never run remotely")).into());
+    }
+    if ctx.config().metadata_only() {
+        return Err(SkipStep(NO_METADATA_REFRESH.to_string()).into());
+    }
+
+    let powershell = powershell::Powershell::windows_powershell();
+    if !powershell.supports_windows_update() {
+        return Err(SkipStep(String::from("Driver updates need the PSWindowsUpdate module")).into());
+    }
+
+    print_separator("Driver updates");
+    powershell.windows_driver_update(ctx)
+}
+
 pub fn reboot() -> Result<()> {
     // If this works, it won't return, but if it doesn't work, it may return a useful error
     // message.
@@ -230,7 +433,7 @@ pub fn insert_startup_scripts(git_repos: &mut RepoStep) -> Result<()> {
             if let Ok(lnk) = parselnk::Lnk::try_from(Path::new(&path)) {
                 debug!("Startup link: {:?}", lnk);
                 if let Some(path) = lnk.relative_path() {
-                    git_repos.insert_if_repo(&startup_dir.join(path));
+                    git_repos.insert_if_repo(startup_dir.join(path));
                 }
             }
         }
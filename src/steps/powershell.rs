@@ -7,6 +7,8 @@ use color_eyre::eyre::Result;
 
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
+#[cfg(windows)]
+use crate::terminal::add_summary_note;
 use crate::terminal::{is_dumb, print_separator};
 use crate::utils::{require_option, which, PathExt};
 use crate::Step;
@@ -121,4 +123,66 @@ impl Powershell {
             ])
             .status_checked()
     }
+
+    #[cfg(windows)]
+    pub fn windows_driver_update(&self, ctx: &ExecutionContext) -> Result<()> {
+        let powershell = require_option(self.path.as_ref(), String::from("Powershell is not installed"))?;
+
+        debug_assert!(self.supports_windows_update());
+
+        let drivers: Vec<String> = Command::new(powershell)
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Import-Module PSWindowsUpdate; Get-WindowsUpdate -UpdateType Driver | ForEach-Object { $_.Title }",
+            ])
+            .output_checked_utf8()?
+            .stdout
+            .lines()
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+            .map(String::from)
+            .collect();
+        if drivers.is_empty() {
+            println!("No driver updates available");
+            return Ok(());
+        }
+
+        let mut command = if let Some(sudo) = ctx.sudo() {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(powershell);
+            command
+        } else {
+            ctx.run_type().execute(powershell)
+        };
+        command
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Import-Module PSWindowsUpdate; Install-WindowsUpdate -UpdateType Driver -AcceptAll -IgnoreReboot -Verbose",
+            ])
+            .status_checked()?;
+        if ctx.run_type().dry() {
+            return Ok(());
+        }
+
+        println!("Updated drivers:");
+        for driver in &drivers {
+            println!("  {driver}");
+        }
+        let reboot_required = Command::new(powershell)
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Import-Module PSWindowsUpdate; Get-WURebootStatus -Silent",
+            ])
+            .output_checked_utf8()
+            .map(|output| output.stdout.trim().eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if reboot_required {
+            add_summary_note("Reboot to finish installing the driver updates");
+        }
+
+        Ok(())
+    }
 }
@@ -1,15 +1,17 @@
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{fmt::Display, rc::Rc, str::FromStr};
+use std::{fmt::Display, fs, rc::Rc, str::FromStr};
 
 use color_eyre::eyre::Result;
 use regex::Regex;
 use strum::EnumString;
 use tracing::{debug, error};
 
+use crate::breaking_changes::data_dir;
 use crate::command::CommandExt;
+use crate::error::{ClassifiedFailure, FailureKind, TopgradeError};
 use crate::execution_context::ExecutionContext;
-use crate::terminal::print_separator;
+use crate::terminal::{print_separator, print_warning, prompt_yesno};
 use crate::{error::SkipStep, utils, Step};
 
 #[derive(Debug, Copy, Clone, EnumString)]
@@ -112,42 +114,192 @@ impl<'a> TemporaryPowerOn<'a> {
             BoxStatus::Running => unreachable!(),
         };
 
+        // Recorded first, a run killed while the box boots must still restore it
+        if !ctx.run_type().dry() {
+            PoweredOnBox {
+                restore: Self::restore_subcommand(vagrant_box, ctx).to_string(),
+                name: vagrant_box.name.clone(),
+                path: vagrant_box.path.to_path_buf(),
+            }
+            .record();
+        }
+
         ctx.run_type()
             .execute(vagrant)
             .args([subcommand, &vagrant_box.name])
             .current_dir(vagrant_box.path.clone())
-            .status_checked()?;
+            .status_checked()
+            .map_err(|e| {
+                // No Drop restores a box that didn't power on
+                if !ctx.run_type().dry() {
+                    PoweredOnBox::forget(&vagrant_box.name, &vagrant_box.path);
+                }
+                let kind = FailureKind::classify(&e);
+                e.wrap_err(ClassifiedFailure {
+                    kind,
+                    message: format!("Failed to power on {vagrant_box}"),
+                })
+            })?;
         Ok(TemporaryPowerOn {
             vagrant,
             vagrant_box,
             ctx,
         })
     }
-}
 
-impl<'a> Drop for TemporaryPowerOn<'a> {
-    fn drop(&mut self) {
-        let subcommand = if self.ctx.config().vagrant_always_suspend().unwrap_or(false) {
+    /// The subcommand bringing the box back to its initial state.
+    fn restore_subcommand(vagrant_box: &VagrantBox, ctx: &ExecutionContext) -> &'static str {
+        if ctx.config().vagrant_always_suspend().unwrap_or(false) {
             "suspend"
         } else {
-            match self.vagrant_box.initial_status {
+            match vagrant_box.initial_status {
                 BoxStatus::PowerOff | BoxStatus::Aborted => "halt",
                 BoxStatus::Saved => "suspend",
                 BoxStatus::Running => unreachable!(),
             }
-        };
+        }
+    }
+}
+
+impl<'a> Drop for TemporaryPowerOn<'a> {
+    fn drop(&mut self) {
+        let subcommand = Self::restore_subcommand(self.vagrant_box, self.ctx);
 
         println!();
-        self.ctx
+        let restored = self
+            .ctx
             .run_type()
             .execute(self.vagrant)
             .args([subcommand, &self.vagrant_box.name])
             .current_dir(self.vagrant_box.path.clone())
-            .status_checked()
-            .ok();
+            .status_checked();
+        if restored.is_ok() && !self.ctx.run_type().dry() {
+            PoweredOnBox::forget(&self.vagrant_box.name, &self.vagrant_box.path);
+        }
+    }
+}
+
+/// A box powered on by a run, kept in a state file until it is restored so that a run killed in
+/// the middle can be cleaned up by the next one.
+#[derive(Debug, PartialEq, Eq)]
+struct PoweredOnBox {
+    restore: String,
+    name: String,
+    path: PathBuf,
+}
+
+impl PoweredOnBox {
+    fn state_file_path() -> PathBuf {
+        data_dir().join("vagrant_powered_on")
+    }
+
+    /// Read the state file.
+    fn read_all() -> Vec<PoweredOnBox> {
+        fs::read_to_string(Self::state_file_path())
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default()
+    }
+
+    /// Parse the state file, one `<restore subcommand>\t<name>\t<path>` line per box.
+    fn parse(content: &str) -> Vec<PoweredOnBox> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                Some(PoweredOnBox {
+                    restore: fields.next()?.to_string(),
+                    name: fields.next()?.to_string(),
+                    path: PathBuf::from(fields.next()?),
+                })
+            })
+            .collect()
+    }
+
+    fn write_all(boxes: &[PoweredOnBox]) {
+        let path = Self::state_file_path();
+        let result = if boxes.is_empty() {
+            fs::remove_file(&path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        } else {
+            fs::create_dir_all(data_dir()).and_then(|_| fs::write(&path, Self::format(boxes)))
+        };
+
+        if let Err(e) = result {
+            error!("Failed to update {}: {}", path.display(), e);
+        }
+    }
+
+    fn format(boxes: &[PoweredOnBox]) -> String {
+        boxes
+            .iter()
+            .map(|b| format!("{}\t{}\t{}\n", b.restore, b.name, b.path.display()))
+            .collect()
+    }
+
+    fn record(self) {
+        let mut boxes = Self::read_all();
+        if !boxes.contains(&self) {
+            boxes.push(self);
+        }
+        Self::write_all(&boxes);
+    }
+
+    fn forget(name: &str, path: &Path) {
+        let mut boxes = Self::read_all();
+        boxes.retain(|b| !(b.name == name && b.path == path));
+        Self::write_all(&boxes);
     }
 }
 
+/// Restore the boxes a previous, interrupted run left powered on.
+pub fn recover_interrupted_run(ctx: &ExecutionContext) -> Result<()> {
+    let boxes = PoweredOnBox::read_all();
+    if boxes.is_empty() {
+        return Ok(());
+    }
+
+    print_separator("Vagrant recovery");
+    print_warning("The previous run was interrupted and left these Vagrant boxes powered on:");
+    for powered_on in &boxes {
+        println!(
+            "  {} @ {} (to {})",
+            powered_on.name,
+            powered_on.path.display(),
+            powered_on.restore
+        );
+    }
+
+    if !(ctx.config().yes(Step::Vagrant) || prompt_yesno("Restore them to their prior state?")?) {
+        return Ok(());
+    }
+
+    let Some(vagrant) = utils::which("vagrant") else {
+        print_warning("Cannot restore the boxes, vagrant isn't in PATH anymore");
+        return Ok(());
+    };
+    let mut remaining = Vec::new();
+    for powered_on in boxes {
+        let restored = ctx
+            .run_type()
+            .execute(&vagrant)
+            .args([&powered_on.restore, &powered_on.name])
+            .current_dir(&powered_on.path)
+            .status_checked();
+        if let Err(e) = restored {
+            error!("Failed to restore {}: {}", powered_on.name, e);
+            remaining.push(powered_on);
+        }
+    }
+
+    if !ctx.run_type().dry() {
+        PoweredOnBox::write_all(&remaining);
+    }
+
+    Ok(())
+}
+
 pub fn collect_boxes(ctx: &ExecutionContext) -> Result<Vec<VagrantBox>> {
     let directories = utils::require_option(
         ctx.config().vagrant_directories(),
@@ -174,6 +326,13 @@ pub fn collect_boxes(ctx: &ExecutionContext) -> Result<Vec<VagrantBox>> {
     Ok(result)
 }
 
+/// With `--explain`, show the status the power on and off decisions are derived from.
+fn explain_box_status(ctx: &ExecutionContext, vagrant_box: &VagrantBox) {
+    if ctx.config().explain().is_some() {
+        println!("{vagrant_box} is {:?}", vagrant_box.initial_status);
+    }
+}
+
 pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) -> Result<()> {
     let vagrant = Vagrant {
         path: utils::require("vagrant")?,
@@ -186,10 +345,12 @@ pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) ->
             return Err(SkipStep(format!("Skipping powered off box {vagrant_box}")).into());
         } else {
             print_separator(seperator);
+            explain_box_status(ctx, vagrant_box);
             _poweron = Some(vagrant.temporary_power_on(vagrant_box, ctx)?);
         }
     } else {
         print_separator(seperator);
+        explain_box_status(ctx, vagrant_box);
     }
     let mut command = format!("env TOPGRADE_PREFIX={} topgrade", vagrant_box.smart_name());
     if ctx.config().yes(Step::Vagrant) {
@@ -201,6 +362,17 @@ pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) ->
         .current_dir(&vagrant_box.path)
         .args(["ssh", "-c", &command])
         .status_checked()
+        .map_err(|e| {
+            // ssh exits with 255 when the connection itself failed, other codes come from Topgrade in the box
+            let kind = match e.downcast_ref::<TopgradeError>() {
+                Some(TopgradeError::ProcessFailed(_, status)) if status.code() == Some(255) => FailureKind::Network,
+                _ => FailureKind::classify(&e),
+            };
+            e.wrap_err(ClassifiedFailure {
+                kind,
+                message: format!("Failed to run Topgrade in {vagrant_box}"),
+            })
+        })
 }
 
 pub fn upgrade_vagrant_boxes(ctx: &ExecutionContext) -> Result<()> {
@@ -237,3 +409,31 @@ pub fn upgrade_vagrant_boxes(ctx: &ExecutionContext) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_powered_on_boxes() {
+        let boxes = vec![
+            PoweredOnBox {
+                restore: String::from("halt"),
+                name: String::from("default"),
+                path: PathBuf::from("/home/user/vm"),
+            },
+            PoweredOnBox {
+                restore: String::from("suspend"),
+                name: String::from("web"),
+                path: PathBuf::from("/home/user/my\tboxes"),
+            },
+        ];
+        let content = PoweredOnBox::format(&boxes);
+        assert_eq!(
+            content,
+            "halt\tdefault\t/home/user/vm\nsuspend\tweb\t/home/user/my\tboxes\n"
+        );
+        assert_eq!(PoweredOnBox::parse(&content), boxes);
+        assert_eq!(PoweredOnBox::parse("halt\tdefault\n"), Vec::new());
+    }
+}
@@ -8,6 +8,19 @@ use std::path::Path;
 use std::{path::PathBuf, process::Command};
 use tracing::debug;
 
+/// A toolbox container, and whether it was running before Topgrade touched it.
+#[derive(Debug)]
+pub struct Toolbox {
+    name: String,
+    running: bool,
+}
+
+impl Toolbox {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 fn list_toolboxes(toolbx: &Path) -> Result<Vec<String>> {
     let output = Command::new(toolbx)
         .args(["list", "--containers"])
@@ -28,39 +41,87 @@ fn list_toolboxes(toolbx: &Path) -> Result<Vec<String>> {
     Ok(proc)
 }
 
-pub fn run_toolbx(ctx: &ExecutionContext) -> Result<()> {
-    let toolbx = require("toolbox")?;
+/// Whether podman reports the container as running.
+fn is_running(name: &str) -> bool {
+    Command::new("podman")
+        .args(["inspect", "--format", "{{.State.Running}}", name])
+        .output_checked_utf8()
+        .is_ok_and(|output| output.stdout.trim() == "true")
+}
 
-    print_separator("Toolbx");
-    let toolboxes = list_toolboxes(&toolbx)?;
+pub fn collect_toolboxes() -> Result<Vec<Toolbox>> {
+    let toolbx = require("toolbox")?;
+    let toolboxes: Vec<Toolbox> = list_toolboxes(&toolbx)?
+        .into_iter()
+        .map(|name| Toolbox {
+            running: is_running(&name),
+            name,
+        })
+        .collect();
     debug!("Toolboxes to inspect: {:?}", toolboxes);
 
+    Ok(toolboxes)
+}
+
+/// `toolbox run` starts a stopped container, this stops it again once the update is done.
+struct TemporaryStart<'a> {
+    toolbox: &'a Toolbox,
+    ctx: &'a ExecutionContext<'a>,
+}
+
+impl<'a> Drop for TemporaryStart<'a> {
+    fn drop(&mut self) {
+        if self.toolbox.running {
+            return;
+        }
+        if let Err(e) = self
+            .ctx
+            .run_type()
+            .execute("podman")
+            .args(["stop", &self.toolbox.name])
+            .output_checked()
+        {
+            debug!("Failed to stop the {} toolbox: {e}", self.toolbox.name);
+        }
+    }
+}
+
+pub fn topgrade_toolbox(ctx: &ExecutionContext, toolbox: &Toolbox) -> Result<()> {
+    let toolbx = require("toolbox")?;
+
+    print_separator(format!("Toolbx ({})", toolbox.name));
+    let _started = TemporaryStart { toolbox, ctx };
+
+    if let Some(command) = ctx.config().toolbx_command() {
+        return ctx
+            .run_type()
+            .execute(&toolbx)
+            .args(["run", "-c", &toolbox.name, "sh", "-c", command])
+            .status_checked();
+    }
+
     let mut topgrade_path = PathBuf::from("/run/host");
     // Path of the running Topgrade executable
     // Skip 1 to eliminate the path root, otherwise push overwrites the path
     topgrade_path.push(std::env::current_exe()?.components().skip(1).collect::<PathBuf>());
     let topgrade_path = topgrade_path.to_str().unwrap();
 
-    for tb in toolboxes.iter() {
-        let topgrade_prefix = format!("TOPGRADE_PREFIX='Toolbx {tb}'");
-        let mut args = vec![
-            "run",
-            "-c",
-            tb,
-            "env",
-            &topgrade_prefix,
-            topgrade_path,
-            "--only",
-            "system",
-            "--no-self-update",
-            "--skip-notify",
-        ];
-        if ctx.config().yes(Step::Toolbx) {
-            args.push("--yes");
-        }
-
-        ctx.run_type().execute(&toolbx).args(&args).status_checked()?;
+    let topgrade_prefix = format!("TOPGRADE_PREFIX='Toolbx {}'", toolbox.name);
+    let mut args = vec![
+        "run",
+        "-c",
+        &toolbox.name,
+        "env",
+        &topgrade_prefix,
+        topgrade_path,
+        "--only",
+        "system",
+        "--no-self-update",
+        "--skip-notify",
+    ];
+    if ctx.config().yes(Step::Toolbx) {
+        args.push("--yes");
     }
 
-    Ok(())
+    ctx.run_type().execute(&toolbx).args(&args).status_checked()
 }
@@ -1,5 +1,5 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use color_eyre::eyre::Result;
@@ -7,6 +7,7 @@ use tracing::debug;
 use walkdir::WalkDir;
 
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 use crate::git::RepoStep;
 use crate::terminal::print_separator;
@@ -165,8 +166,40 @@ pub fn run_zim(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+/// The commit checked out in the oh-my-zsh repository.
+fn oh_my_zsh_revision(oh_my_zsh: &Path) -> Option<String> {
+    Command::new("git")
+        .arg("-C")
+        .arg(oh_my_zsh)
+        .args(["rev-parse", "HEAD"])
+        .output_checked_utf8()
+        .ok()
+        .map(|output| output.stdout.trim().to_string())
+}
+
+/// Compare the checked out commit with the one of the upstream branch, without fetching anything.
+fn check_oh_my_zsh_update(oh_my_zsh: &Path) -> Result<()> {
+    let remote = Command::new("git")
+        .arg("-C")
+        .arg(oh_my_zsh)
+        .args(["ls-remote", "origin", "HEAD"])
+        .output_checked_utf8()?;
+    let latest = remote.stdout.split_whitespace().next().unwrap_or_default();
+
+    if oh_my_zsh_revision(oh_my_zsh).as_deref() == Some(latest) {
+        println!("oh-my-zsh is up to date");
+    } else {
+        println!("An oh-my-zsh update is available");
+    }
+
+    Ok(())
+}
+
 pub fn run_oh_my_zsh(ctx: &ExecutionContext) -> Result<()> {
     require("zsh")?;
+    if !ctx.config().omz() {
+        return Err(SkipStep(String::from("oh-my-zsh updates are disabled by `omz`")).into());
+    }
 
     // When updating `oh-my-zsh` on a remote machine through topgrade, the
     // following processes will be created:
@@ -227,12 +260,28 @@ pub fn run_oh_my_zsh(ctx: &ExecutionContext) -> Result<()> {
     }
 
     custom_repos.remove(&oh_my_zsh);
+
+    if ctx.run_type().dry() {
+        return check_oh_my_zsh_update(&oh_my_zsh);
+    }
+
+    let before = oh_my_zsh_revision(&oh_my_zsh);
     ctx.run_type()
         .execute("zsh")
-        .arg(&oh_my_zsh.join("tools/upgrade.sh"))
+        .arg(oh_my_zsh.join("tools/upgrade.sh"))
+        .env("ZSH", &oh_my_zsh)
         // oh-my-zsh returns 80 when it is already updated and no changes pulled
         // in this update.
         // See this comment: https://github.com/r-darwish/topgrade/issues/569#issuecomment-736756731
         // for more information.
-        .status_checked_with_codes(&[80])
+        .status_checked_with_codes(&[80])?;
+
+    match (before, oh_my_zsh_revision(&oh_my_zsh)) {
+        (Some(before), Some(after)) if before != after => {
+            println!("oh-my-zsh updated from {:.7} to {:.7}", before, after)
+        }
+        _ => println!("oh-my-zsh is up to date"),
+    }
+
+    Ok(())
 }
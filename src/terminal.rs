@@ -1,14 +1,17 @@
+use std::borrow::Cow;
 use std::cmp::{max, min};
 use std::env;
+use std::fs;
 use std::io::{self, Write};
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::sync::Mutex;
 use std::time::Duration;
 
 use chrono::{Local, Timelike};
 use color_eyre::eyre;
 use color_eyre::eyre::Context;
-use console::{style, Key, Term};
+use console::{style, Color, Key, Term};
 use lazy_static::lazy_static;
 use notify_rust::{Notification, Timeout};
 use tracing::{debug, error};
@@ -16,10 +19,74 @@ use tracing::{debug, error};
 use which_crate::which;
 
 use crate::command::CommandExt;
-use crate::report::StepResult;
+use crate::config::{ColorTheme, Colors, SummaryOutput, TerminalColor};
+use crate::report::{Report, StepResult};
 
 lazy_static! {
     static ref TERMINAL: Mutex<Terminal> = Mutex::new(Terminal::new());
+    /// Things steps want the user to notice, shown after the summary
+    static ref SUMMARY_NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// The colors of the output, from the `color_theme` and the `[colors]` overrides.
+#[derive(Clone, Copy)]
+struct Palette {
+    success: Color,
+    failure: Color,
+    /// The warnings, the prompts, the ignored failures and the summary notes
+    warning: Color,
+    /// The skipped steps and the information messages
+    skip: Color,
+    /// `None` keeps the foreground color of the terminal
+    separator: Option<Color>,
+}
+
+impl Palette {
+    const DARK: Palette = Palette {
+        success: Color::Green,
+        failure: Color::Red,
+        warning: Color::Yellow,
+        skip: Color::Blue,
+        separator: None,
+    };
+
+    // Yellow is barely readable on a light background
+    const LIGHT: Palette = Palette {
+        warning: Color::Magenta,
+        ..Palette::DARK
+    };
+}
+
+static PALETTE: Mutex<Palette> = Mutex::new(Palette::DARK);
+
+fn palette() -> Palette {
+    *PALETTE.lock().unwrap()
+}
+
+fn console_color(color: TerminalColor) -> Color {
+    match color {
+        TerminalColor::Black => Color::Black,
+        TerminalColor::Red => Color::Red,
+        TerminalColor::Green => Color::Green,
+        TerminalColor::Yellow => Color::Yellow,
+        TerminalColor::Blue => Color::Blue,
+        TerminalColor::Magenta => Color::Magenta,
+        TerminalColor::Cyan => Color::Cyan,
+        TerminalColor::White => Color::White,
+    }
+}
+
+/// The theme matching the background of the terminal, if it tells it through `COLORFGBG`.
+fn background_theme() -> Option<ColorTheme> {
+    // `foreground;background`, or `foreground;default;background` for rxvt
+    let colorfgbg = env::var("COLORFGBG").ok()?;
+    let background: u8 = colorfgbg.rsplit(';').next()?.parse().ok()?;
+    // The light ones are white (7) and the bright colors but bright black (8)
+    Some(if background == 7 || background > 8 {
+        ColorTheme::Light
+    } else {
+        ColorTheme::Dark
+    })
 }
 
 #[cfg(unix)]
@@ -43,6 +110,9 @@ struct Terminal {
     set_title: bool,
     display_time: bool,
     desktop_notification: bool,
+    separator_char: char,
+    separator_width: u16,
+    separator_full_width: bool,
 }
 
 impl Terminal {
@@ -57,6 +127,9 @@ impl Terminal {
             set_title: true,
             display_time: true,
             desktop_notification: false,
+            separator_char: '─',
+            separator_width: 80,
+            separator_full_width: false,
         }
     }
 
@@ -72,6 +145,12 @@ impl Terminal {
         self.display_time = display_time
     }
 
+    fn set_separator_style(&mut self, separator_char: char, separator_width: u16, separator_full_width: bool) {
+        self.separator_char = separator_char;
+        self.separator_width = separator_width;
+        self.separator_full_width = separator_full_width;
+    }
+
     fn notify_desktop<P: AsRef<str>>(&self, message: P, timeout: Option<Duration>) {
         debug!("Desktop notification: {}", message.as_ref());
         let mut notification = Notification::new();
@@ -110,29 +189,36 @@ impl Terminal {
             String::from(message.as_ref())
         };
 
+        // When the output isn't a terminal, always use the fixed width so that logs are stable
+        let width = match self.width {
+            Some(width) if self.separator_full_width => width,
+            Some(width) => min(self.separator_width, width),
+            None => self.separator_width,
+        };
+        let border = max(
+            2,
+            (width as usize)
+                .checked_sub(4)
+                .and_then(|e| e.checked_sub(message.len()))
+                .unwrap_or(0),
+        );
+        let separator = format!(
+            "{lead} {message} {border}",
+            lead = self.separator_char.to_string().repeat(2),
+            border = self.separator_char.to_string().repeat(border)
+        );
+
         match self.width {
-            Some(width) => {
-                self.term
-                    .write_fmt(format_args!(
-                        "{}\n",
-                        style(format_args!(
-                            "\n── {} {:─^border$}",
-                            message,
-                            "",
-                            border = max(
-                                2,
-                                min(80, width as usize)
-                                    .checked_sub(4)
-                                    .and_then(|e| e.checked_sub(message.len()))
-                                    .unwrap_or(0)
-                            )
-                        ))
-                        .bold()
-                    ))
-                    .ok();
+            Some(_) => {
+                let styled = style(format!("\n{separator}")).bold();
+                let styled = match palette().separator {
+                    Some(color) => styled.fg(color),
+                    None => styled,
+                };
+                self.term.write_fmt(format_args!("{styled}\n")).ok();
             }
             None => {
-                self.term.write_fmt(format_args!("―― {message} ――\n")).ok();
+                self.term.write_fmt(format_args!("{separator}\n")).ok();
             }
         }
     }
@@ -144,7 +230,7 @@ impl Terminal {
         self.term
             .write_fmt(format_args!(
                 "{} {}",
-                style(format!("{key} failed:")).red().bold(),
+                style(format!("{key} failed:")).fg(palette().failure).bold(),
                 message
             ))
             .ok();
@@ -154,7 +240,7 @@ impl Terminal {
     fn print_warning<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
         self.term
-            .write_fmt(format_args!("{}\n", style(message).yellow().bold()))
+            .write_fmt(format_args!("{}\n", style(message).fg(palette().warning).bold()))
             .ok();
     }
 
@@ -162,25 +248,12 @@ impl Terminal {
     fn print_info<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
         self.term
-            .write_fmt(format_args!("{}\n", style(message).blue().bold()))
+            .write_fmt(format_args!("{}\n", style(message).fg(palette().skip).bold()))
             .ok();
     }
 
     fn print_result<P: AsRef<str>>(&mut self, key: P, result: &StepResult) {
-        let key = key.as_ref();
-
-        self.term
-            .write_fmt(format_args!(
-                "{}: {}\n",
-                key,
-                match result {
-                    StepResult::Success => format!("{}", style("OK").bold().green()),
-                    StepResult::Failure => format!("{}", style("FAILED").bold().red()),
-                    StepResult::Ignored => format!("{}", style("IGNORED").bold().yellow()),
-                    StepResult::Skipped(reason) => format!("{}: {}", style("SKIPPED").bold().blue(), reason),
-                }
-            ))
-            .ok();
+        self.term.write_str(&format_result(key, result)).ok();
     }
 
     #[allow(dead_code)]
@@ -188,7 +261,7 @@ impl Terminal {
         self.term
             .write_fmt(format_args!(
                 "{}",
-                style(format!("{question} (y)es/(N)o",)).yellow().bold()
+                style(format!("{question} (y)es/(N)o",)).fg(palette().warning).bold()
             ))
             .ok();
 
@@ -215,7 +288,7 @@ impl Terminal {
         }
 
         let prompt_inner = style(format!("{}Retry? (y)es/(N)o/(s)hell/(q)uit", self.prefix))
-            .yellow()
+            .fg(palette().warning)
             .bold();
 
         self.term.write_fmt(format_args!("\n{prompt_inner}")).ok();
@@ -253,6 +326,133 @@ impl Terminal {
     }
 }
 
+/// Format a line of the summary. Colors are only used if stdout supports them.
+fn format_result<P: AsRef<str>>(key: P, result: &StepResult) -> String {
+    let palette = palette();
+    format!(
+        "{}: {}\n",
+        key.as_ref(),
+        match result {
+            StepResult::Success => format!("{}", style("OK").bold().fg(palette.success)),
+            StepResult::Failure(kind) => format!("{} ({kind})", style("FAILED").bold().fg(palette.failure)),
+            StepResult::Ignored => format!("{}", style("IGNORED").bold().fg(palette.warning)),
+            StepResult::Skipped(reason) => format!("{}: {}", style("SKIPPED").bold().fg(palette.skip), reason),
+        }
+    )
+}
+
+/// The line replacing the successful steps when the summary collapses them.
+fn format_success_count(count: usize) -> String {
+    let steps = if count == 1 { "step" } else { "steps" };
+    format!(
+        "{}\n",
+        style(format!("{count} {steps} succeeded")).bold().fg(palette().success)
+    )
+}
+
+/// Split the results between the successful steps collapsed into a count, if `collapse_success`,
+/// and the ones listed individually.
+fn summary_results<'a>(report: &'a Report, collapse_success: bool) -> (usize, Vec<(&'a str, &'a StepResult)>) {
+    let (collapsed, listed): (Vec<_>, Vec<_>) = report
+        .data()
+        .iter()
+        .map(|(key, result)| (key.as_ref(), result))
+        .partition(|(_, result)| collapse_success && matches!(result, StepResult::Success));
+    (collapsed.len(), listed)
+}
+
+fn format_summary(report: &Report, collapse_success: bool) -> String {
+    let mut summary = String::from("Summary\n\n");
+    let (succeeded, listed) = summary_results(report, collapse_success);
+    if succeeded > 0 {
+        summary.push_str(&format_success_count(succeeded));
+    }
+    for (key, result) in listed {
+        summary.push_str(&format_result(key, result));
+    }
+    for note in SUMMARY_NOTES.lock().unwrap().iter() {
+        summary.push_str(&format!("\n{}\n", style(note).fg(palette().warning).bold()));
+    }
+    summary
+}
+
+/// The whole summary without colors, as emailed.
+pub fn summary_text(report: &Report) -> String {
+    console::strip_ansi_codes(&format_summary(report, false)).into_owned()
+}
+
+/// Add a note that will be shown after the summary, at the end of the run.
+pub fn add_summary_note<P: Into<String>>(note: P) {
+    SUMMARY_NOTES.lock().unwrap().push(note.into());
+}
+
+fn page_summary(summary: &str) -> eyre::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| String::from("less -R"));
+    let pager = shell_words::split(&pager).with_context(|| format!("Failed to parse `PAGER`: `{pager}`"))?;
+    let (program, args) = pager.split_first().ok_or_else(|| eyre::eyre!("`PAGER` is empty"))?;
+
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    // less shows the colors with `-R`, the other pagers may show the raw escape codes instead
+    let summary = if Path::new(program).file_stem().is_some_and(|stem| stem == "less") {
+        let options = env::var("LESS").map_or_else(|_| String::from("-R"), |options| format!("{options} -R"));
+        command.env("LESS", options);
+        Cow::Borrowed(summary)
+    } else {
+        console::strip_ansi_codes(summary)
+    };
+
+    let mut child = command.spawn_checked()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be closed before reading everything, this is not an error
+        stdin.write_all(summary.as_bytes()).ok();
+    }
+    child.wait().context("Failed to wait for the pager")?;
+
+    Ok(())
+}
+
+fn write_summary(summary: &str, path: &Path) -> eyre::Result<()> {
+    fs::write(path, console::strip_ansi_codes(summary).as_bytes())
+        .with_context(|| format!("Failed to write the summary to {}", path.display()))?;
+    println!("Summary written to {}", path.display());
+
+    Ok(())
+}
+
+/// Show the summary of the run at the place chosen with `summary_output`.
+///
+/// Falls back to printing the summary in the terminal if that isn't possible.
+pub fn show_summary(report: &Report, output: SummaryOutput, file: Option<&Path>, collapse_success: bool) {
+    let result = match (output, file) {
+        (SummaryOutput::Terminal, _) => Ok(false),
+        (SummaryOutput::Pager, _) if is_dumb() => Ok(false),
+        (SummaryOutput::Pager, _) => page_summary(&format_summary(report, collapse_success)).map(|_| true),
+        (SummaryOutput::File, Some(file)) => {
+            write_summary(&format_summary(report, collapse_success), file).map(|_| true)
+        }
+        (SummaryOutput::File, None) => Err(eyre::eyre!("`summary_output` is `file` but `summary_file` is not set")),
+    };
+
+    match result {
+        Ok(true) => return,
+        Ok(false) => (),
+        Err(e) => error!("{e:?}"),
+    }
+
+    print_separator("Summary");
+    let (succeeded, listed) = summary_results(report, collapse_success);
+    if succeeded > 0 {
+        print!("{}", format_success_count(succeeded));
+    }
+    for (key, result) in listed {
+        print_result(key, result);
+    }
+    for note in SUMMARY_NOTES.lock().unwrap().iter() {
+        print_warning(format!("\n{note}"));
+    }
+}
+
 impl Default for Terminal {
     fn default() -> Self {
         Self::new()
@@ -318,3 +518,44 @@ pub fn notify_desktop<P: AsRef<str>>(message: P, timeout: Option<Duration>) {
 pub fn display_time(display_time: bool) {
     TERMINAL.lock().unwrap().display_time(display_time);
 }
+
+/// Use the colors of `theme`, overridden by `colors`, or none at all when `NO_COLOR` is set.
+pub fn set_colors(theme: ColorTheme, colors: Option<&Colors>) {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty());
+    let theme = match theme {
+        ColorTheme::Auto => background_theme().unwrap_or(ColorTheme::Dark),
+        theme => theme,
+    };
+    if no_color || theme == ColorTheme::None {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+        return;
+    }
+
+    let mut palette = match theme {
+        ColorTheme::Light => Palette::LIGHT,
+        _ => Palette::DARK,
+    };
+    if let Some(colors) = colors {
+        if let Some(color) = colors.success {
+            palette.success = console_color(color);
+        }
+        if let Some(color) = colors.failure {
+            palette.failure = console_color(color);
+        }
+        if let Some(color) = colors.skip {
+            palette.skip = console_color(color);
+        }
+        if let Some(color) = colors.separator {
+            palette.separator = Some(console_color(color));
+        }
+    }
+    *PALETTE.lock().unwrap() = palette;
+}
+
+pub fn set_separator_style(separator_char: char, separator_width: u16, separator_full_width: bool) {
+    TERMINAL
+        .lock()
+        .unwrap()
+        .set_separator_style(separator_char, separator_width, separator_full_width);
+}
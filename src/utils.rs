@@ -3,6 +3,7 @@ use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 use color_eyre::eyre::Result;
 
@@ -118,6 +119,50 @@ pub fn string_prepend_str(string: &mut String, s: &str) {
     *string = new_string;
 }
 
+/// Parse a human readable duration such as `90`, `45s`, `30m` or `1h30m`.
+///
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(String::from("empty duration"));
+    }
+
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration `{s}`: expected a number before `{c}`"))?;
+        number.clear();
+        let unit: u64 = match c {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("invalid duration `{s}`: unknown unit `{c}`")),
+        };
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("invalid duration `{s}`: too long"))?;
+    }
+
+    if !number.is_empty() {
+        return Err(format!("invalid duration `{s}`: missing unit after `{number}`"));
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
 #[cfg(target_family = "unix")]
 pub fn hostname() -> Result<String> {
     match nix::unistd::gethostname() {
@@ -137,16 +182,16 @@ pub fn hostname() -> Result<String> {
 }
 
 pub mod merge_strategies {
-    use merge::Merge;
+    use std::collections::BTreeMap;
 
-    use crate::config::Commands;
+    use merge::Merge;
 
     /// Prepends right to left (both Option<Vec<T>>)
     pub fn vec_prepend_opt<T>(left: &mut Option<Vec<T>>, right: Option<Vec<T>>) {
         if let Some(left_vec) = left {
             if let Some(mut right_vec) = right {
                 right_vec.append(left_vec);
-                let _ = std::mem::replace(left, Some(right_vec));
+                *left = Some(right_vec);
             }
         } else {
             *left = right;
@@ -178,7 +223,8 @@ pub mod merge_strategies {
         }
     }
 
-    pub fn commands_merge_opt(left: &mut Option<Commands>, right: Option<Commands>) {
+    /// Extends the left map with the right one, the right entries replacing the left ones
+    pub fn commands_merge_opt<V>(left: &mut Option<BTreeMap<String, V>>, right: Option<BTreeMap<String, V>>) {
         if let Some(ref mut left_inner) = left {
             if let Some(right_inner) = right {
                 left_inner.extend(right_inner);
@@ -192,6 +238,8 @@ pub mod merge_strategies {
 // Skip causes
 // TODO: Put them in a better place when we have more of them
 pub const REQUIRE_SUDO: &str = "Require sudo or counterpart but not found, skip";
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd", windows))]
+pub const NO_METADATA_REFRESH: &str = "Nothing to refresh in metadata-only mode, skip";
 
 /// Return `Err(SkipStep)` if `python` is a Python 2 or shim.
 ///
@@ -228,6 +276,52 @@ pub fn check_is_python_2_or_shim(python: PathBuf) -> Result<PathBuf> {
     Ok(python)
 }
 
+/// The package manager that installed the executable at `exe`, if any.
+///
+/// Package managers are recognized by the places they install to, and on Linux by asking the
+/// system package manager which package owns the file.
+pub fn installing_package_manager(exe: &Path) -> Option<&'static str> {
+    let exe = exe.canonicalize().unwrap_or_else(|_| exe.to_path_buf());
+    let path = exe.to_string_lossy();
+
+    if path.contains("/Cellar/") || path.starts_with("/home/linuxbrew/.linuxbrew/") {
+        return Some("Homebrew");
+    }
+    if path.starts_with("/nix/store/") {
+        return Some("Nix");
+    }
+    if path.contains("/opt/local/bin/") {
+        return Some("MacPorts");
+    }
+    let lowercase = path.to_lowercase();
+    if lowercase.contains("\\scoop\\apps\\") {
+        return Some("Scoop");
+    }
+    if lowercase.contains("\\chocolatey\\") {
+        return Some("Chocolatey");
+    }
+    if lowercase.contains("\\winget\\packages\\") {
+        return Some("Winget");
+    }
+
+    // Asking the owner of the file changes nothing
+    let owners: [(&str, &[&str], &str); 4] = [
+        ("dpkg", &["-S"], "apt"),
+        ("pacman", &["-Qo"], "pacman"),
+        ("rpm", &["-qf"], "the RPM package manager"),
+        ("apk", &["info", "--who-owns"], "apk"),
+    ];
+    owners.into_iter().find_map(|(binary, args, name)| {
+        which(binary)?;
+        Command::new(binary)
+            .args(args)
+            .arg(&exe)
+            .output_checked()
+            .ok()
+            .map(|_| name)
+    })
+}
+
 /// Set up the tracing logger
 ///
 /// # Return value
@@ -270,3 +364,21 @@ pub fn install_color_eyre() -> Result<()> {
         .display_location_section(true)
         .install()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("18446744073709551615h").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Send the step results to the `webhook_url` as they complete.
+//!
+//! The payloads are posted with `curl`, a delivery failure is only logged.
+use std::process::Command;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::command::CommandExt;
+use crate::report::{Report, StepResult};
+
+/// The payload posted once a step is complete.
+#[derive(Serialize)]
+struct StepPayload<'a> {
+    event: &'static str,
+    step: &'a str,
+    status: &'static str,
+    duration_secs: f64,
+    /// Why the step was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure_kind: Option<String>,
+}
+
+/// The status of a step in the run complete payload.
+#[derive(Serialize)]
+struct StepStatus<'a> {
+    step: &'a str,
+    status: &'static str,
+}
+
+/// The payload posted once the run is complete.
+#[derive(Serialize)]
+struct RunCompletePayload<'a> {
+    event: &'static str,
+    success: usize,
+    failure: usize,
+    ignored: usize,
+    skipped: usize,
+    duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<Vec<StepStatus<'a>>>,
+}
+
+fn status(result: &StepResult) -> &'static str {
+    match result {
+        StepResult::Success => "success",
+        StepResult::Failure(_) => "failure",
+        StepResult::Ignored => "ignored",
+        StepResult::Skipped(_) => "skipped",
+    }
+}
+
+/// The duration in seconds, to the millisecond.
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_millis() as f64 / 1000.0
+}
+
+fn to_json(payload: &impl Serialize) -> String {
+    serde_json::to_string(payload).expect("the payloads only hold strings and numbers")
+}
+
+fn post(url: &str, payload: &str) {
+    debug!("Posting {payload} to the webhook");
+    let result = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+        .args(["--header", "Content-Type: application/json", "--data-binary", payload])
+        .arg(url)
+        .output_checked();
+    if let Err(e) = result {
+        warn!("Failed to deliver the webhook: {e}");
+    }
+}
+
+/// The payload of the result of a step.
+fn step_payload(key: &str, result: &StepResult, duration: Duration) -> String {
+    to_json(&StepPayload {
+        event: "step",
+        step: key,
+        status: status(result),
+        duration_secs: duration_secs(duration),
+        message: match result {
+            StepResult::Skipped(reason) => Some(reason),
+            _ => None,
+        },
+        failure_kind: match result {
+            StepResult::Failure(kind) => Some(kind.to_string()),
+            _ => None,
+        },
+    })
+}
+
+/// Post the result of a step.
+pub fn send_step_result(url: &str, key: &str, result: &StepResult, duration: Duration) {
+    post(url, &step_payload(key, result, duration));
+}
+
+/// The payload of the complete run, with the status of each step if `steps` is set.
+fn run_complete_payload(report: &Report, duration: Duration, steps: bool) -> String {
+    let count = |wanted: &str| {
+        report
+            .data()
+            .iter()
+            .filter(|(_, result)| status(result) == wanted)
+            .count()
+    };
+    to_json(&RunCompletePayload {
+        event: "run_complete",
+        success: count("success"),
+        failure: count("failure"),
+        ignored: count("ignored"),
+        skipped: count("skipped"),
+        duration_secs: duration_secs(duration),
+        steps: steps.then(|| {
+            report
+                .data()
+                .iter()
+                .map(|(key, result)| StepStatus {
+                    step: key,
+                    status: status(result),
+                })
+                .collect()
+        }),
+    })
+}
+
+/// Post the aggregate counts once the run is complete.
+pub fn send_run_complete(url: &str, report: &Report, duration: Duration) {
+    post(url, &run_complete_payload(report, duration, false));
+}
+
+/// The run complete payload with the status of each step, as emailed with the JSON format.
+pub fn report_json(report: &Report, duration: Duration) -> String {
+    run_complete_payload(report, duration, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_payload() {
+        assert_eq!(
+            step_payload("cargo", &StepResult::Success, Duration::from_millis(1500)),
+            r#"{"event":"step","step":"cargo","status":"success","duration_secs":1.5}"#
+        );
+        assert_eq!(
+            step_payload(
+                "a \"b\"",
+                &StepResult::Skipped(String::from("not\tdue\u{1b}")),
+                Duration::ZERO
+            ),
+            r#"{"event":"step","step":"a \"b\"","status":"skipped","duration_secs":0.0,"message":"not\tdue\u001b"}"#
+        );
+    }
+
+    #[test]
+    fn test_run_complete_payload() {
+        let mut report = Report::new();
+        report.push_result(Some(("cargo", StepResult::Success)));
+        report.push_result(Some(("pip3", StepResult::Ignored)));
+        assert_eq!(
+            report_json(&report, Duration::from_secs(2)),
+            r#"{"event":"run_complete","success":1,"failure":0,"ignored":1,"skipped":0,"duration_secs":2.0,"steps":[{"step":"cargo","status":"success"},{"step":"pip3","status":"ignored"}]}"#
+        );
+    }
+}